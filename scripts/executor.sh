@@ -21,6 +21,10 @@ function execute() {
         build_arg=" --release "
     fi
 
+    if [[ "$FEATURES" != "" ]]; then
+        build_arg="$build_arg --features $FEATURES "
+    fi
+
     if [[ "$NETWORK" != "" ]]; then
         CONFIG="-c config/$APP-$NETWORK.json"
     fi
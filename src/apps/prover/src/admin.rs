@@ -0,0 +1,111 @@
+use std::prelude::v1::*;
+
+use base::format::debug;
+use crypto::Secp256k1PrivateKey;
+use eth_tools::RpcClient;
+use eth_types::{EngineTypes, SH160, SH256};
+use linea::Verifier;
+
+pub const ADMIN_USAGE: &str = "usage: admin <command>
+    info                                show the verifier settings
+    change-owner <address>              transfer the verifier ownership
+    change-attest-validity <seconds>    change the attestation validity
+    set-mrenclave <hash> [true|false]   trust or revoke a mrenclave
+    set-mrsigner <hash> [true|false]    trust or revoke a mrsigner";
+
+#[derive(Debug, Clone)]
+pub enum AdminCommand {
+    Info,
+    ChangeOwner(SH160),
+    ChangeAttestValiditySeconds(u64),
+    SetMrEnclave(SH256, bool),
+    SetMrSigner(SH256, bool),
+}
+
+fn check_hex(val: &str, len: usize) -> Result<(), String> {
+    let hex = val.trim_start_matches("0x");
+    if !val.starts_with("0x") || hex.len() != len * 2 {
+        return Err(format!("expect {} bytes hex, got: {}", len, val));
+    }
+    if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(format!("invalid hex: {}", val));
+    }
+    Ok(())
+}
+
+fn parse_address(val: &str) -> Result<SH160, String> {
+    check_hex(val, 20)?;
+    Ok(val.into())
+}
+
+fn parse_hash(val: &str) -> Result<SH256, String> {
+    check_hex(val, 32)?;
+    Ok(val.into())
+}
+
+fn parse_trusted(val: Option<&String>) -> Result<bool, String> {
+    match val.map(|n| n.as_str()) {
+        None | Some("true") => Ok(true),
+        Some("false") => Ok(false),
+        Some(other) => Err(format!("expect true or false, got: {}", other)),
+    }
+}
+
+impl AdminCommand {
+    pub fn parse(args: &[String]) -> Result<Self, String> {
+        let arg = |idx: usize| {
+            args.get(idx)
+                .map(|n| n.as_str())
+                .ok_or_else(|| ADMIN_USAGE.to_owned())
+        };
+        Ok(match arg(0)? {
+            "info" => Self::Info,
+            "change-owner" => Self::ChangeOwner(parse_address(arg(1)?)?),
+            "change-attest-validity" => {
+                Self::ChangeAttestValiditySeconds(arg(1)?.parse().map_err(debug)?)
+            }
+            "set-mrenclave" => Self::SetMrEnclave(parse_hash(arg(1)?)?, parse_trusted(args.get(2))?),
+            "set-mrsigner" => Self::SetMrSigner(parse_hash(arg(1)?)?, parse_trusted(args.get(2))?),
+            other => return Err(format!("unknown command: {}\n{}", other, ADMIN_USAGE)),
+        })
+    }
+
+    pub fn run<C, E>(
+        &self,
+        verifier: &Verifier<C, E>,
+        signer: &Secp256k1PrivateKey,
+    ) -> Result<(), String>
+    where
+        C: RpcClient + Clone + Send + 'static,
+        E: EngineTypes,
+    {
+        let tx_hash = match self {
+            Self::Info => {
+                glog::info!("owner: {:?}", verifier.owner().map_err(debug)?);
+                glog::info!("threshold: {}", verifier.threshold().map_err(debug)?);
+                glog::info!(
+                    "attest validity seconds: {}",
+                    verifier.attest_validity_seconds().map_err(debug)?
+                );
+                glog::info!(
+                    "dcap attestation: {:?}",
+                    verifier.dcap_attestation().map_err(debug)?
+                );
+                glog::info!("layer2 chain id: {}", verifier.l2_chain_id().map_err(debug)?);
+                return Ok(());
+            }
+            Self::ChangeOwner(owner) => verifier.change_owner(signer, owner)?,
+            Self::ChangeAttestValiditySeconds(secs) => {
+                verifier.change_attest_validity_seconds(signer, *secs)?
+            }
+            Self::SetMrEnclave(mrenclave, trusted) => {
+                verifier.set_mrenclave(signer, mrenclave, *trusted)?
+            }
+            Self::SetMrSigner(mrsigner, trusted) => {
+                verifier.set_mrsigner(signer, mrsigner, *trusted)?
+            }
+        };
+        glog::info!("{:?} done, tx: {:?}", self, tx_hash);
+        Ok(())
+    }
+}
@@ -10,21 +10,30 @@ use base::{
 };
 use crypto::{keccak_encode, Secp256r1PrivateKey, Secp256r1Signature};
 use eth_tools::ExecutionClient;
-use eth_types::{Block, EthereumEngineTypes, HexBytes, SH160, SH256, SU64};
+use eth_types::{Block, EthereumEngineTypes, HexBytes, Receipt, SH160, SH256, SU64};
 use evm_executor::{BlockBuilder, Poe};
 use jsonrpc::{JsonrpcErrorObj, MixRpcClient, RpcArgs, RpcServer, RpcServerConfig};
+#[cfg(not(feature = "tstd"))]
+use linea::FileKeyStore;
+#[cfg(feature = "tstd")]
+use linea::SealedKeyStore;
 use linea::{
-    account_key, BatchTask, BatchTaskSubscriber, BatchTaskSubscriberConfig, BlockExecutor, Prover,
-    Verifier, ZkStateAccount, ZkTrieState,
+    account_key, BatchTask, BatchTaskEvent, BatchTaskSubscriber, BatchTaskSubscriberConfig,
+    BlockExecutor, FileCheckpointStore, HeaderChain, HeaderChainStore, KeyStore, L1MessageAnchor,
+    MessageTreeError, Prover, Rollup, Verifier, VerifierError, VerifierErrorAction, ZkTrieState,
 };
 use mpt::Database;
-use shomei::RollupgetZkEVMStateMerkleProofV0Resp;
 use std::collections::BTreeMap;
 use std::sync::Arc;
 use std::sync::Mutex;
 use zktrie::{PrefixDB, Trace};
 
-use crate::{Args, Config};
+use crate::{
+    token_eq, AdminCommand, Args, AuthConfig, CliCommand, Config, Control, ControlState, EventBus,
+    EventKind, InFlightJob, Job, JobQueue, JobState, L1Progress, LastBatchTracker, Metrics,
+    ProveJobInfo, ProveJobStatus, ProveJobs, ProverEvent, ProverStatus, RateLimiter,
+    TrackedCheckpointStore,
+};
 
 #[derive(Default)]
 pub struct App {
@@ -39,12 +48,32 @@ pub struct App {
     pub cfg: Var<Config>,
     pub build_context: Var<BuildContext>,
     pub shomei: Var<shomei::Client>,
+    pub jobs: Var<JobQueue>,
+    pub prove_jobs: Var<ProveJobs>,
+    pub events: Var<EventBus>,
+    pub l1_progress: Var<L1Progress>,
+    pub metrics: Var<Metrics>,
+    pub control: Var<Control>,
 }
 
 impl app::App for App {
     fn run(&self, env: app::AppEnv) -> Result<(), String> {
         self.args.set(Args::from_args(env.args));
+        let args = self.args.get();
+        if let Some(cmd) = CliCommand::parse(&args.command) {
+            return cmd?.run(self);
+        }
+        self.precheck()?;
         let cfg = self.cfg.get(self);
+        match args.command.first().map(|n| n.as_str()) {
+            Some("admin") => {
+                let cmd = AdminCommand::parse(&args.command[1..])?;
+                let verifier = self.verifier.get(self);
+                return cmd.run(&verifier, &cfg.verifier.relay_account);
+            }
+            Some(other) => return Err(format!("unknown subcommand: {}", other)),
+            None => {}
+        }
         let rollup_el = self.rollup_el.get(self);
 
         let verifier = self.verifier.get(self);
@@ -52,17 +81,90 @@ impl app::App for App {
         let l2 = self.l2.get(self);
         let build_ctx = self.build_context.get(self);
 
-        // thread::spawn("prover-attested-monitor".into(), {
-        //     let prover = prover.clone();
-        //     let relay_account = cfg.verifier.relay_account.clone();
-        //     let verifier = verifier.clone();
-        //     move || {
-        //         prover.monitor_attested(&relay_account, &verifier, |prvkey| {
-        //             let prover_key = prvkey.public().eth_accountid().into();
-        //             verifier.generate_prover_report(&prover_key, false)
-        //         });
-        //     }
-        // });
+        prover.on_attested({
+            let events = self.events.get(self);
+            let metrics = self.metrics.get(self);
+            move |prover| {
+                metrics.attestation_renewals.inc(&[]);
+                events.publish(ProverEvent::AttestationRenewed { prover: *prover })
+            }
+        });
+
+        if let Some(port) = cfg.server.metrics_port {
+            thread::spawn("metrics".into(), {
+                let metrics = self.metrics.get(self);
+                let alive = self.alive.clone();
+                move || {
+                    if let Err(err) = metrics.serve(&alive, port) {
+                        glog::error!("metrics server exited: {}", err);
+                    }
+                }
+            });
+        }
+
+        // the report is generated by the enclave, or faked by the
+        // `sim-attestation` feature in std mode.
+        thread::spawn("prover-attested-monitor".into(), {
+            let prover = prover.clone();
+            let relay_account = cfg.verifier.relay_account.clone();
+            let verifier = verifier.clone();
+            move || {
+                prover.monitor_attested(&relay_account, &verifier, |prvkey| {
+                    let prover_key = prvkey.public().eth_accountid().into();
+                    verifier.generate_prover_report(&prover_key, false)
+                });
+            }
+        });
+
+        let prove_jobs = self.prove_jobs.get(self);
+        for i in 0..cfg.server.prove_workers {
+            thread::spawn(format!("prove-worker-{}", i), {
+                let prove_jobs = prove_jobs.clone();
+                let build_ctx = build_ctx.clone();
+                move || build_ctx.run_prove_worker(&prove_jobs)
+            });
+        }
+
+        let jobs = self.jobs.get(self);
+        thread::spawn("batch-jobs".into(), {
+            let jobs = jobs.clone();
+            let build_ctx = build_ctx.clone();
+            move || build_ctx.run_jobs(&jobs)
+        });
+
+        thread::spawn("batch-task-subscriber".into(), {
+            let cfg = BatchTaskSubscriberConfig {
+                tag: format!("batch-task"),
+                contract: cfg.rollup.contract,
+                max_block: cfg.rollup.max_block,
+                wait_block: cfg.rollup.wait_block,
+                reorg_window: cfg.rollup.reorg_window,
+            };
+            let store = TrackedCheckpointStore::new(
+                FileCheckpointStore::new(cfg.storage.checkpoint.clone()),
+                self.l1_progress.get(self),
+            );
+            let subscriber = BatchTaskSubscriber::new(
+                self.alive.clone(),
+                cfg,
+                rollup_el.0.clone(),
+                Box::new(store),
+            );
+            let alive = self.alive.clone();
+            move || {
+                let result = subscriber.subscribe(move |event| match event {
+                    BatchTaskEvent::New(task) => {
+                        jobs.discover(task);
+                    }
+                    BatchTaskEvent::Reorged(task) => jobs.reorg(&task),
+                });
+                if let Err(err) = result {
+                    // the batches would be missed without the subscriber
+                    glog::error!("batch task subscriber exited: {}", err);
+                    alive.shutdown();
+                }
+            }
+        });
 
         thread::spawn("jsonrpc".into(), {
             let srv = self.server.get(self);
@@ -75,37 +177,12 @@ impl app::App for App {
         .join()
         .unwrap();
 
-        // let subscriber = {
-        //     let cfg = BatchTaskSubscriberConfig {
-        //         tag: format!("batch-task"),
-        //         contract: cfg.rollup.contract,
-        //         max_block: cfg.rollup.max_block,
-        //         wait_block: cfg.rollup.wait_block,
-        //     };
-        //     BatchTaskSubscriber::new(self.alive.clone(), cfg, rollup_el.0.clone())
-        // };
-        // subscriber
-        //     .subscribe({
-        //         let alive = self.alive.clone();
-        //         let prover = prover.clone();
-        //         move |task| {
-        //             if !prover.wait_attested(&alive) {
-        //                 glog::error!("cancel task: {:?}", task);
-        //                 return;
-        //             }
-        //             if let Err(err) = build_ctx.execute_task(task.clone()) {
-        //                 glog::error!("prove task[{:?}] failed: {:?}", task.blocks, err);
-        //                 return;
-        //             };
-        //         }
-        //     })
-        //     .unwrap();
-
         Ok(())
     }
 
     fn terminate(&self) {
-        self.alive.shutdown()
+        self.alive.shutdown();
+        self.prove_jobs.get(self).cancel_all();
     }
 }
 
@@ -117,10 +194,51 @@ pub struct BuildContext {
     pub verifier: Arc<Verifier<Arc<MixRpcClient>, EthereumEngineTypes>>,
     pub l2: Arc<ExecutionClient<Arc<MixRpcClient>>>,
     pub shomei: Arc<shomei::Client>,
+    pub header_chain: Option<Arc<HeaderChain<Arc<MixRpcClient>>>>,
+    pub rollup: Arc<Rollup<Arc<MixRpcClient>>>,
+    pub events: Arc<EventBus>,
+    pub metrics: Arc<Metrics>,
+    pub control: Arc<Control>,
+}
+
+struct ExecutedBlock {
+    block: Block,
+    poe: Poe,
+    messages: Vec<SH256>,
+    anchors: Vec<L1MessageAnchor>,
+}
+
+/// The failures of generating a batch Poe, only the message tree overflow
+/// fails the job for good.
+#[derive(Debug)]
+pub enum ProveError {
+    MessageTree(MessageTreeError),
+    Other(String),
+}
+
+impl From<String> for ProveError {
+    fn from(err: String) -> Self {
+        Self::Other(err)
+    }
+}
+
+impl From<MessageTreeError> for ProveError {
+    fn from(err: MessageTreeError) -> Self {
+        Self::MessageTree(err)
+    }
+}
+
+impl From<ProveError> for String {
+    fn from(err: ProveError) -> String {
+        match err {
+            ProveError::MessageTree(err) => err.into(),
+            ProveError::Other(err) => err,
+        }
+    }
 }
 
 impl BuildContext {
-    fn submit_poe(&self, task: BatchTask, poe: Poe) -> Result<(), String> {
+    fn submit_poe(&self, task: &BatchTask, poe: &Poe) -> Result<(), VerifierError> {
         let prover = &self.prover;
         let verifier = &self.verifier;
         let cfg = &self.cfg;
@@ -128,35 +246,163 @@ impl BuildContext {
         let batch_id = (*task.blocks.last().unwrap()).into();
         let report = poe.encode();
 
-        verifier.commit_batch(&cfg.verifier.relay_account, &batch_id, &report)?;
+        let mut retry = 0;
+        loop {
+            let sent_at = base::time::now();
+            let err = match verifier.commit_batch(&cfg.verifier.relay_account, &batch_id, &report) {
+                Ok(tx_hash) => {
+                    self.metrics
+                        .commit_batch_confirm_seconds
+                        .observe_duration(&[], base::time::now() - sent_at);
+                    match verifier.get_receipt(&tx_hash) {
+                        Ok(Some(receipt)) => self
+                            .metrics
+                            .commit_batch_gas_used
+                            .observe(&[], receipt.gas_used.as_u64() as f64),
+                        Ok(None) => glog::warn!("receipt of {:?} not found", tx_hash),
+                        Err(err) => glog::warn!("fetch receipt of {:?} fail: {:?}", tx_hash, err),
+                    }
+                    break;
+                }
+                Err(err) => err,
+            };
+            match err.action() {
+                VerifierErrorAction::Skip => {
+                    glog::info!("batch{:?} skipped: {}", task.blocks, err);
+                    break;
+                }
+                VerifierErrorAction::Retry
+                    if retry < cfg.retry.submit_retries
+                        && self.alive.sleep_ms(cfg.retry.submit_retry_interval_ms) =>
+                {
+                    retry += 1;
+                    glog::warn!("batch{:?} retry[{}]: {}", task.blocks, retry, err);
+                }
+                VerifierErrorAction::Reattest => {
+                    // the Poe is signed by a key which is no longer valid,
+                    // the task should be proved again after the re-attestation.
+                    prover.mark_unattested();
+                    return Err(err);
+                }
+                _ => return Err(err),
+            }
+        }
+        self.events.publish(ProverEvent::BatchCommitted {
+            start: *task.blocks.first().unwrap(),
+            end: *task.blocks.last().unwrap(),
+            batch_hash: poe.batch_hash,
+        });
+        Ok(())
+    }
+
+    /// The batch which the task is built on, it's carried by the submission:
+    /// the parent shnarf of the blobs or the data parent hash of the calldata.
+    fn parent_batch(&self, task: &BatchTask) -> Option<SH256> {
+        match (&task.blob, &task.calldata) {
+            (Some(blob), _) => Some(blob.parent_shnarf),
+            (None, Some(calldata)) => Some(calldata.data_parent_hash),
+            (None, None) => None,
+        }
+    }
+
+    /// The roots of the submission are the zktrie roots, they can only be
+    /// compared with the Poe generated on the zktrie. The MPT Poe isn't bound
+    /// to the submitted data, the data hash can't be rebuilt from the blocks
+    /// without Linea's compressor.
+    fn check_submission(&self, task: &BatchTask, poe: &Poe) -> Result<(), String> {
+        // the blob submission only carries the final root, its parent is
+        // checked by `check_parent_batch`.
+        let (parent_state_root, final_state_root) = match (&task.calldata, &task.blob) {
+            (Some(calldata), _) => (
+                Some(calldata.parent_state_root),
+                calldata.final_state_root,
+            ),
+            (None, Some(blob)) => (None, blob.final_state_root),
+            (None, None) => return Ok(()),
+        };
+        if !self.cfg.verifier.zktrie {
+            glog::warn!(
+                "batch{:?} the MPT roots can't be checked against the submission",
+                task.blocks
+            );
+            return Ok(());
+        }
+        if parent_state_root.map_or(false, |root| root != poe.prev_state_root) {
+            return Err(format!(
+                "batch{:?} parent state root mismatch, submitted: {:?}, local: {:?}",
+                task.blocks, parent_state_root, poe.prev_state_root
+            ));
+        }
+        if final_state_root != poe.new_state_root {
+            return Err(format!(
+                "batch{:?} final state root mismatch, submitted: {:?}, local: {:?}",
+                task.blocks, final_state_root, poe.new_state_root
+            ));
+        }
+        Ok(())
+    }
+
+    /// Makes sure the Poe extends the state committed on the verifier, the
+    /// `commitBatch` will revert or fork the state otherwise.
+    fn check_parent_batch(&self, task: &BatchTask, poe: &Poe) -> Result<(), String> {
+        let parent = match self.parent_batch(task) {
+            Some(parent) => parent,
+            None => {
+                return Err(format!(
+                    "batch{:?} is not an L1 submission, its parent is unknown",
+                    task.blocks
+                ))
+            }
+        };
+        if parent == SH256::default() || self.cfg.verifier.genesis_batch == Some(task.data_hash) {
+            // the first batch proved on this verifier
+            glog::info!(
+                "batch{:?} is the genesis batch, prev_state_root: {:?}",
+                task.blocks,
+                poe.prev_state_root
+            );
+            return Ok(());
+        }
+        let info = match self.verifier.batch_info(&parent)? {
+            Some(info) => info,
+            None => {
+                return Err(format!(
+                    "batch gap: parent batch {:?} is not committed, prev_state_root: {:?}",
+                    parent, poe.prev_state_root
+                ))
+            }
+        };
+        if info.new_state_root != poe.prev_state_root {
+            return Err(format!(
+                "state fork: parent batch {:?}[{}] new_state_root: {:?}, prev_state_root: {:?}",
+                parent, info.batch_index, info.new_state_root, poe.prev_state_root
+            ));
+        }
         Ok(())
     }
 
-    fn generate_poe_v2(
+    fn execute_zk_block(
         &self,
         chain_id: u64,
-        result: RollupgetZkEVMStateMerkleProofV0Resp,
+        block_trace: &[Trace],
         block: Block,
-    ) -> Result<(), String> {
-        glog::info!("start root_hash: {:?}", result.zk_parent_state_root_hash);
-        glog::info!("end root_hash: {:?}", result.zk_end_state_root_hash);
-        let block_trace = &result.zk_state_merkle_proof[0];
+    ) -> Result<(Vec<Receipt>, SH256), String> {
         let current_block = (block.header.number.as_u64() - 1).into();
+        let start = base::time::now();
         let proofs = self
             .shomei
             .fetch_proof_by_traces(&block_trace, current_block)
             .map_err(debug)?;
-
-        for item in block_trace {
-            glog::info!("traces: {:?}", item);
-        }
+        self.metrics.shomei_request_seconds.observe_duration(
+            &[("method", "fetch_proof_by_traces")],
+            base::time::now() - start,
+        );
 
         let mut codes = Vec::new();
         for t in block_trace {
             if t.location().len() == 0 {
                 let value = t.read_value();
                 if value.len() > 0 {
-                    let acc = ZkStateAccount::from_bytes(value);
                     let mut addr = SH160::default();
                     addr.0.copy_from_slice(t.key());
                     codes.push(addr);
@@ -168,26 +414,30 @@ impl BuildContext {
         let mut db = zktrie::MemStore::from_traces(&block_trace).map_err(debug)?;
         db.add_codes(codes);
 
+        // the proofs are against the parent state, so they must lead to the
+        // root the traces start from.
+        let parent_root = block_trace[0].old_top_hash();
         for proof in proofs {
             let hkey = account_key(&proof.account_proof.key);
-            let root_hash = if let Some((leaf_index, proof)) = proof.account_proof.inclusion() {
-                db.add_proof(
+            let (leaf_index, inclusion) = match proof.account_proof.inclusion() {
+                Some(inclusion) => inclusion,
+                None => continue,
+            };
+            let root_hash = db
+                .add_proof(
                     u64::max_value(),
                     leaf_index,
                     hkey,
-                    proof.value.as_ref().map(|n| n.as_bytes()),
-                    &proof.proof_related_nodes,
+                    inclusion.value.as_ref().map(|n| n.as_bytes()),
+                    &inclusion.proof_related_nodes,
                 )
-                .map_err(debug)?
-            } else {
-                glog::info!("proof: {:?}", proof.account_proof);
-                continue;
-            };
-            glog::info!(
-                "[{:?}] state root_hash: {:?}",
-                proof.account_proof.key,
-                root_hash
-            );
+                .map_err(debug)?;
+            if root_hash != parent_root {
+                return Err(format!(
+                    "[{:?}] proof root mismatch, expect: {:?}, got: {:?}",
+                    proof.account_proof.key, parent_root, root_hash
+                ));
+            }
         }
 
         let be = BlockExecutor::new(chain_id.into());
@@ -195,11 +445,124 @@ impl BuildContext {
             PrefixDB::new(u64::max_value(), db.into()),
             &block_trace,
             block,
-        )?;
+        )
+    }
+
+    /// Generates the Poe whose state roots are the zktrie top root hashes,
+    /// the blocks are executed on the state rebuilt from the shomei traces.
+    fn generate_zk_poe(&self, chain_id: u64, task: BatchTask) -> Result<Poe, ProveError> {
+        glog::info!("zk task: {:?}", task);
+        let start = *task.blocks.first().unwrap();
+        let end = *task.blocks.last().unwrap();
+        let trusted_hashes = match &self.header_chain {
+            Some(header_chain) => Some(header_chain.verify_task(&task)?),
+            None => None,
+        };
+        let fetch_start = base::time::now();
+        let result = self.shomei.fetch_proof(start, end).map_err(debug)?;
+        self.metrics.shomei_request_seconds.observe_duration(
+            &[("method", "fetch_proof")],
+            base::time::now() - fetch_start,
+        );
+        if result.zk_state_merkle_proof.len() != task.blocks.len() {
+            return Err(format!(
+                "traces count mismatch, blocks: {}, traces: {}",
+                task.blocks.len(),
+                result.zk_state_merkle_proof.len()
+            )
+            .into());
+        }
+
+        let mut poes = Vec::with_capacity(task.blocks.len());
+        let mut blocks = Vec::with_capacity(task.blocks.len());
+        let mut messages = Vec::new();
+        let mut anchors = Vec::new();
+        let mut prev_state_root = result.zk_parent_state_root_hash;
+        for (blk, block_trace) in task.blocks.iter().zip(&result.zk_state_merkle_proof) {
+            let block = self.l2.get_block((*blk).into()).map_err(debug)?;
+            let block_hash = block.header.hash();
+            if let Some(trusted_hashes) = &trusted_hashes {
+                if trusted_hashes.get(blk) != Some(&block_hash) {
+                    return Err(format!(
+                        "block[{}] hash {:?} is not in the verified header chain",
+                        blk, block_hash
+                    )
+                    .into());
+                }
+            }
+            let (receipts, new_state_root) = if block_trace.len() == 0 {
+                // no state access, but the block may still pay the fee to
+                // the coinbase, so only the empty block is allowed.
+                if block.transactions.len() > 0 {
+                    return Err(format!("block[{}] has txs but no traces", blk).into());
+                }
+                (Vec::new(), prev_state_root)
+            } else {
+                let trace_root = block_trace[0].old_top_hash();
+                if trace_root != prev_state_root {
+                    return Err(format!(
+                        "block[{}] zktrie root not continuous, expect: {:?}, got: {:?}",
+                        blk, prev_state_root, trace_root
+                    )
+                    .into());
+                }
+                let exec_start = base::time::now();
+                let result = self.execute_zk_block(chain_id, block_trace, block.clone())?;
+                self.metrics
+                    .block_execution_seconds
+                    .observe_duration(&[], base::time::now() - exec_start);
+                result
+            };
+            self.metrics
+                .block_txs
+                .observe(&[], block.transactions.len() as f64);
+            if let Some(cfg) = &self.cfg.message_service {
+                messages.extend(linea::collect_l2_messages(&cfg.l2_contract, &receipts));
+                anchors.extend(linea::collect_l1_anchors(&cfg.l2_contract, &receipts)?);
+            }
+            // the block hash binds the executed transactions and the
+            // traces are bound by the roots.
+            poes.push(Poe::single_block(
+                block_hash,
+                prev_state_root,
+                new_state_root,
+                SH256::default(),
+            ));
+            blocks.push(block);
+            prev_state_root = new_state_root;
+        }
+        if prev_state_root != result.zk_end_state_root_hash {
+            return Err(format!(
+                "zktrie end root mismatch, shomei: {:?}, local: {:?}",
+                result.zk_end_state_root_hash, prev_state_root
+            )
+            .into());
+        }
+        if anchors.len() > 0 {
+            self.verify_l1_messages(&anchors)?;
+        }
+        self.seal_poe(chain_id, &task, &blocks, &poes, &messages)
+    }
+
+    fn verify_l1_messages(&self, anchors: &[L1MessageAnchor]) -> Result<(), String> {
+        let prev_number = anchors[0].first_message_number() - 1;
+        let prev_rolling_hash = if prev_number == 0 {
+            SH256::default()
+        } else {
+            self.rollup.rolling_hash(prev_number).map_err(debug)?
+        };
+        let (number, rolling_hash) = linea::verify_l1_anchors(&prev_rolling_hash, anchors)?;
+        let l1_rolling_hash = self.rollup.rolling_hash(number).map_err(debug)?;
+        if l1_rolling_hash != rolling_hash {
+            return Err(format!(
+                "message[{}] rolling hash mismatch, l1: {:?}, l2: {:?}",
+                number, l1_rolling_hash, rolling_hash
+            ));
+        }
         Ok(())
     }
 
-    fn generate_poe(&self, chain_id: u64, task: BatchTask) -> Result<Poe, String> {
+    fn generate_poe(&self, chain_id: u64, task: BatchTask) -> Result<Poe, ProveError> {
         let l2 = &self.l2;
         let be = BlockExecutor::new(chain_id.into());
         let alive = self.alive.clone();
@@ -207,26 +570,64 @@ impl BuildContext {
         let batch_result = Arc::new(Mutex::new(BTreeMap::new()));
 
         glog::info!("task: {:?}", task);
-        parallel(&alive, task.blocks.clone(), 8, {
+        let trusted_hashes = match &self.header_chain {
+            Some(header_chain) => Some(Arc::new(header_chain.verify_task(&task)?)),
+            None => None,
+        };
+        parallel(&alive, task.blocks.clone(), self.control.parallelism(), {
             let batch_result = batch_result.clone();
             let be = be.clone();
             let l2 = l2.clone();
+            let message_service = self.cfg.message_service.clone();
+            let metrics = self.metrics.clone();
             move |blk| {
-                let handle = || -> Result<(Block, Poe), String> {
+                let handle = || -> Result<ExecutedBlock, String> {
+                    let fetch_start = base::time::now();
                     let mut pob = be.generate_pob(l2.as_ref(), blk.into())?;
+                    metrics
+                        .pob_fetch_seconds
+                        .observe_duration(&[], base::time::now() - fetch_start);
+                    if let Some(trusted_hashes) = &trusted_hashes {
+                        let hash = pob.block.header.hash();
+                        if trusted_hashes.get(&blk) != Some(&hash) {
+                            return Err(format!(
+                                "block[{}] hash {:?} is not in the verified header chain",
+                                blk, hash
+                            ));
+                        }
+                    }
                     let state_hash = pob.state_hash();
                     let prev_state_root = pob.data.prev_state_root;
                     let withdrawal_root = SH256::default();
                     let expect_state_root = pob.block.header.state_root;
                     let db = Database::new();
-                    let block = be.execute(&db, pob)?;
+                    let exec_start = base::time::now();
+                    let (block, receipts) = be.execute(&db, pob)?;
+                    metrics
+                        .block_execution_seconds
+                        .observe_duration(&[], base::time::now() - exec_start);
+                    metrics
+                        .block_txs
+                        .observe(&[], block.transactions.len() as f64);
+                    let (messages, anchors) = match &message_service {
+                        Some(cfg) => (
+                            linea::collect_l2_messages(&cfg.l2_contract, &receipts),
+                            linea::collect_l1_anchors(&cfg.l2_contract, &receipts)?,
+                        ),
+                        None => (Vec::new(), Vec::new()),
+                    };
                     let poe = Poe::single_block(
                         state_hash,
                         prev_state_root,
                         expect_state_root,
                         withdrawal_root,
                     );
-                    Ok((block, poe))
+                    Ok(ExecutedBlock {
+                        block,
+                        poe,
+                        messages,
+                        anchors,
+                    })
                 };
                 let result = handle();
                 let mut batch_result = batch_result.lock().unwrap();
@@ -249,94 +650,545 @@ impl BuildContext {
                 "some task failed, tasks: {}, finished: {}",
                 task.blocks.len(),
                 batch_result.len()
-            ));
+            )
+            .into());
         }
         let mut poes = Vec::with_capacity(batch_result.len());
+        let mut blocks = Vec::with_capacity(batch_result.len());
+        let mut messages = Vec::new();
+        let mut anchors = Vec::new();
         for (_, result) in batch_result {
-            let (block, poe) = result?;
+            let ExecutedBlock {
+                block,
+                poe,
+                messages: block_messages,
+                anchors: block_anchors,
+            } = result?;
             if poe.new_state_root != block.header.state_root {
                 return Err(format!(
                     "block[{}] state root mismatch, remote: {:?}, local: {:?}",
                     block.header.number, poe.new_state_root, block.header.state_root
-                ));
+                )
+                .into());
             }
             poes.push(poe);
+            blocks.push(block);
+            messages.extend(block_messages);
+            anchors.extend(block_anchors);
         }
+        if anchors.len() > 0 {
+            self.verify_l1_messages(&anchors)?;
+        }
+        self.seal_poe(chain_id, &task, &blocks, &poes, &messages)
+    }
 
-        // FIXME: data_hash should be calculated by the data instead of fetching from the chain
-        let mut poe = Poe::batch(task.data_hash, &poes)?;
+    /// Aggregates the block Poes into the batch Poe and signs it with the
+    /// prover key.
+    fn seal_poe(
+        &self,
+        chain_id: u64,
+        task: &BatchTask,
+        blocks: &[Block],
+        poes: &[Poe],
+        messages: &[SH256],
+    ) -> Result<Poe, ProveError> {
+        let batch_hash = if task.is_l1() {
+            // the submitted data is checked against the L1 tx by the
+            // subscriber, the blocks are bound to it by the state roots.
+            task.data_hash
+        } else {
+            glog::info!(
+                "batch{:?} is not submitted on L1, identified by the block hashes",
+                task.blocks
+            );
+            linea::range_batch_hash(blocks)
+        };
+        let mut poe = Poe::batch(batch_hash, poes)?;
+        poe.withdrawal_root = match &self.cfg.message_service {
+            Some(cfg) => linea::withdrawal_root(messages, cfg.tree_depth)?,
+            None => SH256::default(),
+        };
+        if task.is_l1() {
+            self.check_submission(task, &poe)?;
+            self.check_parent_batch(task, &poe)?;
+        }
         let prover_key = self.prover.get_prvkey();
+        let sign_start = base::time::now();
         poe.sign(&chain_id.into(), &prover_key);
+        self.metrics
+            .poe_sign_seconds
+            .observe_duration(&[], base::time::now() - sign_start);
         Ok(poe)
     }
 
-    fn execute_task(&self, task: BatchTask) -> Result<(), String> {
-        let chain_id = self.l2.chain_id().map_err(debug)?;
-        let poe = self.generate_poe(chain_id, task.clone())?;
-        self.submit_poe(task, poe)?;
-        Ok(())
+    fn generate_task_poe(&self, chain_id: u64, task: BatchTask) -> Result<Poe, ProveError> {
+        let start = *task.blocks.first().unwrap();
+        let end = *task.blocks.last().unwrap();
+        let poe = if self.cfg.verifier.zktrie {
+            self.generate_zk_poe(chain_id, task)?
+        } else {
+            self.generate_poe(chain_id, task)?
+        };
+        self.events.publish(ProverEvent::PoeProduced {
+            start,
+            end,
+            poe: poe.clone(),
+        });
+        Ok(poe)
+    }
+
+    /// Executes the `proveSubmit` jobs, the job's `Alive` replaces the
+    /// context's one so the cancellation stops the block execution.
+    pub fn run_prove_worker(&self, jobs: &ProveJobs) {
+        while self.alive.is_alive() {
+            let job = match self.control.is_draining() {
+                true => None,
+                false => jobs.pop(),
+            };
+            let (id, task, alive) = match job {
+                Some(job) => job,
+                None => {
+                    self.alive.sleep_ms(100);
+                    continue;
+                }
+            };
+            glog::info!("prove job[{}] started: {:?}", id, task.blocks);
+            let mut ctx = self.clone();
+            ctx.alive = alive;
+            let result =
+                self.l2.chain_id().map_err(debug).and_then(|chain_id| {
+                    ctx.generate_task_poe(chain_id, task).map_err(String::from)
+                });
+            if result.is_err() {
+                self.metrics.failures.inc(&[("kind", "prove")]);
+            }
+            jobs.finish(id, result);
+        }
+    }
+
+    /// Runs the jobs one by one, the batches have to be committed in order.
+    pub fn run_jobs(&self, jobs: &JobQueue) {
+        while self.alive.is_alive() {
+            let job = match jobs.next(base::time::now().as_secs()) {
+                Some(job) => job,
+                None => {
+                    self.alive.sleep_ms(1000);
+                    continue;
+                }
+            };
+            // the job is either waiting for the submission or not started
+            let hold = match job.poe.is_some() {
+                true => self.control.is_paused(),
+                false => self.control.is_draining(),
+            };
+            if hold {
+                self.alive.sleep_ms(1000);
+                continue;
+            }
+            if !self.prover.wait_attested(&self.alive) {
+                break;
+            }
+            self.process_job(jobs, job);
+        }
+    }
+
+    fn process_job(&self, jobs: &JobQueue, job: Job) {
+        let now = || base::time::now().as_secs();
+        let fail = |resume: JobState, kind: &str, err: String| {
+            self.metrics.failures.inc(&[("kind", kind)]);
+            jobs.fail(job.id, resume, err, now())
+        };
+        let poe = match (job.state, job.poe) {
+            (JobState::Signed, Some(poe)) => poe,
+            (JobState::Submitted, Some(poe)) => {
+                // the process exited before the receipt was confirmed
+                match self.verifier.is_batch_committed(&job.task.data_hash) {
+                    Ok(true) => return jobs.transit(job.id, JobState::Confirmed, None),
+                    Ok(false) => poe,
+                    Err(err) => return fail(JobState::Submitted, "rpc", err),
+                }
+            }
+            _ => {
+                jobs.transit(job.id, JobState::Fetching, None);
+                match self.verifier.is_batch_committed(&job.task.data_hash) {
+                    Ok(true) => {
+                        glog::info!("batch{:?} already committed, skip", job.task.blocks);
+                        return jobs.transit(job.id, JobState::Confirmed, None);
+                    }
+                    Ok(false) => {}
+                    Err(err) => return fail(JobState::Discovered, "rpc", err),
+                }
+                let chain_id = match self.l2.chain_id() {
+                    Ok(chain_id) => chain_id,
+                    Err(err) => return fail(JobState::Discovered, "rpc", debug(err)),
+                };
+                jobs.transit(job.id, JobState::Executing, None);
+                let poe = match self.generate_task_poe(chain_id, job.task.clone()) {
+                    Ok(poe) => poe,
+                    Err(ProveError::MessageTree(err)) => {
+                        return fail(JobState::Failed, "prove", err.into())
+                    }
+                    Err(ProveError::Other(err)) => return fail(JobState::Discovered, "prove", err),
+                };
+                jobs.transit(job.id, JobState::Signed, Some(poe.clone()));
+                if self.control.is_paused() {
+                    glog::info!("job[{}] signed, the submission is paused", job.id);
+                    return;
+                }
+                poe
+            }
+        };
+
+        jobs.transit(job.id, JobState::Submitted, None);
+        match self.submit_poe(&job.task, &poe) {
+            Ok(()) => jobs.transit(job.id, JobState::Confirmed, None),
+            Err(err) => {
+                // only the transient errors can reuse the signed Poe
+                let resume = match err.action() {
+                    VerifierErrorAction::Retry => JobState::Signed,
+                    _ => JobState::Discovered,
+                };
+                fail(resume, err.kind(), err.into());
+            }
+        }
     }
 }
 
 pub struct Api {
     build_context: Arc<BuildContext>,
+    admin_token: Option<String>,
+    jobs: Arc<JobQueue>,
+    prove_jobs: Arc<ProveJobs>,
+    events: Arc<EventBus>,
+    l1_progress: Arc<L1Progress>,
+    auth: Option<AuthConfig>,
+    rate_limiter: RateLimiter,
+    max_block_range: u64,
+    last_batch: LastBatchTracker,
 }
 
+/// The `CommitBatch` events are fetched in the windows of blocks, up to
+/// the max scan back from the head.
+const LAST_BATCH_SCAN_WINDOW: u64 = 10_000;
+const LAST_BATCH_MAX_SCAN: u64 = 100_000;
+
 impl Api {
-    fn test(&self, arg: RpcArgs<(u64,)>) -> Result<(), JsonrpcErrorObj> {
-        let block_number = arg.params.0;
-        let result = self
-            .build_context
-            .shomei
-            .fetch_proof(block_number, block_number)
-            .unwrap();
+    /// Authenticates the request by its path and applies the rate limit of
+    /// the method.
+    fn guard<T>(&self, method: &str, arg: &RpcArgs<T>) -> Result<(), JsonrpcErrorObj> {
+        let now = base::time::now();
+        if let Some(auth) = &self.auth {
+            let credential = arg.path.trim_start_matches('/');
+            if let Err(err) = auth.verify(method, credential, now.as_secs()) {
+                glog::warn!("[{}] rejected: {}", method, err);
+                return Err(JsonrpcErrorObj::client(err));
+            }
+        }
+        self.rate_limiter
+            .check(method, now.as_millis() as u64)
+            .map_err(JsonrpcErrorObj::client)
+    }
+
+    /// The admin methods take the `server.admin_token` as the first param.
+    fn check_admin(&self, token: &str) -> Result<(), JsonrpcErrorObj> {
+        match &self.admin_token {
+            Some(expect) if token_eq(expect, token) => Ok(()),
+            _ => Err(JsonrpcErrorObj::client("unauthorized".into())),
+        }
+    }
+
+    fn control_state(&self) -> ControlState {
+        let batch = self
+            .jobs
+            .list()
+            .into_iter()
+            .filter(|job| {
+                matches!(
+                    job.state,
+                    JobState::Fetching | JobState::Executing | JobState::Submitted
+                )
+            })
+            .count();
+        let prove = self
+            .prove_jobs
+            .list()
+            .into_iter()
+            .filter(|job| job.status == ProveJobStatus::Running)
+            .count();
+        self.build_context.control.state(batch + prove)
+    }
+
+    /// Attests a new prover key, the current one keeps signing until the new
+    /// one is approved.
+    fn admin_rotate_key(&self, arg: RpcArgs<(String,)>) -> Result<bool, JsonrpcErrorObj> {
+        self.guard("admin_rotateKey", &arg)?;
+        self.check_admin(&arg.params.0)?;
+        self.build_context
+            .prover
+            .request_rotation()
+            .map_err(JsonrpcErrorObj::client)?;
+        Ok(true)
+    }
+
+    fn admin_pause(&self, arg: RpcArgs<(String,)>) -> Result<ControlState, JsonrpcErrorObj> {
+        self.guard("admin_pause", &arg)?;
+        self.check_admin(&arg.params.0)?;
+        glog::info!("batch submission paused");
+        self.build_context.control.pause();
+        Ok(self.control_state())
+    }
+
+    fn admin_resume(&self, arg: RpcArgs<(String,)>) -> Result<ControlState, JsonrpcErrorObj> {
+        self.guard("admin_resume", &arg)?;
+        self.check_admin(&arg.params.0)?;
+        glog::info!("resumed");
+        self.build_context.control.resume();
+        Ok(self.control_state())
+    }
+
+    /// Stops picking up the jobs, it can be called repeatedly until
+    /// `runningJobs` drops to 0.
+    fn admin_drain(&self, arg: RpcArgs<(String,)>) -> Result<ControlState, JsonrpcErrorObj> {
+        self.guard("admin_drain", &arg)?;
+        self.check_admin(&arg.params.0)?;
+        if !self.build_context.control.is_draining() {
+            glog::info!("draining the jobs");
+            self.build_context.control.drain();
+        }
+        Ok(self.control_state())
+    }
+
+    fn admin_set_parallelism(
+        &self,
+        arg: RpcArgs<(String, SU64)>,
+    ) -> Result<ControlState, JsonrpcErrorObj> {
+        self.guard("admin_setParallelism", &arg)?;
+        self.check_admin(&arg.params.0)?;
+        let n = arg.params.1.as_u64() as usize;
+        self.build_context
+            .control
+            .set_parallelism(n)
+            .map_err(JsonrpcErrorObj::client)?;
+        glog::info!("parallelism set to {}", n);
+        Ok(self.control_state())
+    }
+
+    /// Reports the state of the prover, `ready` is false until the prover
+    /// key is attested. The fields that need the chain are null if the
+    /// request fails.
+    fn status(
+        &self,
+        arg: RpcArgs<Vec<serde_json::Value>>,
+    ) -> Result<ProverStatus, JsonrpcErrorObj> {
+        self.guard("status", &arg)?;
+        let ctx = &self.build_context;
+        let prover = ctx.prover.address();
+        let attested_until = ctx
+            .verifier
+            .prover_status(&prover)
+            .and_then(|attested| match attested {
+                // not attested
+                0 => Ok(None),
+                attested => Ok(Some(attested + ctx.verifier.attest_validity_seconds()?)),
+            })
+            .map_err(|err| glog::error!("getting prover[{:?}] status fail: {:?}", prover, err))
+            .ok()
+            .flatten();
+        let last_batch = ctx
+            .verifier
+            .block_number()
+            .map_err(debug)
+            .and_then(|head| {
+                self.last_batch.last_batch(head, |from, to| {
+                    ctx.verifier.commit_batch_events(from, to)
+                })
+            })
+            .map_err(|err| glog::error!("fetch the last batch fail: {}", err))
+            .ok()
+            .flatten();
+        let l2_chain_id = ctx
+            .l2
+            .chain_id()
+            .map_err(|err| glog::error!("fetch chain id fail: {:?}", err))
+            .ok();
+
+        let mut jobs = Vec::new();
+        for job in self.jobs.list() {
+            if job.state.is_final() {
+                continue;
+            }
+            jobs.push(InFlightJob {
+                kind: "batch".into(),
+                id: job.id,
+                start: *job.task.blocks.first().unwrap(),
+                end: *job.task.blocks.last().unwrap(),
+                state: format!("{:?}", job.state),
+            });
+        }
+        for job in self.prove_jobs.list() {
+            if job.status.is_final() {
+                continue;
+            }
+            jobs.push(InFlightJob {
+                kind: "prove".into(),
+                id: job.id,
+                start: job.start,
+                end: job.end,
+                state: format!("{:?}", job.status),
+            });
+        }
+
+        Ok(ProverStatus {
+            ready: ctx.prover.is_attested(),
+            prover,
+            staging_prover: ctx.prover.staging_address(),
+            attested_until,
+            l2_chain_id,
+            last_l1_block: self.l1_progress.last_block(),
+            last_batch,
+            jobs,
+            mrenclave: crate::self_mrenclave(),
+        })
+    }
+
+    /// Subscribes the websocket connection to one of "poeProduced",
+    /// "batchCommitted", "attestationRenewed" and "jobFailed", returns the
+    /// subscription id of the `eth_subscription` notifications.
+    fn eth_subscribe(&self, arg: RpcArgs<(EventKind,)>) -> Result<SU64, JsonrpcErrorObj> {
+        self.guard("eth_subscribe", &arg)?;
+        let session = match &arg.session {
+            Some(session) => session.clone(),
+            None => {
+                return Err(JsonrpcErrorObj::client(
+                    "eth_subscribe is only available over websocket".into(),
+                ))
+            }
+        };
+        let sink = Box::new(move |msg: String| session.send(msg).is_ok());
+        Ok(self.events.subscribe(arg.params.0, sink).into())
+    }
+
+    fn eth_unsubscribe(&self, arg: RpcArgs<(SU64,)>) -> Result<bool, JsonrpcErrorObj> {
+        self.guard("eth_unsubscribe", &arg)?;
+        Ok(self.events.unsubscribe(arg.params.0.as_u64()))
+    }
+
+    /// Queues the proving of the block range, returns the job id.
+    fn prove_submit(&self, arg: RpcArgs<(SU64, SU64)>) -> Result<SU64, JsonrpcErrorObj> {
+        self.guard("proveSubmit", &arg)?;
+        let task = self.range_task(arg.params.0.as_u64(), arg.params.1.as_u64())?;
+        if self.build_context.control.is_draining() {
+            return Err(JsonrpcErrorObj::client("the prover is draining".into()));
+        }
+        let id = self
+            .prove_jobs
+            .submit(task)
+            .map_err(JsonrpcErrorObj::client)?;
+        Ok(id.into())
+    }
+
+    fn prove_status(&self, arg: RpcArgs<(SU64,)>) -> Result<ProveJobInfo, JsonrpcErrorObj> {
+        self.guard("proveStatus", &arg)?;
+        let id = arg.params.0.as_u64();
+        self.prove_jobs
+            .status(id)
+            .ok_or_else(|| JsonrpcErrorObj::client(format!("job[{}] not found", id)))
+    }
 
+    fn prove_result(&self, arg: RpcArgs<(SU64,)>) -> Result<Poe, JsonrpcErrorObj> {
+        self.guard("proveResult", &arg)?;
+        let id = arg.params.0.as_u64();
+        match self.prove_jobs.result(id) {
+            Some(Ok(poe)) => Ok(poe),
+            Some(Err(info)) => Err(JsonrpcErrorObj::client(match info.error {
+                Some(err) => format!("job[{}] {:?}: {}", id, info.status, err),
+                None => format!("job[{}] is {:?}", id, info.status),
+            })),
+            None => Err(JsonrpcErrorObj::client(format!("job[{}] not found", id))),
+        }
+    }
+
+    fn prove_cancel(&self, arg: RpcArgs<(SU64,)>) -> Result<bool, JsonrpcErrorObj> {
+        self.guard("proveCancel", &arg)?;
+        Ok(self.prove_jobs.cancel(arg.params.0.as_u64()))
+    }
+
+    fn prove_zk(&self, arg: RpcArgs<(SU64, SU64)>) -> Result<Poe, JsonrpcErrorObj> {
+        self.guard("proveZk", &arg)?;
+        let task = self.range_task(arg.params.0.as_u64(), arg.params.1.as_u64())?;
         let chain_id = self
             .build_context
             .l2
             .chain_id()
             .map_err(|e| JsonrpcErrorObj::server("fetch chain id fail", e))?;
-
-        let block = self
+        let poe = self
             .build_context
-            .l2
-            .get_block(block_number.into())
-            .map_err(JsonrpcErrorObj::unknown)?;
-        glog::info!("blk: {:?}", block);
-
-        self.build_context
-            .generate_poe_v2(chain_id, result, block)
-            .map_err(JsonrpcErrorObj::client)?;
-        Ok(())
+            .generate_zk_poe(chain_id, task)
+            .map_err(|err| JsonrpcErrorObj::client(String::from(err)))?;
+        Ok(poe)
     }
 
-    fn prove(&self, arg: RpcArgs<(SU64, SU64)>) -> Result<Poe, JsonrpcErrorObj> {
-        let start = arg.params.0.as_u64();
-        let end = arg.params.1.as_u64();
+    fn range_task(&self, start: u64, end: u64) -> Result<BatchTask, JsonrpcErrorObj> {
         if end < start {
             return Err(JsonrpcErrorObj::client(format!(
                 "invalid block range: {}..{}",
                 start, end
             )));
         }
+        if end - start + 1 > self.max_block_range {
+            return Err(JsonrpcErrorObj::client(format!(
+                "block range {}..{} exceeds the limit: {}",
+                start, end, self.max_block_range
+            )));
+        }
+        Ok(BatchTask {
+            data_hash: SH256::default(),
+            blocks: (start..=end).collect(),
+            blob: None,
+            calldata: None,
+        })
+    }
+
+    fn prove(&self, arg: RpcArgs<(SU64, SU64)>) -> Result<Poe, JsonrpcErrorObj> {
+        self.guard("prove", &arg)?;
+        let task = self.range_task(arg.params.0.as_u64(), arg.params.1.as_u64())?;
         let chain_id = self
             .build_context
             .l2
             .chain_id()
             .map_err(|e| JsonrpcErrorObj::server("fetch chain id fail", e))?;
-
-        let task = BatchTask {
-            data_hash: SH256::default(),
-            blocks: (start..=end).collect(),
-        };
         let poe = self
             .build_context
             .generate_poe(chain_id, task)
-            .map_err(JsonrpcErrorObj::client)?;
+            .map_err(|err| JsonrpcErrorObj::client(String::from(err)))?;
         Ok(poe)
     }
 }
 
+impl App {
+    /// Reports the problems of the config and the files it points to, the
+    /// getters can only unwrap them.
+    pub fn precheck(&self) -> Result<(), String> {
+        let cfg = Config::load(&self.args.get().cfg)?;
+        JobQueue::check(&cfg.jobs.path)?;
+        read_tls(&cfg.server.tls)?;
+        Ok(())
+    }
+}
+
+/// Reads `<path>.crt` and `<path>.key`, TLS is disabled if the path is empty.
+fn read_tls(path: &str) -> Result<(Vec<u8>, Vec<u8>), String> {
+    if path.is_empty() {
+        return Ok((Vec::new(), Vec::new()));
+    }
+    let read = |file: String| -> Result<Vec<u8>, String> {
+        read_file(&file)
+            .map(|data| data.into())
+            .map_err(|err| format!("server.tls: read {} fail: {:?}", file, err))
+    };
+    Ok((read(format!("{}.crt", path))?, read(format!("{}.key", path))?))
+}
+
 impl Getter<shomei::Client> for App {
     fn generate(&self) -> shomei::Client {
         let cfg = self.cfg.get(self);
@@ -348,16 +1200,20 @@ impl Getter<RpcServer<Api>> for App {
     fn generate(&self) -> RpcServer<Api> {
         let args = self.args.get();
         let cfg = self.cfg.get(self);
-        let (tls_cert, tls_key) = match cfg.server.tls.as_str() {
-            "" => (Vec::new(), Vec::new()),
-            path => (
-                read_file(&format!("{}.crt", path)).unwrap().into(),
-                read_file(&format!("{}.key", path)).unwrap().into(),
-            ),
-        };
+        // checked by `App::precheck`
+        let (tls_cert, tls_key) = read_tls(&cfg.server.tls).unwrap();
 
         let api = Api {
             build_context: self.build_context.get(self),
+            admin_token: cfg.server.admin_token.clone(),
+            jobs: self.jobs.get(self),
+            prove_jobs: self.prove_jobs.get(self),
+            events: self.events.get(self),
+            l1_progress: self.l1_progress.get(self),
+            auth: cfg.server.auth.clone(),
+            rate_limiter: RateLimiter::new(cfg.server.rate_limits.clone()),
+            max_block_range: cfg.server.max_block_range,
+            last_batch: LastBatchTracker::new(LAST_BATCH_SCAN_WINDOW, LAST_BATCH_MAX_SCAN),
         };
         let server_cfg = RpcServerConfig {
             listen_addr: format!("0.0.0.0:{}", args.port),
@@ -369,17 +1225,31 @@ impl Getter<RpcServer<Api>> for App {
             max_idle_secs: Some(60),
         };
         let mut srv = RpcServer::new(self.alive.clone(), server_cfg, Arc::new(api)).unwrap();
+        srv.jsonrpc("status", Api::status);
         srv.jsonrpc("prove", Api::prove);
-        srv.jsonrpc("test", Api::test);
+        srv.jsonrpc("proveZk", Api::prove_zk);
+        srv.jsonrpc("proveSubmit", Api::prove_submit);
+        srv.jsonrpc("proveStatus", Api::prove_status);
+        srv.jsonrpc("proveResult", Api::prove_result);
+        srv.jsonrpc("proveCancel", Api::prove_cancel);
+        srv.jsonrpc("eth_subscribe", Api::eth_subscribe);
+        srv.jsonrpc("eth_unsubscribe", Api::eth_unsubscribe);
+        if cfg.server.admin_token.is_some() {
+            srv.jsonrpc("admin_rotateKey", Api::admin_rotate_key);
+            srv.jsonrpc("admin_pause", Api::admin_pause);
+            srv.jsonrpc("admin_resume", Api::admin_resume);
+            srv.jsonrpc("admin_drain", Api::admin_drain);
+            srv.jsonrpc("admin_setParallelism", Api::admin_set_parallelism);
+        }
         srv
     }
 }
 
 impl Getter<Config> for App {
+    /// The config is checked by `App::precheck`, or by the cli commands
+    /// which use it.
     fn generate(&self) -> Config {
-        let data = base::fs::read_file(&self.args.get().cfg).unwrap();
-        let cfg: Config = serde_json::from_slice(&data).unwrap();
-        cfg
+        Config::load(&self.args.get().cfg).unwrap()
     }
 }
 
@@ -420,8 +1290,8 @@ impl Getter<Verifier<Arc<MixRpcClient>, EthereumEngineTypes>> for App {
     fn generate(&self) -> Verifier<Arc<MixRpcClient>, EthereumEngineTypes> {
         let el = &self.verifier_el.get(self).0;
         let chain_id = el.chain_id().unwrap();
-        let resend_timeout = Duration::from_secs(45);
         let cfg = self.cfg.get(self);
+        let resend_timeout = Duration::from_secs(cfg.retry.resend_timeout_secs);
 
         let verifier = Verifier::new(
             &self.alive,
@@ -436,12 +1306,75 @@ impl Getter<Verifier<Arc<MixRpcClient>, EthereumEngineTypes>> for App {
 
 impl Getter<Prover> for App {
     fn generate(&self) -> Prover {
-        Prover::new(self.alive.clone())
+        let cfg = self.cfg.get(self);
+        let store: Option<Box<dyn KeyStore>> = match &cfg.verifier.key_store {
+            #[cfg(feature = "tstd")]
+            Some(path) => Some(Box::new(SealedKeyStore::new(path.clone()))),
+            #[cfg(not(feature = "tstd"))]
+            Some(path) => Some(Box::new(FileKeyStore::new(path.clone()))),
+            None => None,
+        };
+        Prover::new(self.alive.clone(), store)
+    }
+}
+
+impl Getter<ProveJobs> for App {
+    fn generate(&self) -> ProveJobs {
+        let cfg = self.cfg.get(self);
+        ProveJobs::new(
+            cfg.server.max_prove_jobs,
+            cfg.server.keep_prove_jobs,
+            self.events.get(self),
+        )
+    }
+}
+
+impl Getter<JobQueue> for App {
+    fn generate(&self) -> JobQueue {
+        // checked by `App::precheck`
+        JobQueue::new(self.cfg.get(self).jobs.clone(), self.events.get(self)).unwrap()
+    }
+}
+
+impl Getter<L1Progress> for App {
+    fn generate(&self) -> L1Progress {
+        L1Progress::default()
+    }
+}
+
+impl Getter<Metrics> for App {
+    fn generate(&self) -> Metrics {
+        Metrics::new()
+    }
+}
+
+impl Getter<Control> for App {
+    fn generate(&self) -> Control {
+        Control::new(self.cfg.get(self).execution.parallelism)
+    }
+}
+
+impl Getter<EventBus> for App {
+    fn generate(&self) -> EventBus {
+        EventBus::new()
     }
 }
 
 impl Getter<BuildContext> for App {
     fn generate(&self) -> BuildContext {
+        let cfg = self.cfg.get(self);
+        let header_chain = match &cfg.header_chain {
+            Some(header_chain) => Some(Arc::new(HeaderChain::new(
+                &self.alive,
+                header_chain.clone(),
+                self.l2.get(self).as_ref().clone(),
+                HeaderChainStore::new(cfg.storage.header_chain.clone()),
+            ))),
+            None => {
+                glog::warn!("header chain verification is disabled, the l2 endpoint is trusted");
+                None
+            }
+        };
         let build_ctx = BuildContext {
             alive: self.alive.clone(),
             cfg: self.cfg.get(self),
@@ -449,6 +1382,14 @@ impl Getter<BuildContext> for App {
             verifier: self.verifier.get(self),
             l2: self.l2.get(self),
             shomei: self.shomei.get(self),
+            header_chain,
+            rollup: Arc::new(Rollup::new(
+                self.rollup_el.get(self).0.clone(),
+                cfg.rollup.contract,
+            )),
+            events: self.events.get(self),
+            metrics: self.metrics.get(self),
+            control: self.control.get(self),
         };
         build_ctx
     }
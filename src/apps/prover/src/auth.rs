@@ -0,0 +1,171 @@
+use std::prelude::v1::*;
+
+use crypto::sha256_sum;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+/// How the JSON-RPC clients authenticate. The credential is the request path,
+/// e.g. `http://prover:18400/<credential>`, so it ends up in the access logs
+/// of the proxies in between.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
+pub enum AuthConfig {
+    /// The credential is the token itself.
+    Bearer { token: String },
+    /// The credential is
+    /// `<unix timestamp>.<hex hmac-sha256(secret, "<timestamp>.<method>")>`,
+    /// so the secret never goes over the wire and a logged credential only
+    /// works for the same method until it expires. The params aren't covered,
+    /// the handlers don't see the request body.
+    Hmac {
+        secret: String,
+        #[serde(default = "default_max_skew_secs")]
+        max_skew_secs: u64,
+    },
+}
+
+fn default_max_skew_secs() -> u64 {
+    30
+}
+
+/// Compares in constant time, the token shouldn't be guessed by the timing.
+pub fn token_eq(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0, |acc, (x, y)| acc | (x ^ y))
+            == 0
+}
+
+pub fn hmac_sha256(key: &[u8], msg: &[u8]) -> [u8; 32] {
+    let mut block = [0_u8; 64];
+    if key.len() > block.len() {
+        block[..32].copy_from_slice(&sha256_sum(key));
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+    let mut inner: Vec<u8> = block.iter().map(|b| b ^ 0x36).collect();
+    inner.extend_from_slice(msg);
+    let mut outer: Vec<u8> = block.iter().map(|b| b ^ 0x5c).collect();
+    outer.extend_from_slice(&sha256_sum(&inner));
+    sha256_sum(&outer)
+}
+
+fn hex_encode(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+impl AuthConfig {
+    pub fn verify(&self, method: &str, credential: &str, now: u64) -> Result<(), String> {
+        match self {
+            Self::Bearer { token } => match token_eq(token, credential) {
+                true => Ok(()),
+                false => Err("unauthorized".into()),
+            },
+            Self::Hmac {
+                secret,
+                max_skew_secs,
+            } => {
+                let (ts, mac) = credential
+                    .split_once('.')
+                    .ok_or_else(|| "unauthorized".to_owned())?;
+                let ts: u64 = ts.parse().map_err(|_| "unauthorized".to_owned())?;
+                let skew = if ts > now { ts - now } else { now - ts };
+                if skew > *max_skew_secs {
+                    return Err(format!("credential expired, skew: {}s", skew));
+                }
+                let msg = format!("{}.{}", ts, method);
+                let expect = hex_encode(&hmac_sha256(secret.as_bytes(), msg.as_bytes()));
+                match token_eq(&expect, &mac.to_ascii_lowercase()) {
+                    true => Ok(()),
+                    false => Err("unauthorized".into()),
+                }
+            }
+        }
+    }
+}
+
+/// The token buckets of the methods, refilled continuously up to the limit
+/// per minute.
+pub struct RateLimiter {
+    limits: BTreeMap<String, u32>,
+    buckets: Mutex<BTreeMap<String, (f64, u64)>>,
+}
+
+impl RateLimiter {
+    pub fn new(limits: BTreeMap<String, u32>) -> Self {
+        Self {
+            limits,
+            buckets: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// `now_ms` is the unix timestamp in milliseconds.
+    pub fn check(&self, method: &str, now_ms: u64) -> Result<(), String> {
+        let limit = match self.limits.get(method) {
+            Some(limit) => *limit as f64,
+            None => return Ok(()),
+        };
+        let mut buckets = self.buckets.lock().unwrap();
+        let (tokens, last) = buckets
+            .entry(method.into())
+            .or_insert_with(|| (limit, now_ms));
+        let elapsed = now_ms.saturating_sub(*last) as f64;
+        *tokens = (*tokens + elapsed * limit / 60_000.0).min(limit);
+        *last = now_ms;
+        if *tokens < 1.0 {
+            return Err(format!("{} is limited to {} per minute", method, limit));
+        }
+        *tokens -= 1.0;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_hmac_sha256() {
+        // RFC 4231 test case 1
+        let mac = hmac_sha256(&[0x0b; 20], b"Hi There");
+        assert_eq!(
+            hex_encode(&mac),
+            "b0344c61d8db38535ca8afceaf0bf12b881dc200c9833da726e9376c2e32cff7"
+        );
+
+        let auth = AuthConfig::Hmac {
+            secret: "secret".into(),
+            max_skew_secs: 30,
+        };
+        // hmac-sha256("secret", "1700000000.status")
+        let credential =
+            "1700000000.3c5179a1be67939c845f62ad918293b4005161d684353a029363d2b09f44036d";
+        assert_eq!(auth.verify("status", credential, 1700000010), Ok(()));
+        assert!(auth.verify("status", credential, 1700000031).is_err());
+        // the credential can't be reused for the other methods
+        assert!(auth.verify("proveSubmit", credential, 1700000010).is_err());
+        assert!(auth.verify("status", "1700000000.00", 1700000000).is_err());
+        // the mac of the timestamp alone
+        let credential =
+            "1700000000.4b227f8831b3763d066901751ad4c583ed08832bf1924a4ec50c2e871b1e8586";
+        assert!(auth.verify("status", credential, 1700000010).is_err());
+
+        let auth = AuthConfig::Bearer {
+            token: "token".into(),
+        };
+        assert_eq!(auth.verify("status", "token", 0), Ok(()));
+        assert!(auth.verify("status", "toke", 0).is_err());
+    }
+
+    #[test]
+    fn test_rate_limiter() {
+        let limiter = RateLimiter::new([("prove".to_owned(), 2)].into_iter().collect());
+        assert!(limiter.check("prove", 0).is_ok());
+        assert!(limiter.check("prove", 0).is_ok());
+        assert!(limiter.check("prove", 1000).is_err());
+        assert!(limiter.check("prove", 31_000).is_ok());
+        assert!(limiter.check("status", 31_000).is_ok());
+    }
+}
@@ -0,0 +1,169 @@
+use std::prelude::v1::*;
+
+use app::Getter;
+use base::format::debug;
+use eth_types::{HexBytes, SH256};
+use evm_executor::{Pob, Poe};
+use linea::BlockExecutor;
+use mpt::Database;
+
+use crate::{App, Config};
+
+pub const CLI_USAGE: &str = "usage: <command>
+    fetch-pob <block> [file]        write the Pob of the block to the file
+    execute <pob-file>              execute the block of the Pob
+    verify-poe <hex>                recover the signer of the encoded Poe
+    dump-state <root> <pob-file>    list the state trie leaves in the Pob
+    mrenclave                       show the mrenclave of this build";
+
+#[derive(Debug, Clone)]
+pub enum CliCommand {
+    FetchPob { block: u64, file: String },
+    Execute(String),
+    VerifyPoe(Vec<u8>),
+    DumpState { root: SH256, file: String },
+    MrEnclave,
+}
+
+fn parse_hex(val: &str) -> Result<Vec<u8>, String> {
+    let hex = val.trim_start_matches("0x");
+    if hex.len() % 2 != 0 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(format!("invalid hex: {}", val));
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).map_err(debug))
+        .collect()
+}
+
+fn read_pob(file: &str) -> Result<Pob, String> {
+    let data = base::fs::read_file(file).map_err(|err| format!("read {} fail: {:?}", file, err))?;
+    serde_json::from_slice(&data).map_err(|err| format!("decode {} fail: {:?}", file, err))
+}
+
+impl CliCommand {
+    /// Returns None if it's not a cli command.
+    pub fn parse(args: &[String]) -> Option<Result<Self, String>> {
+        let arg = |idx: usize| {
+            args.get(idx)
+                .map(|n| n.as_str())
+                .ok_or_else(|| CLI_USAGE.to_owned())
+        };
+        let parse = || -> Result<Self, String> {
+            Ok(match arg(0)? {
+                "fetch-pob" => {
+                    let block = arg(1)?;
+                    Self::FetchPob {
+                        block: block.parse().map_err(debug)?,
+                        file: match args.get(2) {
+                            Some(file) => file.clone(),
+                            None => format!("pob-{}.json", block),
+                        },
+                    }
+                }
+                "execute" => Self::Execute(arg(1)?.into()),
+                "verify-poe" => Self::VerifyPoe(parse_hex(arg(1)?)?),
+                "dump-state" => {
+                    let root = parse_hex(arg(1)?)?;
+                    if root.len() != 32 {
+                        return Err(format!("expect 32 bytes root, got: {}", args[1]));
+                    }
+                    let mut hash = [0_u8; 32];
+                    hash.copy_from_slice(&root);
+                    Self::DumpState {
+                        root: hash.into(),
+                        file: arg(2)?.into(),
+                    }
+                }
+                "mrenclave" => Self::MrEnclave,
+                _ => unreachable!(),
+            })
+        };
+        match args.first().map(|n| n.as_str()) {
+            Some("fetch-pob" | "execute" | "verify-poe" | "dump-state" | "mrenclave") => {
+                Some(parse())
+            }
+            _ => None,
+        }
+    }
+
+    /// Only `fetch-pob` and `verify-poe` read the config, they check it before
+    /// the getters. The others work offline.
+    pub fn run(&self, app: &App) -> Result<(), String> {
+        match self {
+            Self::FetchPob { block, file } => {
+                Config::load(&app.args.get().cfg)?;
+                let l2 = app.l2.get(app);
+                let chain_id = l2.chain_id().map_err(debug)?;
+                let be = BlockExecutor::new(chain_id.into());
+                let pob = be.generate_pob(l2.as_ref(), (*block).into())?;
+                let data = serde_json::to_vec(&pob).map_err(debug)?;
+                base::fs::write_file(file, &data).map_err(debug)?;
+                glog::info!("block[{}] pob written to {}", block, file);
+            }
+            Self::Execute(file) => {
+                let pob = read_pob(file)?;
+                let state_hash = pob.state_hash();
+                let prev_state_root = pob.data.prev_state_root;
+                let expect_state_root = pob.block.header.state_root;
+                let be = BlockExecutor::new(pob.data.chain_id.into());
+                let (block, receipts) = be.execute(&Database::new(), pob)?;
+                glog::info!(
+                    "block[{}] executed, txs: {}, receipts: {}",
+                    block.header.number,
+                    block.transactions.len(),
+                    receipts.len()
+                );
+                if block.header.state_root != expect_state_root {
+                    return Err(format!(
+                        "state root mismatch, remote: {:?}, local: {:?}",
+                        expect_state_root, block.header.state_root
+                    ));
+                }
+                let poe = Poe::single_block(
+                    state_hash,
+                    prev_state_root,
+                    expect_state_root,
+                    SH256::default(),
+                );
+                glog::info!("poe: {:?}", poe);
+            }
+            Self::VerifyPoe(data) => {
+                Config::load(&app.args.get().cfg)?;
+                let verifier = app.verifier.get(app);
+                let signer = verifier.recover_poe(data).map_err(debug)?;
+                let attested = verifier.prover_status(&signer).map_err(debug)?;
+                let validity = verifier.attest_validity_seconds().map_err(debug)?;
+                let now = base::time::now().as_secs();
+                glog::info!(
+                    "signer: {:?}, attested: {}, attested until: {}",
+                    signer,
+                    attested + validity > now,
+                    attested + validity
+                );
+            }
+            Self::DumpState { root, file } => {
+                let pob = read_pob(file)?;
+                let nodes: Vec<&[u8]> = pob.data.mpt_nodes.iter().map(|n| n.as_bytes()).collect();
+                let dump = linea::dump_trie(&nodes, root)?;
+                for (key, value) in &dump.leaves {
+                    glog::info!(
+                        "{:?} => {:?}",
+                        HexBytes::from(&key[..]),
+                        HexBytes::from(&value[..])
+                    );
+                }
+                glog::info!(
+                    "leaves: {}, nodes not in the pob: {}",
+                    dump.leaves.len(),
+                    dump.missing.len()
+                );
+            }
+            Self::MrEnclave => match crate::self_mrenclave() {
+                Some(mrenclave) => glog::info!("mrenclave: {:?}", mrenclave),
+                None => return Err("mrenclave is only available in the enclave".into()),
+            },
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,412 @@
+use std::prelude::v1::*;
+
+use eth_types::SH160;
+use serde_json::Value;
+
+use crate::{AuthConfig, Config};
+
+/// The prefix of the environment variables overriding the config, the
+/// sections are separated by `__`, e.g. `PROVER__VERIFIER__RELAY_ACCOUNT`.
+pub const ENV_PREFIX: &str = "PROVER__";
+
+/// How the value of an overridden field is taken.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum FieldKind {
+    /// Taken as it is, so a value like `12345` or `true` stays a string. The
+    /// hex values are strings as well, a private key may be all digits.
+    Str,
+    /// Parsed as json.
+    Json,
+    /// A map whose keys are the last part of the path, they keep their case,
+    /// e.g. `PROVER__SERVER__RATE_LIMITS__proveSubmit`. The whole map can be
+    /// set as json as well.
+    Map,
+}
+
+use FieldKind::{Json, Map, Str};
+
+/// The fields which can be overridden, any other path is rejected.
+const FIELDS: &[(&str, FieldKind)] = &[
+    ("l2", Str),
+    ("server.tls", Str),
+    ("server.body_limit", Json),
+    ("server.workers", Json),
+    ("server.prove_workers", Json),
+    ("server.max_prove_jobs", Json),
+    ("server.keep_prove_jobs", Json),
+    ("server.metrics_port", Json),
+    ("server.admin_token", Str),
+    ("server.auth.type", Str),
+    ("server.auth.token", Str),
+    ("server.auth.secret", Str),
+    ("server.auth.max_skew_secs", Json),
+    ("server.rate_limits", Map),
+    ("server.max_block_range", Json),
+    ("rollup.endpoint", Str),
+    ("rollup.contract", Str),
+    ("rollup.wait_block", Json),
+    ("rollup.max_block", Json),
+    ("rollup.reorg_window", Json),
+    ("verifier.endpoint", Str),
+    ("verifier.contract", Str),
+    ("verifier.relay_account", Str),
+    ("verifier.zktrie", Json),
+    ("verifier.key_store", Str),
+    ("verifier.genesis_batch", Str),
+    ("shomei.endpoint", Str),
+    ("shomei.version", Str),
+    ("header_chain.checkpoint_number", Json),
+    ("header_chain.checkpoint_hash", Str),
+    ("header_chain.epoch", Json),
+    ("header_chain.period", Json),
+    ("message_service.l2_contract", Str),
+    ("message_service.tree_depth", Json),
+    ("jobs.path", Str),
+    ("jobs.max_attempts", Json),
+    ("jobs.retry_backoff_secs", Json),
+    ("jobs.keep_finished", Json),
+    ("execution.parallelism", Json),
+    ("retry.submit_retries", Json),
+    ("retry.submit_retry_interval_ms", Json),
+    ("retry.resend_timeout_secs", Json),
+    ("storage.checkpoint", Str),
+    ("storage.header_chain", Str),
+];
+
+fn field_kind(path: &str) -> Option<FieldKind> {
+    FIELDS
+        .iter()
+        .find(|(field, _)| *field == path)
+        .map(|(_, kind)| *kind)
+}
+
+/// Maps the parts of `PROVER__<SECTION>__<FIELD>` to the config path, the
+/// section and field names are case insensitive, the map keys are not.
+fn resolve_path(parts: &[&str]) -> Option<(Vec<String>, FieldKind)> {
+    let lower: Vec<String> = parts.iter().map(|p| p.to_ascii_lowercase()).collect();
+    match field_kind(&lower.join(".")) {
+        Some(Map) => return Some((lower, Json)),
+        Some(kind) => return Some((lower, kind)),
+        None => {}
+    }
+    let (key, section) = parts.split_last()?;
+    match field_kind(&lower[..section.len()].join(".")) {
+        Some(Map) => {
+            let mut path = lower[..section.len()].to_vec();
+            path.push(key.to_string());
+            Some((path, Json))
+        }
+        _ => None,
+    }
+}
+
+impl Config {
+    /// Reads the config file, applies the environment overrides and
+    /// validates the result.
+    pub fn load(path: &str) -> Result<Self, String> {
+        let data = base::fs::read_file(path)
+            .map_err(|err| format!("read config {} fail: {:?}", path, err))?;
+        let mut value: Value = serde_json::from_slice(&data)
+            .map_err(|err| format!("config {} is not a valid json: {}", path, err))?;
+        apply_env_overrides(&mut value, std::env::vars())?;
+        let cfg: Config = serde_json::from_value(value)
+            .map_err(|err| format!("config {} is invalid: {}", path, err))?;
+        if let Err(errs) = cfg.validate() {
+            return Err(format!(
+                "config {} is invalid:\n  {}",
+                path,
+                errs.join("\n  ")
+            ));
+        }
+        Ok(cfg)
+    }
+
+    /// Collects all the problems instead of stopping at the first one.
+    pub fn validate(&self) -> Result<(), Vec<String>> {
+        let mut errs = Vec::new();
+        let mut check = |ok: bool, msg: &str| {
+            if !ok {
+                errs.push(msg.to_owned());
+            }
+        };
+        let is_url = |url: &str| {
+            ["http://", "https://", "ws://", "wss://"]
+                .iter()
+                .any(|scheme| url.starts_with(scheme))
+        };
+
+        check(is_url(&self.l2), "l2: expect a http(s) or ws(s) url");
+        check(
+            is_url(&self.rollup.endpoint),
+            "rollup.endpoint: expect a http(s) or ws(s) url",
+        );
+        check(
+            self.rollup.contract != SH160::default(),
+            "rollup.contract: should not be the zero address",
+        );
+        check(self.rollup.max_block > 0, "rollup.max_block: should be > 0");
+        check(
+            is_url(&self.verifier.endpoint),
+            "verifier.endpoint: expect a http(s) or ws(s) url",
+        );
+        check(
+            self.verifier.contract != SH160::default(),
+            "verifier.contract: should not be the zero address",
+        );
+        check(
+            is_url(&self.shomei.endpoint),
+            "shomei.endpoint: expect a http(s) or ws(s) url",
+        );
+
+        check(self.server.workers > 0, "server.workers: should be > 0");
+        check(
+            self.server.body_limit > 0,
+            "server.body_limit: should be > 0",
+        );
+        check(
+            self.server.prove_workers > 0,
+            "server.prove_workers: should be > 0",
+        );
+        check(
+            self.server.keep_prove_jobs > 0,
+            "server.keep_prove_jobs: should be > 0",
+        );
+        check(
+            self.server.max_block_range > 0,
+            "server.max_block_range: should be > 0",
+        );
+        check(
+            self.server.admin_token.as_ref().map(|t| t.len() > 0) != Some(false),
+            "server.admin_token: should not be empty",
+        );
+        let auth_ok = match &self.server.auth {
+            Some(AuthConfig::Bearer { token }) => token.len() > 0,
+            Some(AuthConfig::Hmac { secret, .. }) => secret.len() > 0,
+            None => true,
+        };
+        check(
+            auth_ok,
+            "server.auth: the token or secret should not be empty",
+        );
+        check(
+            self.server.rate_limits.values().all(|limit| *limit > 0),
+            "server.rate_limits: the limits should be > 0",
+        );
+
+        check(
+            self.jobs.max_attempts > 0,
+            "jobs.max_attempts: should be > 0",
+        );
+        check(self.jobs.path.len() > 0, "jobs.path: should not be empty");
+        check(
+            self.execution.parallelism > 0,
+            "execution.parallelism: should be > 0",
+        );
+        check(
+            self.storage.checkpoint.len() > 0,
+            "storage.checkpoint: should not be empty",
+        );
+        check(
+            self.storage.header_chain.len() > 0,
+            "storage.header_chain: should not be empty",
+        );
+
+        match errs.len() {
+            0 => Ok(()),
+            _ => Err(errs),
+        }
+    }
+}
+
+/// Sets the fields from `PROVER__<SECTION>__<FIELD>`. Only the paths in
+/// `FIELDS` are accepted, a typo fails the startup instead of being ignored.
+/// The string fields take the value as it is, so the secrets can be passed as
+/// they are. The others are parsed as json.
+pub fn apply_env_overrides<I>(cfg: &mut Value, vars: I) -> Result<(), String>
+where
+    I: IntoIterator<Item = (String, String)>,
+{
+    for (key, val) in vars {
+        let path = match key.strip_prefix(ENV_PREFIX) {
+            Some(path) => path,
+            None => continue,
+        };
+        let parts: Vec<&str> = path.split("__").collect();
+        if parts.iter().any(|part| part.is_empty()) {
+            return Err(format!("invalid config override: {}", key));
+        }
+        let (fields, kind) =
+            resolve_path(&parts).ok_or_else(|| format!("unknown config override: {}", key))?;
+
+        let mut node = &mut *cfg;
+        for field in &fields[..fields.len() - 1] {
+            let obj = node
+                .as_object_mut()
+                .ok_or_else(|| format!("{}: {} is not a section", key, field))?;
+            node = obj
+                .entry(field.to_string())
+                .or_insert_with(|| Value::Object(Default::default()));
+        }
+        let obj = node
+            .as_object_mut()
+            .ok_or_else(|| format!("{}: the parent is not a section", key))?;
+        let new = match kind {
+            Str => Value::String(val),
+            _ => serde_json::from_str(&val).unwrap_or(Value::String(val)),
+        };
+        glog::info!("config overridden by {}", key);
+        obj.insert(fields[fields.len() - 1].clone(), new);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_apply_env_overrides() {
+        let mut cfg = serde_json::json!({
+            "l2": "http://localhost:8545",
+            "server": {"workers": 10, "tls": ""},
+        });
+        let vars = vec![
+            ("PROVER__L2".to_owned(), "http://l2:8545".to_owned()),
+            ("PROVER__SERVER__WORKERS".to_owned(), "4".to_owned()),
+            ("PROVER__SERVER__TLS".to_owned(), "123".to_owned()),
+            (
+                "PROVER__VERIFIER__RELAY_ACCOUNT".to_owned(),
+                "0x1234".to_owned(),
+            ),
+            ("PROVER__EXECUTION__PARALLELISM".to_owned(), "16".to_owned()),
+            ("HOME".to_owned(), "/root".to_owned()),
+        ];
+        apply_env_overrides(&mut cfg, vars).unwrap();
+        assert_eq!(
+            cfg,
+            serde_json::json!({
+                "l2": "http://l2:8545",
+                "server": {"workers": 4, "tls": "123"},
+                "verifier": {"relay_account": "0x1234"},
+                "execution": {"parallelism": 16},
+            })
+        );
+
+        // the missing fields take the type of the config
+        let mut missing = serde_json::json!({"server": {}});
+        let vars = vec![
+            ("PROVER__SERVER__ADMIN_TOKEN".to_owned(), "12345".to_owned()),
+            ("PROVER__SERVER__AUTH__TYPE".to_owned(), "hmac".to_owned()),
+            ("PROVER__SERVER__AUTH__SECRET".to_owned(), "true".to_owned()),
+            (
+                "PROVER__SERVER__AUTH__MAX_SKEW_SECS".to_owned(),
+                "60".to_owned(),
+            ),
+            (
+                "PROVER__VERIFIER__RELAY_ACCOUNT".to_owned(),
+                "1234".to_owned(),
+            ),
+            ("PROVER__VERIFIER__ZKTRIE".to_owned(), "true".to_owned()),
+        ];
+        apply_env_overrides(&mut missing, vars).unwrap();
+        assert_eq!(
+            missing,
+            serde_json::json!({
+                "server": {
+                    "admin_token": "12345",
+                    "auth": {"type": "hmac", "secret": "true", "max_skew_secs": 60},
+                },
+                "verifier": {"relay_account": "1234", "zktrie": true},
+            })
+        );
+        let auth: AuthConfig = serde_json::from_value(missing["server"]["auth"].clone()).unwrap();
+        match auth {
+            AuthConfig::Hmac {
+                secret,
+                max_skew_secs,
+            } => assert_eq!((secret.as_str(), max_skew_secs), ("true", 60)),
+            other => panic!("unexpected auth: {:?}", other),
+        }
+
+        let vars = vec![("PROVER__L2__ENDPOINT".to_owned(), "x".to_owned())];
+        assert!(apply_env_overrides(&mut cfg, vars).is_err());
+        let vars = vec![("PROVER____L2".to_owned(), "x".to_owned())];
+        assert!(apply_env_overrides(&mut cfg, vars).is_err());
+    }
+
+    #[test]
+    fn test_unknown_overrides() {
+        let mut cfg = serde_json::json!({"server": {"workers": 10}});
+        for key in [
+            "PROVER__SERVER__WORKER",
+            "PROVER__VERIFIER__ZKTRI",
+            "PROVER__SERVER__WORKERS__MAX",
+            "PROVER__STORAGE",
+        ] {
+            let vars = vec![(key.to_owned(), "1".to_owned())];
+            let err = apply_env_overrides(&mut cfg, vars).unwrap_err();
+            assert!(err.starts_with("unknown config override"), "{}", err);
+        }
+        assert_eq!(cfg, serde_json::json!({"server": {"workers": 10}}));
+
+        // the map keys keep their case, the sections don't care
+        let vars = vec![
+            (
+                "PROVER__SERVER__RATE_LIMITS__proveSubmit".to_owned(),
+                "5".to_owned(),
+            ),
+            (
+                "PROVER__Server__Rate_Limits__prove".to_owned(),
+                "10".to_owned(),
+            ),
+        ];
+        apply_env_overrides(&mut cfg, vars).unwrap();
+        assert_eq!(
+            cfg,
+            serde_json::json!({
+                "server": {"workers": 10, "rate_limits": {"proveSubmit": 5, "prove": 10}},
+            })
+        );
+        let vars = vec![(
+            "PROVER__SERVER__RATE_LIMITS".to_owned(),
+            r#"{"proveZk": 1}"#.to_owned(),
+        )];
+        apply_env_overrides(&mut cfg, vars).unwrap();
+        assert_eq!(
+            cfg["server"]["rate_limits"],
+            serde_json::json!({"proveZk": 1})
+        );
+    }
+
+    #[test]
+    fn test_deny_unknown_fields() {
+        let mut cfg = serde_json::json!({
+            "l2": "http://localhost:8545",
+            "verifier": {
+                "endpoint": "http://localhost:8545",
+                "relay_account": "0x135e5f68224c169b016d92aedb6af6163e6d985dd6d25b3bbd1124e964490843",
+                "contract": "0xB2712722E724C7a111eB9ED90F556f11a0038026"
+            },
+            "rollup": {
+                "endpoint": "http://localhost:8545",
+                "contract": "0x70BaD09280FD342D02fe64119779BC1f0791BAC2"
+            },
+            "server": {"tls": "", "body_limit": 2097152, "workers": 10},
+            "shomei": {"endpoint": "http://localhost:8888", "version": "2.2.0"},
+        });
+        assert!(serde_json::from_value::<Config>(cfg.clone()).is_ok());
+
+        for (section, field) in [
+            ("server", "worker"),
+            ("verifier", "zk_trie"),
+            ("execution", "paralelism"),
+        ] {
+            let mut typo = cfg.clone();
+            typo[section][field] = 1.into();
+            let err = serde_json::from_value::<Config>(typo).unwrap_err();
+            assert!(err.to_string().contains("unknown field"), "{}", err);
+        }
+        cfg["server"]["auth"] = serde_json::json!({"type": "bearer", "secret": "x"});
+        assert!(serde_json::from_value::<Config>(cfg).is_err());
+    }
+}
@@ -0,0 +1,79 @@
+use std::prelude::v1::*;
+
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+/// The switches the operator can flip on a running prover through the admin
+/// RPC.
+pub struct Control {
+    paused: AtomicBool,
+    draining: AtomicBool,
+    parallelism: AtomicUsize,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ControlState {
+    pub paused: bool,
+    pub draining: bool,
+    pub parallelism: usize,
+    /// The jobs being executed, the drain is done when it reaches 0.
+    pub running_jobs: usize,
+}
+
+impl Control {
+    pub fn new(parallelism: usize) -> Self {
+        Self {
+            paused: AtomicBool::new(false),
+            draining: AtomicBool::new(false),
+            parallelism: AtomicUsize::new(parallelism),
+        }
+    }
+
+    /// The signed Poe is held until resumed.
+    pub fn pause(&self) {
+        self.paused.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::SeqCst)
+    }
+
+    /// No new job is picked up, the running ones are finished.
+    pub fn drain(&self) {
+        self.draining.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_draining(&self) -> bool {
+        self.draining.load(Ordering::SeqCst)
+    }
+
+    /// Clears both the pause and the drain.
+    pub fn resume(&self) {
+        self.paused.store(false, Ordering::SeqCst);
+        self.draining.store(false, Ordering::SeqCst);
+    }
+
+    /// How many blocks of a batch are executed concurrently, it takes effect
+    /// from the next batch.
+    pub fn parallelism(&self) -> usize {
+        self.parallelism.load(Ordering::SeqCst)
+    }
+
+    pub fn set_parallelism(&self, n: usize) -> Result<(), String> {
+        if n == 0 {
+            return Err("parallelism should be greater than 0".into());
+        }
+        self.parallelism.store(n, Ordering::SeqCst);
+        Ok(())
+    }
+
+    pub fn state(&self, running_jobs: usize) -> ControlState {
+        ControlState {
+            paused: self.is_paused(),
+            draining: self.is_draining(),
+            parallelism: self.parallelism(),
+            running_jobs,
+        }
+    }
+}
@@ -0,0 +1,231 @@
+use std::prelude::v1::*;
+
+use eth_types::{SH160, SH256, SU64};
+use evm_executor::Poe;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum EventKind {
+    PoeProduced,
+    BatchCommitted,
+    AttestationRenewed,
+    JobFailed,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum ProverEvent {
+    #[serde(rename_all = "camelCase")]
+    PoeProduced { start: u64, end: u64, poe: Poe },
+    #[serde(rename_all = "camelCase")]
+    BatchCommitted {
+        start: u64,
+        end: u64,
+        batch_hash: SH256,
+    },
+    #[serde(rename_all = "camelCase")]
+    AttestationRenewed { prover: SH160 },
+    /// `job` is "batch" for the L1 jobs and "prove" for the `proveSubmit` jobs.
+    #[serde(rename_all = "camelCase")]
+    JobFailed {
+        job: String,
+        id: u64,
+        start: u64,
+        end: u64,
+        error: String,
+    },
+}
+
+impl ProverEvent {
+    pub fn kind(&self) -> EventKind {
+        match self {
+            Self::PoeProduced { .. } => EventKind::PoeProduced,
+            Self::BatchCommitted { .. } => EventKind::BatchCommitted,
+            Self::AttestationRenewed { .. } => EventKind::AttestationRenewed,
+            Self::JobFailed { .. } => EventKind::JobFailed,
+        }
+    }
+}
+
+/// Delivers a notification to the subscriber, returns false once the
+/// connection is closed.
+pub type EventSink = Box<dyn Fn(String) -> bool + Send>;
+
+struct Subscription {
+    kind: EventKind,
+    sink: EventSink,
+}
+
+#[derive(Default)]
+struct EventBusState {
+    next_id: u64,
+    subs: BTreeMap<u64, Subscription>,
+}
+
+/// Fans out the prover events to the subscriptions as the
+/// `eth_subscription` notifications.
+///
+/// The subscription is removed by `unsubscribe` or once its sink reports
+/// the connection is closed.
+#[derive(Default)]
+pub struct EventBus {
+    state: Mutex<EventBusState>,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn publish(&self, event: ProverEvent) {
+        let mut state = self.state.lock().unwrap();
+        let kind = event.kind();
+        state.subs.retain(|id, sub| {
+            if sub.kind != kind {
+                return true;
+            }
+            (sub.sink)(notification(*id, &event))
+        });
+    }
+
+    pub fn subscribe(&self, kind: EventKind, sink: EventSink) -> u64 {
+        let mut state = self.state.lock().unwrap();
+        let id = state.next_id;
+        state.next_id += 1;
+        state.subs.insert(id, Subscription { kind, sink });
+        id
+    }
+
+    pub fn unsubscribe(&self, id: u64) -> bool {
+        self.state.lock().unwrap().subs.remove(&id).is_some()
+    }
+}
+
+/// Encodes the event as the `eth_subscription` notification of the
+/// subscription.
+pub fn notification(id: u64, event: &ProverEvent) -> String {
+    serde_json::json!({
+        "jsonrpc": "2.0",
+        "method": "eth_subscription",
+        "params": {
+            "subscription": SU64::from(id),
+            "result": event,
+        },
+    })
+    .to_string()
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use super::*;
+    use std::sync::Arc;
+
+    fn committed(start: u64) -> ProverEvent {
+        ProverEvent::BatchCommitted {
+            start,
+            end: start + 10,
+            batch_hash: SH256::default(),
+        }
+    }
+
+    /// Collects the notifications until `closed` is set.
+    fn sink(received: &Arc<Mutex<Vec<serde_json::Value>>>, closed: &Arc<Mutex<bool>>) -> EventSink {
+        let received = received.clone();
+        let closed = closed.clone();
+        Box::new(move |msg| {
+            if *closed.lock().unwrap() {
+                return false;
+            }
+            received
+                .lock()
+                .unwrap()
+                .push(serde_json::from_str(&msg).unwrap());
+            true
+        })
+    }
+
+    /// Subscribes to the kind, returns the received notifications.
+    pub(crate) fn collect(bus: &EventBus, kind: EventKind) -> Arc<Mutex<Vec<serde_json::Value>>> {
+        let received = Arc::new(Mutex::new(Vec::new()));
+        bus.subscribe(kind, sink(&received, &Arc::new(Mutex::new(false))));
+        received
+    }
+
+    fn starts(received: &Arc<Mutex<Vec<serde_json::Value>>>) -> Vec<u64> {
+        received
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|msg| msg["params"]["result"]["start"].as_u64().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_notification() {
+        let msg: serde_json::Value =
+            serde_json::from_str(&notification(26, &committed(100))).unwrap();
+        assert_eq!(msg["jsonrpc"], "2.0");
+        assert_eq!(msg["method"], "eth_subscription");
+        assert_eq!(msg["params"]["subscription"], "0x1a");
+        let result = &msg["params"]["result"];
+        assert_eq!(result["type"], "batchCommitted");
+        assert_eq!(
+            (result["start"].as_u64(), result["end"].as_u64()),
+            (Some(100), Some(110))
+        );
+    }
+
+    #[test]
+    fn test_fan_out() {
+        let bus = EventBus::new();
+        let closed = Arc::new(Mutex::new(false));
+        let (first, second, renewed) = (
+            Arc::new(Mutex::new(Vec::new())),
+            Arc::new(Mutex::new(Vec::new())),
+            Arc::new(Mutex::new(Vec::new())),
+        );
+        let first_id = bus.subscribe(EventKind::BatchCommitted, sink(&first, &closed));
+        let second_id = bus.subscribe(EventKind::BatchCommitted, sink(&second, &closed));
+        bus.subscribe(EventKind::AttestationRenewed, sink(&renewed, &closed));
+        bus.publish(committed(100));
+        bus.publish(ProverEvent::AttestationRenewed {
+            prover: SH160::default(),
+        });
+        bus.publish(committed(200));
+
+        assert_eq!(starts(&first), vec![100, 200]);
+        assert_eq!(starts(&second), vec![100, 200]);
+        let subscription = SU64::from(second_id);
+        assert!(second
+            .lock()
+            .unwrap()
+            .iter()
+            .all(|msg| msg["params"]["subscription"] == serde_json::json!(subscription)));
+        let renewed = renewed.lock().unwrap();
+        assert_eq!(renewed.len(), 1);
+        assert_eq!(renewed[0]["params"]["result"]["type"], "attestationRenewed");
+
+        assert!(bus.unsubscribe(first_id));
+        assert!(!bus.unsubscribe(first_id));
+        bus.publish(committed(300));
+        assert_eq!(starts(&first), vec![100, 200]);
+        assert_eq!(starts(&second), vec![100, 200, 300]);
+    }
+
+    #[test]
+    fn test_closed_sink() {
+        let bus = EventBus::new();
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let closed = Arc::new(Mutex::new(false));
+        let id = bus.subscribe(EventKind::BatchCommitted, sink(&received, &closed));
+        bus.publish(committed(100));
+        *closed.lock().unwrap() = true;
+        bus.publish(committed(200));
+        assert_eq!(starts(&received), vec![100]);
+        // the subscription of the closed connection is removed
+        assert!(!bus.unsubscribe(id));
+    }
+}
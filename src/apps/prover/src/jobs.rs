@@ -0,0 +1,443 @@
+use std::prelude::v1::*;
+
+use base::format::debug;
+use evm_executor::Poe;
+use linea::{read_file_opt, write_file_atomic, BatchTask};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+use crate::{EventBus, JobsConfig, ProverEvent};
+
+/// The lifecycle of a batch job:
+///
+/// Discovered -> Fetching -> Executing -> Signed -> Submitted -> Confirmed
+///
+/// A failed step goes back to `Discovered` (or `Signed` if the Poe is still
+/// valid) after the backoff, and ends in `Failed` when the attempts run out
+/// or the error can't be fixed by a retry.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub enum JobState {
+    Discovered,
+    Fetching,
+    Executing,
+    Signed,
+    Submitted,
+    Confirmed,
+    Failed,
+}
+
+impl JobState {
+    pub fn is_final(&self) -> bool {
+        matches!(self, Self::Confirmed | Self::Failed)
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Job {
+    pub id: u64,
+    pub task: BatchTask,
+    pub state: JobState,
+    pub attempts: u32,
+    /// The unix timestamp before which the job won't be picked up.
+    pub next_retry: u64,
+    pub poe: Option<Poe>,
+    pub error: Option<String>,
+}
+
+#[derive(Default, Deserialize, Serialize)]
+struct JobQueueState {
+    next_id: u64,
+    jobs: BTreeMap<u64, Job>,
+}
+
+/// The batch jobs discovered from the rollup contract, persisted to the file
+/// on every state change.
+pub struct JobQueue {
+    cfg: JobsConfig,
+    events: Arc<EventBus>,
+    state: Mutex<JobQueueState>,
+}
+
+impl JobQueue {
+    /// Only starts empty if there's no file, a file which can't be loaded is
+    /// an error, or the jobs in it are lost.
+    pub fn new(cfg: JobsConfig, events: Arc<EventBus>) -> Result<Self, String> {
+        let mut state = Self::load(&cfg.path)?.unwrap_or_default();
+        for job in state.jobs.values_mut() {
+            // the work in progress is lost with the process
+            match job.state {
+                JobState::Fetching | JobState::Executing => job.state = JobState::Discovered,
+                _ => {}
+            }
+        }
+        let pending = state
+            .jobs
+            .values()
+            .filter(|job| !job.state.is_final())
+            .count();
+        glog::info!("jobs[{}] loaded, pending: {}", cfg.path, pending);
+        Ok(Self {
+            cfg,
+            events,
+            state: Mutex::new(state),
+        })
+    }
+
+    /// Checks the file can be loaded, before the queue is created.
+    pub fn check(path: &str) -> Result<(), String> {
+        Self::load(path).map(|_| ())
+    }
+
+    fn load(path: &str) -> Result<Option<JobQueueState>, String> {
+        let data = match read_file_opt(path)? {
+            Some(data) => data,
+            None => return Ok(None),
+        };
+        let state = serde_json::from_slice(&data)
+            .map_err(|err| format!("jobs[{}] is corrupted: {}", path, err))?;
+        Ok(Some(state))
+    }
+
+    fn save(&self, state: &JobQueueState) {
+        let result = serde_json::to_vec(state)
+            .map_err(debug)
+            .and_then(|data| write_file_atomic(&self.cfg.path, &data));
+        if let Err(err) = result {
+            glog::error!("save jobs[{}] fail: {}", self.cfg.path, err);
+        }
+    }
+
+    fn prune(&self, state: &mut JobQueueState) {
+        let finished: Vec<u64> = state
+            .jobs
+            .values()
+            .filter(|job| job.state.is_final())
+            .map(|job| job.id)
+            .collect();
+        if finished.len() > self.cfg.keep_finished {
+            for id in &finished[..finished.len() - self.cfg.keep_finished] {
+                state.jobs.remove(id);
+            }
+        }
+    }
+
+    /// Adds the task unless the same batch is already queued.
+    pub fn discover(&self, task: BatchTask) -> Option<u64> {
+        let mut state = self.state.lock().unwrap();
+        let exists = state
+            .jobs
+            .values()
+            .any(|job| job.task.data_hash == task.data_hash && job.state != JobState::Failed);
+        if exists {
+            return None;
+        }
+        let id = state.next_id;
+        state.next_id += 1;
+        glog::info!("job[{}] discovered: batch{:?}", id, task.blocks);
+        state.jobs.insert(
+            id,
+            Job {
+                id,
+                task,
+                state: JobState::Discovered,
+                attempts: 0,
+                next_retry: 0,
+                poe: None,
+                error: None,
+            },
+        );
+        self.prune(&mut state);
+        self.save(&state);
+        Some(id)
+    }
+
+    /// The task is orphaned by an L1 reorg, drops the job unless it's
+    /// already confirmed.
+    pub fn reorg(&self, task: &BatchTask) {
+        let mut state = self.state.lock().unwrap();
+        for job in state.jobs.values_mut() {
+            if job.task.data_hash == task.data_hash && !job.state.is_final() {
+                glog::warn!("job[{}] reorged: batch{:?}", job.id, job.task.blocks);
+                job.state = JobState::Failed;
+                job.error = Some("reorged".into());
+            }
+        }
+        self.save(&state);
+    }
+
+    /// The oldest unfinished job which is ready to run, the batches are
+    /// committed in order so the later ones wait.
+    pub fn next(&self, now: u64) -> Option<Job> {
+        let state = self.state.lock().unwrap();
+        let job = state.jobs.values().find(|job| !job.state.is_final())?;
+        if job.next_retry > now {
+            return None;
+        }
+        Some(job.clone())
+    }
+
+    pub fn get(&self, id: u64) -> Option<Job> {
+        self.state.lock().unwrap().jobs.get(&id).cloned()
+    }
+
+    pub fn list(&self) -> Vec<Job> {
+        self.state.lock().unwrap().jobs.values().cloned().collect()
+    }
+
+    pub fn transit(&self, id: u64, to: JobState, poe: Option<Poe>) {
+        let mut state = self.state.lock().unwrap();
+        let job = match state.jobs.get_mut(&id) {
+            Some(job) => job,
+            None => return,
+        };
+        glog::info!("job[{}] {:?} -> {:?}", id, job.state, to);
+        job.state = to;
+        if poe.is_some() {
+            job.poe = poe;
+        }
+        if to == JobState::Confirmed {
+            job.error = None;
+        }
+        self.save(&state);
+    }
+
+    /// Schedules the retry from `resume` with the exponential backoff, or
+    /// fails the job if the attempts run out. Resuming from `Failed` fails
+    /// the job at once.
+    pub fn fail(&self, id: u64, resume: JobState, err: String, now: u64) {
+        let mut state = self.state.lock().unwrap();
+        let job = match state.jobs.get_mut(&id) {
+            Some(job) => job,
+            None => return,
+        };
+        job.attempts += 1;
+        job.error = Some(err.clone());
+        if job.attempts >= self.cfg.max_attempts || resume == JobState::Failed {
+            glog::error!(
+                "job[{}] failed after {} attempts: {}",
+                id,
+                job.attempts,
+                err
+            );
+            job.state = JobState::Failed;
+            self.events.publish(ProverEvent::JobFailed {
+                job: "batch".into(),
+                id,
+                start: *job.task.blocks.first().unwrap(),
+                end: *job.task.blocks.last().unwrap(),
+                error: err,
+            });
+        } else {
+            let backoff = self.cfg.retry_backoff_secs << (job.attempts - 1).min(6);
+            glog::warn!(
+                "job[{}] attempt[{}] failed, retry in {}s: {}",
+                id,
+                job.attempts,
+                backoff,
+                err
+            );
+            job.state = resume;
+            if resume == JobState::Discovered {
+                job.poe = None;
+            }
+            job.next_retry = now + backoff;
+        }
+        self.save(&state);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use eth_types::SH256;
+
+    use crate::events::test::collect;
+    use crate::EventKind;
+
+    struct TestQueue {
+        dir: std::path::PathBuf,
+        cfg: JobsConfig,
+        events: Arc<EventBus>,
+    }
+
+    impl TestQueue {
+        fn new(name: &str) -> Self {
+            let dir =
+                std::env::temp_dir().join(format!("prover-jobs-{}-{}", name, std::process::id()));
+            std::fs::create_dir_all(&dir).unwrap();
+            let cfg = JobsConfig {
+                path: dir.join("batch-jobs.json").to_str().unwrap().into(),
+                max_attempts: 3,
+                retry_backoff_secs: 10,
+                keep_finished: 2,
+            };
+            let events = Arc::new(EventBus::new());
+            Self { dir, cfg, events }
+        }
+
+        fn open(&self) -> JobQueue {
+            JobQueue::new(self.cfg.clone(), self.events.clone()).unwrap()
+        }
+    }
+
+    impl Drop for TestQueue {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    fn task(n: u64) -> BatchTask {
+        BatchTask {
+            data_hash: SH256::from([n as u8; 32]),
+            blocks: vec![n * 10, n * 10 + 1],
+            blob: None,
+            calldata: None,
+        }
+    }
+
+    fn poe(n: u64) -> Poe {
+        Poe::single_block(
+            SH256::from([n as u8; 32]),
+            SH256::default(),
+            SH256::from([0xff; 32]),
+            SH256::default(),
+        )
+    }
+
+    #[test]
+    fn test_discover() {
+        let test = TestQueue::new("discover");
+        let jobs = test.open();
+        assert_eq!(jobs.discover(task(1)), Some(0));
+        assert_eq!(jobs.discover(task(2)), Some(1));
+        // the same batch is emitted again after a restart
+        assert_eq!(jobs.discover(task(1)), None);
+
+        let job = jobs.next(0).unwrap();
+        assert_eq!(
+            (job.id, job.state, job.attempts),
+            (0, JobState::Discovered, 0)
+        );
+        assert_eq!(jobs.list().len(), 2);
+
+        // a failed batch can be discovered again
+        jobs.fail(0, JobState::Failed, "bad".into(), 0);
+        assert_eq!(jobs.discover(task(1)), Some(2));
+    }
+
+    #[test]
+    fn test_reorg() {
+        let test = TestQueue::new("reorg");
+        let jobs = test.open();
+        jobs.discover(task(1));
+        jobs.discover(task(2));
+        jobs.transit(0, JobState::Confirmed, None);
+
+        jobs.reorg(&task(1));
+        jobs.reorg(&task(2));
+        // the confirmed job is on the verifier already
+        assert_eq!(jobs.get(0).unwrap().state, JobState::Confirmed);
+        let job = jobs.get(1).unwrap();
+        assert_eq!(job.state, JobState::Failed);
+        assert_eq!(job.error.as_deref(), Some("reorged"));
+        assert!(jobs.next(0).is_none());
+    }
+
+    #[test]
+    fn test_backoff() {
+        let test = TestQueue::new("backoff");
+        let failed = collect(&test.events, EventKind::JobFailed);
+        let jobs = test.open();
+        jobs.discover(task(1));
+
+        jobs.transit(0, JobState::Signed, Some(poe(1)));
+        jobs.fail(0, JobState::Signed, "rpc".into(), 1000);
+        let job = jobs.get(0).unwrap();
+        assert_eq!(
+            (job.state, job.attempts, job.next_retry),
+            (JobState::Signed, 1, 1010)
+        );
+        // the Poe is reused for the transient errors
+        assert!(job.poe.is_some());
+        assert!(jobs.next(1009).is_none());
+        assert_eq!(jobs.next(1010).unwrap().id, 0);
+
+        // the Poe is generated again from `Discovered`
+        jobs.fail(0, JobState::Discovered, "state root".into(), 1010);
+        let job = jobs.get(0).unwrap();
+        assert_eq!(
+            (job.state, job.attempts, job.next_retry),
+            (JobState::Discovered, 2, 1030)
+        );
+        assert!(job.poe.is_none());
+        assert_eq!(failed.lock().unwrap().len(), 0);
+
+        jobs.fail(0, JobState::Discovered, "state root".into(), 1030);
+        let job = jobs.get(0).unwrap();
+        assert_eq!((job.state, job.attempts), (JobState::Failed, 3));
+        assert_eq!(job.error.as_deref(), Some("state root"));
+        assert!(jobs.next(u64::MAX).is_none());
+        let failed = failed.lock().unwrap();
+        assert_eq!(failed.len(), 1);
+        let event = &failed[0]["params"]["result"];
+        assert_eq!(event["type"], "jobFailed");
+        assert_eq!(
+            (
+                event["id"].as_u64(),
+                event["start"].as_u64(),
+                event["end"].as_u64()
+            ),
+            (Some(0), Some(10), Some(11))
+        );
+    }
+
+    #[test]
+    fn test_fail_terminal() {
+        let test = TestQueue::new("terminal");
+        let jobs = test.open();
+        jobs.discover(task(1));
+        jobs.fail(0, JobState::Failed, "overflow".into(), 0);
+        let job = jobs.get(0).unwrap();
+        assert_eq!((job.state, job.attempts), (JobState::Failed, 1));
+    }
+
+    #[test]
+    fn test_prune() {
+        let test = TestQueue::new("prune");
+        let jobs = test.open();
+        for n in 0..4 {
+            jobs.discover(task(n));
+            jobs.transit(n, JobState::Confirmed, None);
+        }
+        jobs.discover(task(4));
+        let ids: Vec<u64> = jobs.list().iter().map(|job| job.id).collect();
+        assert_eq!(ids, vec![2, 3, 4]);
+    }
+
+    #[test]
+    fn test_reload() {
+        let test = TestQueue::new("reload");
+        {
+            let jobs = test.open();
+            jobs.discover(task(1));
+            jobs.discover(task(2));
+            jobs.transit(0, JobState::Signed, Some(poe(1)));
+            jobs.transit(0, JobState::Submitted, None);
+            jobs.transit(1, JobState::Executing, None);
+        }
+
+        let jobs = test.open();
+        let job = jobs.get(0).unwrap();
+        assert_eq!(job.state, JobState::Submitted);
+        assert_eq!(job.poe.unwrap().new_state_root, SH256::from([0xff; 32]));
+        // the work in progress starts over
+        assert_eq!(jobs.get(1).unwrap().state, JobState::Discovered);
+        assert_eq!(jobs.discover(task(3)), Some(2));
+
+        std::fs::write(&test.cfg.path, b"{\"next_id\":").unwrap();
+        let err = JobQueue::check(&test.cfg.path).unwrap_err();
+        assert!(err.contains("corrupted"), "{}", err);
+        assert!(JobQueue::new(test.cfg.clone(), test.events.clone()).is_err());
+    }
+}
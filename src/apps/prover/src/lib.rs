@@ -7,4 +7,24 @@ extern crate sgxlib as std;
 mod apps;
 pub use crate::apps::*;
 mod types;
-pub use types::*;
\ No newline at end of file
+pub use types::*;
+mod admin;
+pub use admin::*;
+mod jobs;
+pub use jobs::*;
+mod prove_jobs;
+pub use prove_jobs::*;
+mod events;
+pub use events::*;
+mod status;
+pub use status::*;
+mod metrics;
+pub use metrics::*;
+mod control;
+pub use control::*;
+mod auth;
+pub use auth::*;
+mod cli;
+pub use cli::*;
+mod config;
+pub use config::*;
@@ -0,0 +1,302 @@
+use std::prelude::v1::*;
+
+use base::trace::Alive;
+use core::time::Duration;
+use std::collections::BTreeMap;
+use std::io::{ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::Mutex;
+
+const SECONDS_BUCKETS: &[f64] = &[0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0];
+const TXS_BUCKETS: &[f64] = &[0.0, 1.0, 5.0, 10.0, 25.0, 50.0, 100.0, 250.0, 500.0];
+const GAS_BUCKETS: &[f64] = &[1e5, 2e5, 5e5, 1e6, 2e6, 5e6, 1e7];
+const CONFIRM_BUCKETS: &[f64] = &[1.0, 5.0, 10.0, 15.0, 30.0, 60.0, 120.0, 300.0, 600.0];
+
+fn format_labels(labels: &[(&str, &str)]) -> String {
+    let labels: Vec<String> = labels
+        .iter()
+        .map(|(k, v)| format!("{}=\"{}\"", k, v.replace('\\', "\\\\").replace('"', "\\\"")))
+        .collect();
+    labels.join(",")
+}
+
+fn series_name(name: &str, labels: &str, extra: Option<String>) -> String {
+    let labels = match (labels, extra) {
+        ("", None) => return name.into(),
+        ("", Some(extra)) => extra,
+        (labels, None) => labels.into(),
+        (labels, Some(extra)) => format!("{},{}", labels, extra),
+    };
+    format!("{}{{{}}}", name, labels)
+}
+
+pub struct Counter {
+    name: &'static str,
+    help: &'static str,
+    values: Mutex<BTreeMap<String, u64>>,
+}
+
+impl Counter {
+    pub fn new(name: &'static str, help: &'static str) -> Self {
+        Self {
+            name,
+            help,
+            values: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn inc(&self, labels: &[(&str, &str)]) {
+        let mut values = self.values.lock().unwrap();
+        *values.entry(format_labels(labels)).or_default() += 1;
+    }
+
+    fn render(&self, out: &mut String) {
+        out.push_str(&format!("# HELP {} {}\n", self.name, self.help));
+        out.push_str(&format!("# TYPE {} counter\n", self.name));
+        for (labels, value) in self.values.lock().unwrap().iter() {
+            out.push_str(&format!(
+                "{} {}\n",
+                series_name(self.name, labels, None),
+                value
+            ));
+        }
+    }
+}
+
+#[derive(Default)]
+struct HistogramSeries {
+    /// Not cumulative, summed up on rendering.
+    buckets: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+pub struct Histogram {
+    name: &'static str,
+    help: &'static str,
+    bounds: &'static [f64],
+    series: Mutex<BTreeMap<String, HistogramSeries>>,
+}
+
+impl Histogram {
+    pub fn new(name: &'static str, help: &'static str, bounds: &'static [f64]) -> Self {
+        Self {
+            name,
+            help,
+            bounds,
+            series: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn observe(&self, labels: &[(&str, &str)], value: f64) {
+        let mut series = self.series.lock().unwrap();
+        let series = series.entry(format_labels(labels)).or_default();
+        if series.buckets.len() == 0 {
+            series.buckets = vec![0; self.bounds.len()];
+        }
+        if let Some(idx) = self.bounds.iter().position(|bound| value <= *bound) {
+            series.buckets[idx] += 1;
+        }
+        series.sum += value;
+        series.count += 1;
+    }
+
+    pub fn observe_duration(&self, labels: &[(&str, &str)], dur: Duration) {
+        self.observe(labels, dur.as_secs_f64())
+    }
+
+    fn render(&self, out: &mut String) {
+        out.push_str(&format!("# HELP {} {}\n", self.name, self.help));
+        out.push_str(&format!("# TYPE {} histogram\n", self.name));
+        let bucket_name = format!("{}_bucket", self.name);
+        for (labels, series) in self.series.lock().unwrap().iter() {
+            let mut acc = 0;
+            for (bound, count) in self.bounds.iter().zip(&series.buckets) {
+                acc += count;
+                let le = Some(format!("le=\"{}\"", bound));
+                out.push_str(&format!(
+                    "{} {}\n",
+                    series_name(&bucket_name, labels, le),
+                    acc
+                ));
+            }
+            let le = Some("le=\"+Inf\"".into());
+            out.push_str(&format!(
+                "{} {}\n",
+                series_name(&bucket_name, labels, le),
+                series.count
+            ));
+            let sum_name = format!("{}_sum", self.name);
+            out.push_str(&format!(
+                "{} {}\n",
+                series_name(&sum_name, labels, None),
+                series.sum
+            ));
+            let count_name = format!("{}_count", self.name);
+            out.push_str(&format!(
+                "{} {}\n",
+                series_name(&count_name, labels, None),
+                series.count
+            ));
+        }
+    }
+}
+
+/// The prover metrics, exported in the Prometheus text format.
+pub struct Metrics {
+    pub pob_fetch_seconds: Histogram,
+    pub block_execution_seconds: Histogram,
+    pub block_txs: Histogram,
+    /// Labeled by the shomei method.
+    pub shomei_request_seconds: Histogram,
+    pub poe_sign_seconds: Histogram,
+    pub commit_batch_gas_used: Histogram,
+    /// From sending the `commitBatch` tx to the receipt.
+    pub commit_batch_confirm_seconds: Histogram,
+    pub attestation_renewals: Counter,
+    /// Labeled by the error kind.
+    pub failures: Counter,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self {
+            pob_fetch_seconds: Histogram::new(
+                "prover_pob_fetch_seconds",
+                "The time fetching the proof of block.",
+                SECONDS_BUCKETS,
+            ),
+            block_execution_seconds: Histogram::new(
+                "prover_block_execution_seconds",
+                "The time executing a block.",
+                SECONDS_BUCKETS,
+            ),
+            block_txs: Histogram::new(
+                "prover_block_txs",
+                "The number of txs in the executed block.",
+                TXS_BUCKETS,
+            ),
+            shomei_request_seconds: Histogram::new(
+                "prover_shomei_request_seconds",
+                "The latency of the shomei requests.",
+                SECONDS_BUCKETS,
+            ),
+            poe_sign_seconds: Histogram::new(
+                "prover_poe_sign_seconds",
+                "The time signing the Poe.",
+                SECONDS_BUCKETS,
+            ),
+            commit_batch_gas_used: Histogram::new(
+                "prover_commit_batch_gas_used",
+                "The gas used by the commitBatch tx.",
+                GAS_BUCKETS,
+            ),
+            commit_batch_confirm_seconds: Histogram::new(
+                "prover_commit_batch_confirm_seconds",
+                "The time from sending the commitBatch tx to its confirmation.",
+                CONFIRM_BUCKETS,
+            ),
+            attestation_renewals: Counter::new(
+                "prover_attestation_renewals_total",
+                "The number of times the prover key is attested.",
+            ),
+            failures: Counter::new("prover_failures_total", "The failures by the error kind."),
+        }
+    }
+
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        self.pob_fetch_seconds.render(&mut out);
+        self.block_execution_seconds.render(&mut out);
+        self.block_txs.render(&mut out);
+        self.shomei_request_seconds.render(&mut out);
+        self.poe_sign_seconds.render(&mut out);
+        self.commit_batch_gas_used.render(&mut out);
+        self.commit_batch_confirm_seconds.render(&mut out);
+        self.attestation_renewals.render(&mut out);
+        self.failures.render(&mut out);
+        out
+    }
+
+    /// Serves `GET /metrics` on the port until the `alive` is shut down.
+    pub fn serve(&self, alive: &Alive, port: u32) -> Result<(), String> {
+        let listener = TcpListener::bind(format!("0.0.0.0:{}", port))
+            .map_err(|err| format!("listen metrics port[{}] fail: {:?}", port, err))?;
+        listener
+            .set_nonblocking(true)
+            .map_err(|err| format!("set nonblocking fail: {:?}", err))?;
+        glog::info!("serving metrics on :{}", port);
+        while alive.is_alive() {
+            match listener.accept() {
+                Ok((stream, _)) => {
+                    if let Err(err) = self.handle(stream) {
+                        glog::warn!("metrics request fail: {}", err);
+                    }
+                }
+                Err(err) if err.kind() == ErrorKind::WouldBlock => {
+                    alive.sleep_ms(100);
+                }
+                Err(err) => glog::warn!("accept metrics conn fail: {:?}", err),
+            }
+        }
+        Ok(())
+    }
+
+    fn handle(&self, mut stream: TcpStream) -> Result<(), String> {
+        stream
+            .set_nonblocking(false)
+            .map_err(|err| format!("{:?}", err))?;
+        stream
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .map_err(|err| format!("{:?}", err))?;
+        // only the request line matters
+        let mut buf = [0_u8; 1024];
+        let n = stream.read(&mut buf).map_err(|err| format!("{:?}", err))?;
+        let request = String::from_utf8_lossy(&buf[..n]);
+        let (status, content_type, body) = if request.starts_with("GET /metrics ") {
+            ("200 OK", "text/plain; version=0.0.4", self.render())
+        } else {
+            ("404 Not Found", "text/plain", "not found\n".into())
+        };
+        let response = format!(
+            "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status,
+            content_type,
+            body.len(),
+            body
+        );
+        stream
+            .write_all(response.as_bytes())
+            .map_err(|err| format!("{:?}", err))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let metrics = Metrics::new();
+        metrics.block_txs.observe(&[], 3.0);
+        metrics.block_txs.observe(&[], 600.0);
+        metrics.failures.inc(&[("kind", "prove")]);
+        metrics.failures.inc(&[("kind", "prove")]);
+        metrics
+            .shomei_request_seconds
+            .observe(&[("method", "fetch_proof")], 0.2);
+
+        let out = metrics.render();
+        assert!(out.contains("# TYPE prover_block_txs histogram\n"));
+        assert!(out.contains("prover_block_txs_bucket{le=\"1\"} 0\n"));
+        assert!(out.contains("prover_block_txs_bucket{le=\"5\"} 1\n"));
+        assert!(out.contains("prover_block_txs_bucket{le=\"500\"} 1\n"));
+        assert!(out.contains("prover_block_txs_bucket{le=\"+Inf\"} 2\n"));
+        assert!(out.contains("prover_block_txs_sum 603\n"));
+        assert!(out.contains("prover_block_txs_count 2\n"));
+        assert!(out.contains("prover_failures_total{kind=\"prove\"} 2\n"));
+        assert!(out.contains(
+            "prover_shomei_request_seconds_bucket{method=\"fetch_proof\",le=\"0.25\"} 1\n"
+        ));
+    }
+}
@@ -0,0 +1,323 @@
+use std::prelude::v1::*;
+
+use base::trace::Alive;
+use evm_executor::Poe;
+use linea::BatchTask;
+use serde::Serialize;
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::{Arc, Mutex};
+
+use crate::{EventBus, ProverEvent};
+
+#[derive(Clone, Copy, Debug, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ProveJobStatus {
+    Queued,
+    Running,
+    Done,
+    Failed,
+    Cancelled,
+}
+
+impl ProveJobStatus {
+    pub fn is_final(&self) -> bool {
+        matches!(self, Self::Done | Self::Failed | Self::Cancelled)
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct ProveJobInfo {
+    pub id: u64,
+    pub start: u64,
+    pub end: u64,
+    pub status: ProveJobStatus,
+    pub error: Option<String>,
+    pub created_at: u64,
+    pub finished_at: Option<u64>,
+}
+
+struct ProveJob {
+    info: ProveJobInfo,
+    task: BatchTask,
+    /// Shut down to cancel the running job.
+    alive: Alive,
+    result: Option<Poe>,
+}
+
+#[derive(Default)]
+struct ProveJobsState {
+    next_id: u64,
+    jobs: BTreeMap<u64, ProveJob>,
+    queue: VecDeque<u64>,
+}
+
+/// The jobs submitted by `proveSubmit`, they're kept in memory and picked up
+/// by the prove workers.
+pub struct ProveJobs {
+    events: Arc<EventBus>,
+    max_queued: usize,
+    keep_finished: usize,
+    state: Mutex<ProveJobsState>,
+}
+
+impl ProveJobs {
+    pub fn new(max_queued: usize, keep_finished: usize, events: Arc<EventBus>) -> Self {
+        Self {
+            events,
+            max_queued,
+            keep_finished,
+            state: Mutex::new(ProveJobsState::default()),
+        }
+    }
+
+    pub fn submit(&self, task: BatchTask) -> Result<u64, String> {
+        let mut state = self.state.lock().unwrap();
+        if state.queue.len() >= self.max_queued {
+            return Err(format!("too many queued jobs: {}", state.queue.len()));
+        }
+        let id = state.next_id;
+        state.next_id += 1;
+        let info = ProveJobInfo {
+            id,
+            start: *task.blocks.first().unwrap(),
+            end: *task.blocks.last().unwrap(),
+            status: ProveJobStatus::Queued,
+            error: None,
+            created_at: base::time::now().as_secs(),
+            finished_at: None,
+        };
+        state.jobs.insert(
+            id,
+            ProveJob {
+                info,
+                task,
+                alive: Alive::new(),
+                result: None,
+            },
+        );
+        state.queue.push_back(id);
+        Ok(id)
+    }
+
+    pub fn status(&self, id: u64) -> Option<ProveJobInfo> {
+        let state = self.state.lock().unwrap();
+        state.jobs.get(&id).map(|job| job.info.clone())
+    }
+
+    pub fn list(&self) -> Vec<ProveJobInfo> {
+        let state = self.state.lock().unwrap();
+        state.jobs.values().map(|job| job.info.clone()).collect()
+    }
+
+    /// Returns the Poe if the job is done, or the status otherwise.
+    pub fn result(&self, id: u64) -> Option<Result<Poe, ProveJobInfo>> {
+        let state = self.state.lock().unwrap();
+        let job = state.jobs.get(&id)?;
+        Some(match &job.result {
+            Some(poe) => Ok(poe.clone()),
+            None => Err(job.info.clone()),
+        })
+    }
+
+    /// Returns false if the job is not found or already finished.
+    pub fn cancel(&self, id: u64) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let job = match state.jobs.get_mut(&id) {
+            Some(job) if !job.info.status.is_final() => job,
+            _ => return false,
+        };
+        job.alive.shutdown();
+        job.info.status = ProveJobStatus::Cancelled;
+        job.info.finished_at = Some(base::time::now().as_secs());
+        state.queue.retain(|queued| *queued != id);
+        glog::info!("prove job[{}] cancelled", id);
+        self.prune(&mut state);
+        true
+    }
+
+    /// Cancels all the unfinished jobs, called on shutdown.
+    pub fn cancel_all(&self) {
+        let ids: Vec<u64> = {
+            let state = self.state.lock().unwrap();
+            state.jobs.keys().cloned().collect()
+        };
+        for id in ids {
+            self.cancel(id);
+        }
+    }
+
+    /// Takes the next queued job, the job should be executed with the
+    /// returned `Alive`.
+    pub fn pop(&self) -> Option<(u64, BatchTask, Alive)> {
+        let mut state = self.state.lock().unwrap();
+        let id = state.queue.pop_front()?;
+        let job = state.jobs.get_mut(&id)?;
+        job.info.status = ProveJobStatus::Running;
+        Some((id, job.task.clone(), job.alive.clone()))
+    }
+
+    pub fn finish(&self, id: u64, result: Result<Poe, String>) {
+        let mut state = self.state.lock().unwrap();
+        let job = match state.jobs.get_mut(&id) {
+            Some(job) => job,
+            None => return,
+        };
+        if job.info.status == ProveJobStatus::Cancelled {
+            // the result of a cancelled job is incomplete
+            return;
+        }
+        job.info.finished_at = Some(base::time::now().as_secs());
+        match result {
+            Ok(poe) => {
+                job.info.status = ProveJobStatus::Done;
+                job.result = Some(poe);
+            }
+            Err(err) => {
+                glog::error!("prove job[{}] failed: {}", id, err);
+                job.info.status = ProveJobStatus::Failed;
+                job.info.error = Some(err.clone());
+                self.events.publish(ProverEvent::JobFailed {
+                    job: "prove".into(),
+                    id,
+                    start: job.info.start,
+                    end: job.info.end,
+                    error: err,
+                });
+            }
+        }
+        self.prune(&mut state);
+    }
+
+    fn prune(&self, state: &mut ProveJobsState) {
+        let finished: Vec<u64> = state
+            .jobs
+            .values()
+            .filter(|job| job.info.status.is_final())
+            .map(|job| job.info.id)
+            .collect();
+        if finished.len() > self.keep_finished {
+            for id in &finished[..finished.len() - self.keep_finished] {
+                state.jobs.remove(id);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use eth_types::SH256;
+
+    use crate::events::test::collect;
+    use crate::EventKind;
+
+    fn task(start: u64) -> BatchTask {
+        BatchTask {
+            data_hash: SH256::default(),
+            blocks: (start..start + 3).collect(),
+            blob: None,
+            calldata: None,
+        }
+    }
+
+    fn poe() -> Poe {
+        Poe::single_block(
+            SH256::from([1; 32]),
+            SH256::default(),
+            SH256::from([0xff; 32]),
+            SH256::default(),
+        )
+    }
+
+    fn new_jobs(max_queued: usize, keep_finished: usize) -> (ProveJobs, Arc<EventBus>) {
+        let events = Arc::new(EventBus::new());
+        (
+            ProveJobs::new(max_queued, keep_finished, events.clone()),
+            events,
+        )
+    }
+
+    #[test]
+    fn test_submit() {
+        let (jobs, events) = new_jobs(2, 10);
+        let failed = collect(&events, EventKind::JobFailed);
+        assert_eq!(jobs.submit(task(100)), Ok(0));
+        assert_eq!(jobs.submit(task(200)), Ok(1));
+        let err = jobs.submit(task(300)).unwrap_err();
+        assert!(err.contains("too many queued jobs"), "{}", err);
+
+        let info = jobs.status(0).unwrap();
+        assert_eq!((info.start, info.end), (100, 102));
+        assert_eq!(info.status, ProveJobStatus::Queued);
+        assert!(matches!(jobs.result(0), Some(Err(_))));
+        assert!(jobs.result(5).is_none());
+
+        // the running jobs don't count
+        let (id, popped, alive) = jobs.pop().unwrap();
+        assert_eq!((id, popped.blocks[0]), (0, 100));
+        assert!(alive.is_alive());
+        assert_eq!(jobs.status(0).unwrap().status, ProveJobStatus::Running);
+        assert_eq!(jobs.submit(task(300)), Ok(2));
+
+        jobs.finish(0, Ok(poe()));
+        let info = jobs.status(0).unwrap();
+        assert_eq!(info.status, ProveJobStatus::Done);
+        assert!(info.finished_at.is_some());
+        assert_eq!(
+            jobs.result(0).unwrap().unwrap().new_state_root,
+            SH256::from([0xff; 32])
+        );
+
+        assert_eq!(jobs.pop().unwrap().0, 1);
+        jobs.finish(1, Err("bad block".into()));
+        let info = jobs.status(1).unwrap();
+        assert_eq!(info.status, ProveJobStatus::Failed);
+        assert_eq!(info.error.as_deref(), Some("bad block"));
+        assert_eq!(failed.lock().unwrap().len(), 1);
+
+        assert_eq!(jobs.pop().unwrap().0, 2);
+        assert!(jobs.pop().is_none());
+    }
+
+    #[test]
+    fn test_cancel() {
+        let (jobs, _) = new_jobs(10, 10);
+        jobs.submit(task(100)).unwrap();
+        jobs.submit(task(200)).unwrap();
+        let (id, _, alive) = jobs.pop().unwrap();
+
+        // the running job is stopped by its `Alive`
+        assert!(jobs.cancel(id));
+        assert!(!alive.is_alive());
+        assert!(!jobs.cancel(id));
+        jobs.finish(id, Ok(poe()));
+        let info = jobs.status(id).unwrap();
+        assert_eq!(info.status, ProveJobStatus::Cancelled);
+        assert!(matches!(jobs.result(id), Some(Err(_))));
+
+        // the queued job is never picked up
+        assert!(jobs.cancel(1));
+        assert!(jobs.pop().is_none());
+        assert!(!jobs.cancel(5));
+    }
+
+    #[test]
+    fn test_prune() {
+        let (jobs, _) = new_jobs(10, 2);
+        for n in 0..3 {
+            jobs.submit(task(n * 100)).unwrap();
+        }
+        jobs.submit(task(300)).unwrap();
+        for _ in 0..2 {
+            let (id, _, _) = jobs.pop().unwrap();
+            jobs.finish(id, Ok(poe()));
+        }
+        // the cancelled jobs are pruned as well
+        jobs.cancel(2);
+        let ids: Vec<u64> = jobs.list().iter().map(|info| info.id).collect();
+        assert_eq!(ids, vec![1, 2, 3]);
+        jobs.cancel_all();
+        let ids: Vec<u64> = jobs.list().iter().map(|info| info.id).collect();
+        assert_eq!(ids, vec![2, 3]);
+    }
+}
@@ -0,0 +1,222 @@
+use std::prelude::v1::*;
+
+use eth_types::{SH160, SH256};
+use linea::{CheckpointStore, CommitBatchEvent, SubscriberCheckpoint};
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InFlightJob {
+    /// "batch" for the L1 jobs and "prove" for the `proveSubmit` jobs.
+    pub kind: String,
+    pub id: u64,
+    pub start: u64,
+    pub end: u64,
+    pub state: String,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProverStatus {
+    /// Whether the prover is able to sign the Poe.
+    pub ready: bool,
+    pub prover: SH160,
+    pub staging_prover: Option<SH160>,
+    /// The unix timestamp the attestation of `prover` expires, null if it's
+    /// not attested.
+    pub attested_until: Option<u64>,
+    pub l2_chain_id: Option<u64>,
+    /// The last L1 block scanned by the batch task subscriber.
+    pub last_l1_block: Option<u64>,
+    /// The last batch committed on the verifier, by any prover.
+    pub last_batch: Option<SH256>,
+    pub jobs: Vec<InFlightJob>,
+    pub mrenclave: Option<SH256>,
+}
+
+/// The progress of the batch task subscriber.
+#[derive(Default)]
+pub struct L1Progress(AtomicU64);
+
+impl L1Progress {
+    pub fn last_block(&self) -> Option<u64> {
+        match self.0.load(Ordering::SeqCst) {
+            0 => None,
+            n => Some(n),
+        }
+    }
+}
+
+/// Records the block number of the saved checkpoint.
+pub struct TrackedCheckpointStore<S: CheckpointStore> {
+    inner: S,
+    progress: Arc<L1Progress>,
+}
+
+impl<S: CheckpointStore> TrackedCheckpointStore<S> {
+    pub fn new(inner: S, progress: Arc<L1Progress>) -> Self {
+        Self { inner, progress }
+    }
+}
+
+impl<S: CheckpointStore> CheckpointStore for TrackedCheckpointStore<S> {
+    fn load(&self) -> Result<Option<SubscriberCheckpoint>, String> {
+        let cp = self.inner.load()?;
+        if let Some(cp) = &cp {
+            self.progress.0.store(cp.number, Ordering::SeqCst);
+        }
+        Ok(cp)
+    }
+
+    fn save(&self, cp: &SubscriberCheckpoint) -> Result<(), String> {
+        self.inner.save(cp)?;
+        self.progress.0.store(cp.number, Ordering::SeqCst);
+        Ok(())
+    }
+}
+
+/// Finds the last `CommitBatch` event of the verifier. The first lookup scans
+/// back from the head, the later ones only scan the new blocks.
+pub struct LastBatchTracker {
+    /// The blocks of each `eth_getLogs`.
+    window: u64,
+    /// How far back the first lookup scans.
+    max_scan: u64,
+    state: Mutex<Option<LastBatchState>>,
+}
+
+struct LastBatchState {
+    scanned: u64,
+    last: Option<CommitBatchEvent>,
+}
+
+impl LastBatchTracker {
+    pub fn new(window: u64, max_scan: u64) -> Self {
+        Self {
+            window,
+            max_scan,
+            state: Mutex::new(None),
+        }
+    }
+
+    /// `events` lists the `CommitBatch` events in the block range `from..=to`.
+    pub fn last_batch<F>(&self, head: u64, events: F) -> Result<Option<SH256>, String>
+    where
+        F: Fn(u64, u64) -> Result<Vec<CommitBatchEvent>, String>,
+    {
+        let mut state = self.state.lock().unwrap();
+        let floor = match &*state {
+            Some(state) if state.scanned >= head => {
+                return Ok(state.last.as_ref().map(|event| event.batch_hash))
+            }
+            Some(state) => state.scanned + 1,
+            None => head.saturating_sub(self.max_scan),
+        };
+        let mut found = None;
+        let mut to = head;
+        loop {
+            let from = to.saturating_sub(self.window - 1).max(floor);
+            if let Some(event) = events(from, to)?.pop() {
+                found = Some(event);
+                break;
+            }
+            if from == floor {
+                break;
+            }
+            to = from - 1;
+        }
+        let last = match (found, state.take()) {
+            (Some(event), _) => Some(event),
+            (None, prev) => prev.and_then(|prev| prev.last),
+        };
+        let batch = last.as_ref().map(|event| event.batch_hash);
+        *state = Some(LastBatchState {
+            scanned: head,
+            last,
+        });
+        Ok(batch)
+    }
+}
+
+/// The mrenclave of the running enclave, the simulated one with the
+/// `sim-attestation` feature.
+pub fn self_mrenclave() -> Option<SH256> {
+    #[cfg(feature = "tstd")]
+    {
+        let report = unsafe { &*sgxlib::sgx_types::sgx_self_report() };
+        return Some(report.body.mr_enclave.m.into());
+    }
+    #[cfg(all(not(feature = "tstd"), feature = "sim-attestation"))]
+    {
+        return Some(linea::sim_mrenclave().into());
+    }
+    #[allow(unreachable_code)]
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::cell::RefCell;
+
+    fn event(block: u64) -> CommitBatchEvent {
+        CommitBatchEvent {
+            batch_index: block,
+            batch_hash: SH256::from([block as u8; 32]),
+            prev_state_root: SH256::default(),
+            new_state_root: SH256::default(),
+            block_number: block,
+            tx_hash: SH256::default(),
+        }
+    }
+
+    #[test]
+    fn test_last_batch_tracker() {
+        let committed = RefCell::new(vec![event(120)]);
+        let ranges = RefCell::new(Vec::new());
+        let events = |from: u64, to: u64| -> Result<Vec<CommitBatchEvent>, String> {
+            ranges.borrow_mut().push((from, to));
+            let committed = committed.borrow();
+            Ok(committed
+                .iter()
+                .filter(|event| event.block_number >= from && event.block_number <= to)
+                .cloned()
+                .collect())
+        };
+
+        let tracker = LastBatchTracker::new(50, 1000);
+        // scans back from the head until an event is found
+        assert_eq!(
+            tracker.last_batch(200, &events),
+            Ok(Some(event(120).batch_hash))
+        );
+        assert_eq!(*ranges.borrow(), vec![(151, 200), (101, 150)]);
+
+        // only the new blocks are scanned
+        ranges.borrow_mut().clear();
+        assert_eq!(
+            tracker.last_batch(200, &events),
+            Ok(Some(event(120).batch_hash))
+        );
+        assert_eq!(
+            tracker.last_batch(230, &events),
+            Ok(Some(event(120).batch_hash))
+        );
+        assert_eq!(*ranges.borrow(), vec![(201, 230)]);
+
+        committed.borrow_mut().push(event(240));
+        committed.borrow_mut().push(event(245));
+        assert_eq!(
+            tracker.last_batch(250, &events),
+            Ok(Some(event(245).batch_hash))
+        );
+
+        // nothing committed within the scan
+        let tracker = LastBatchTracker::new(50, 80);
+        ranges.borrow_mut().clear();
+        assert_eq!(tracker.last_batch(100, &events), Ok(None));
+        assert_eq!(*ranges.borrow(), vec![(51, 100), (20, 50)]);
+    }
+}
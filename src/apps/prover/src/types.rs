@@ -2,26 +2,51 @@ use std::prelude::v1::*;
 
 use app::getargs::{Opt, Options};
 use crypto::Secp256k1PrivateKey;
-use eth_types::SH160;
+use eth_types::{SH160, SH256};
 use serde::Deserialize;
+use std::collections::BTreeMap;
 
 #[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct Config {
     pub server: ServerConfig,
     pub l2: String,
     pub rollup: RollupConfig,
     pub verifier: VerifierConfig,
     pub shomei: shomei::ShomeiConfig,
+    pub header_chain: Option<linea::HeaderChainConfig>,
+    pub message_service: Option<linea::MessageServiceConfig>,
+    #[serde(default)]
+    pub jobs: JobsConfig,
+    #[serde(default)]
+    pub execution: ExecutionConfig,
+    #[serde(default)]
+    pub retry: RetryConfig,
+    #[serde(default)]
+    pub storage: StorageConfig,
 }
 
 #[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct VerifierConfig {
     pub endpoint: String,
     pub contract: SH160,
     pub relay_account: Secp256k1PrivateKey,
+    /// Commits the zktrie root hashes instead of the MPT state roots.
+    #[serde(default)]
+    pub zktrie: bool,
+    /// Persists the prover keys to the path, they're sealed in the enclave.
+    #[serde(default)]
+    pub key_store: Option<String>,
+    /// The first batch committed to the verifier, its parent doesn't have to
+    /// be committed. Only needed if the verifier starts in the middle of the
+    /// chain.
+    #[serde(default)]
+    pub genesis_batch: Option<SH256>,
 }
 
 #[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct RollupConfig {
     pub endpoint: String,
     pub contract: SH160,
@@ -29,6 +54,9 @@ pub struct RollupConfig {
     pub wait_block: u64,
     #[serde(default = "default_max_block")]
     pub max_block: u64,
+    /// How many recent L1 blocks are kept to detect the reorg.
+    #[serde(default = "default_reorg_window")]
+    pub reorg_window: u64,
 }
 
 fn default_wait_block() -> u64 {
@@ -37,12 +65,177 @@ fn default_wait_block() -> u64 {
 fn default_max_block() -> u64 {
     10
 }
+fn default_reorg_window() -> u64 {
+    128
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ExecutionConfig {
+    /// How many blocks of a batch are executed concurrently, it can be
+    /// changed by `admin_setParallelism` at runtime.
+    #[serde(default = "default_parallelism")]
+    pub parallelism: usize,
+}
+
+impl Default for ExecutionConfig {
+    fn default() -> Self {
+        Self {
+            parallelism: default_parallelism(),
+        }
+    }
+}
+
+fn default_parallelism() -> usize {
+    8
+}
+
+/// The retries of the verifier transactions, the batch jobs have their own
+/// backoff in `JobsConfig`.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct RetryConfig {
+    /// The retries of a `commitBatch` which failed with a transient error.
+    #[serde(default = "default_submit_retries")]
+    pub submit_retries: u32,
+    #[serde(default = "default_submit_retry_interval_ms")]
+    pub submit_retry_interval_ms: u64,
+    /// The tx is resent if it's not confirmed in time.
+    #[serde(default = "default_resend_timeout_secs")]
+    pub resend_timeout_secs: u64,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            submit_retries: default_submit_retries(),
+            submit_retry_interval_ms: default_submit_retry_interval_ms(),
+            resend_timeout_secs: default_resend_timeout_secs(),
+        }
+    }
+}
+
+fn default_submit_retries() -> u32 {
+    3
+}
+fn default_submit_retry_interval_ms() -> u64 {
+    3000
+}
+fn default_resend_timeout_secs() -> u64 {
+    45
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct StorageConfig {
+    /// The progress of the batch task subscriber.
+    #[serde(default = "default_checkpoint_path")]
+    pub checkpoint: String,
+    /// The verified L2 header chain, used if `header_chain` is set.
+    #[serde(default = "default_header_chain_path")]
+    pub header_chain: String,
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        Self {
+            checkpoint: default_checkpoint_path(),
+            header_chain: default_header_chain_path(),
+        }
+    }
+}
+
+fn default_checkpoint_path() -> String {
+    "batch-task.json".into()
+}
+fn default_header_chain_path() -> String {
+    "header-chain.json".into()
+}
+
+/// The batch jobs discovered from the rollup contract.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct JobsConfig {
+    #[serde(default = "default_jobs_path")]
+    pub path: String,
+    /// The job fails after the attempts.
+    #[serde(default = "default_max_attempts")]
+    pub max_attempts: u32,
+    /// The backoff of the first retry, it doubles for each attempt.
+    #[serde(default = "default_retry_backoff_secs")]
+    pub retry_backoff_secs: u64,
+    /// How many confirmed or failed jobs are kept in the file.
+    #[serde(default = "default_keep_finished")]
+    pub keep_finished: usize,
+}
+
+impl Default for JobsConfig {
+    fn default() -> Self {
+        Self {
+            path: default_jobs_path(),
+            max_attempts: default_max_attempts(),
+            retry_backoff_secs: default_retry_backoff_secs(),
+            keep_finished: default_keep_finished(),
+        }
+    }
+}
+
+fn default_jobs_path() -> String {
+    "batch-jobs.json".into()
+}
+fn default_max_attempts() -> u32 {
+    5
+}
+fn default_retry_backoff_secs() -> u64 {
+    30
+}
+fn default_keep_finished() -> usize {
+    100
+}
 
 #[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
 pub struct ServerConfig {
     pub tls: String,
     pub body_limit: usize,
     pub workers: usize,
+    /// The threads executing the `proveSubmit` jobs.
+    #[serde(default = "default_prove_workers")]
+    pub prove_workers: usize,
+    /// `proveSubmit` is rejected if too many jobs are queued.
+    #[serde(default = "default_max_prove_jobs")]
+    pub max_prove_jobs: usize,
+    /// How many finished `proveSubmit` jobs are kept for `proveResult`.
+    #[serde(default = "default_keep_prove_jobs")]
+    pub keep_prove_jobs: usize,
+    /// Serves the Prometheus metrics on `/metrics` of the port.
+    #[serde(default)]
+    pub metrics_port: Option<u32>,
+    /// Enables the `admin_*` methods, they take the token as the first param.
+    #[serde(default)]
+    pub admin_token: Option<String>,
+    /// Authenticates all the methods if set.
+    #[serde(default)]
+    pub auth: Option<crate::AuthConfig>,
+    /// The requests per minute of the methods, e.g. `{"prove": 10}`.
+    #[serde(default)]
+    pub rate_limits: BTreeMap<String, u32>,
+    /// The max blocks of a prove request.
+    #[serde(default = "default_max_block_range")]
+    pub max_block_range: u64,
+}
+
+fn default_prove_workers() -> usize {
+    2
+}
+fn default_max_prove_jobs() -> usize {
+    16
+}
+fn default_keep_prove_jobs() -> usize {
+    100
+}
+fn default_max_block_range() -> u64 {
+    100
 }
 
 #[derive(Debug)]
@@ -50,6 +243,8 @@ pub struct Args {
     pub executable: String,
     pub port: u32,
     pub cfg: String,
+    /// The positional arguments, e.g. `admin change-owner 0x..`
+    pub command: Vec<String>,
 }
 
 impl Default for Args {
@@ -58,6 +253,7 @@ impl Default for Args {
             executable: "".into(),
             port: 18400,
             cfg: "config/prover.json".into(),
+            command: Vec::new(),
         }
     }
 }
@@ -81,6 +277,7 @@ impl Args {
                 }
             }
         }
+        out.command = opts.positionals().map(|n| n.to_string()).collect();
         out
     }
 }
@@ -0,0 +1,278 @@
+use std::prelude::v1::*;
+
+use crate::{fixed_bytes, x509_time, DerNode, TAG_BIT_STRING, TAG_INTEGER, TAG_OCTET_STRING, TAG_OID, TAG_SEQUENCE};
+
+// See `PEMCertChainLib.sol`.
+pub const PEM_HEADER: &str = "-----BEGIN CERTIFICATE-----";
+pub const PEM_FOOTER: &str = "-----END CERTIFICATE-----";
+
+pub const PCK_COMMON_NAME: &str = "Intel SGX PCK Certificate";
+pub const PCK_ISSUER_NAME: &str = "Intel SGX PCK Platform CA";
+
+const COMMON_NAME_OID: &[u8] = &[0x55, 0x04, 0x03];
+const SGX_EXTENSION_OID: &[u8] = &[0x2A, 0x86, 0x48, 0x86, 0xF8, 0x4D, 0x01, 0x0D, 0x01];
+const TCB_OID: &[u8] = &[0x2A, 0x86, 0x48, 0x86, 0xF8, 0x4D, 0x01, 0x0D, 0x01, 0x02];
+const PCESVN_OID: &[u8] = &[0x2A, 0x86, 0x48, 0x86, 0xF8, 0x4D, 0x01, 0x0D, 0x01, 0x02, 0x11];
+const PCEID_OID: &[u8] = &[0x2A, 0x86, 0x48, 0x86, 0xF8, 0x4D, 0x01, 0x0D, 0x01, 0x03];
+const FMSPC_OID: &[u8] = &[0x2A, 0x86, 0x48, 0x86, 0xF8, 0x4D, 0x01, 0x0D, 0x01, 0x04];
+
+pub const CPUSVN_LENGTH: usize = 16;
+
+const TAG_EXTENSIONS: u8 = 0xA3;
+
+/// The SGX extension of the PCK certificate.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PckInfo {
+    /// lower case hex, as the `fmspc` in tcbInfo.json
+    pub fmspc: String,
+    /// lower case hex, as the `pceId` in tcbInfo.json
+    pub pceid: String,
+    pub pcesvn: u16,
+    pub cpusvns: [u16; CPUSVN_LENGTH],
+}
+
+#[derive(Clone, Debug)]
+pub struct Certificate {
+    pub serial_number: Vec<u8>,
+    pub not_before: u64,
+    pub not_after: u64,
+    /// The DER encoded tbsCertificate, it's what the issuer signs.
+    pub tbs_certificate: Vec<u8>,
+    /// x || y of the P-256 public key.
+    pub pub_key: [u8; 64],
+    /// r || s of the issuer signature.
+    pub signature: [u8; 64],
+    pub pck: Option<PckInfo>,
+}
+
+impl Certificate {
+    pub fn decode(der: &[u8], is_pck: bool) -> Result<Self, String> {
+        let root = DerNode::parse_all(der)?.expect(TAG_SEQUENCE)?.children()?;
+        if root.len() != 3 {
+            return Err(format!("cert: expect 3 elements, got {}", root.len()));
+        }
+        let tbs_node = root[0].expect(TAG_SEQUENCE)?;
+        let tbs = tbs_node.children()?;
+        if tbs.len() < 7 {
+            return Err(format!("cert: tbsCertificate has {} elements", tbs.len()));
+        }
+        // version, serial, signature, issuer, validity, subject, spki, [extensions]
+        let serial_number = tbs[1].expect(TAG_INTEGER)?.content.to_vec();
+
+        let validity = tbs[4].expect(TAG_SEQUENCE)?.children()?;
+        if validity.len() != 2 {
+            return Err("cert: invalid validity".into());
+        }
+        let not_before = x509_time(&validity[0])?;
+        let not_after = x509_time(&validity[1])?;
+
+        let spki = tbs[6].expect(TAG_SEQUENCE)?.children()?;
+        let pub_key = match spki.get(1) {
+            Some(node) => node.bit_string()?,
+            None => return Err("cert: missing subject public key".into()),
+        };
+        // skip the uncompressed (0x04) prefix
+        if pub_key.len() != 65 || pub_key[0] != 0x04 {
+            return Err(format!("cert: unsupported public key: {} bytes", pub_key.len()));
+        }
+
+        let sig = DerNode::parse_all(root[2].bit_string()?)?
+            .expect(TAG_SEQUENCE)?
+            .children()?;
+        if sig.len() != 2 {
+            return Err("cert: invalid signature".into());
+        }
+        let mut signature = [0_u8; 64];
+        signature[..32].copy_from_slice(&fixed_bytes(sig[0].expect(TAG_INTEGER)?.content, 32)?);
+        signature[32..].copy_from_slice(&fixed_bytes(sig[1].expect(TAG_INTEGER)?.content, 32)?);
+
+        let pck = if is_pck {
+            let issuer = common_name(&tbs[3])?;
+            if issuer != PCK_ISSUER_NAME {
+                return Err(format!("cert: unexpected pck issuer: {:?}", issuer));
+            }
+            let subject = common_name(&tbs[5])?;
+            if subject != PCK_COMMON_NAME {
+                return Err(format!("cert: unexpected pck subject: {:?}", subject));
+            }
+            let extensions = match tbs.get(7) {
+                Some(node) => node.expect(TAG_EXTENSIONS)?,
+                None => return Err("cert: missing pck extensions".into()),
+            };
+            Some(PckInfo::find(&extensions)?)
+        } else {
+            None
+        };
+
+        let mut cert_pub_key = [0_u8; 64];
+        cert_pub_key.copy_from_slice(&pub_key[1..]);
+        Ok(Self {
+            serial_number,
+            not_before,
+            not_after,
+            tbs_certificate: tbs_node.raw.to_vec(),
+            pub_key: cert_pub_key,
+            signature,
+            pck,
+        })
+    }
+
+    pub fn is_pck(&self) -> bool {
+        self.pck.is_some()
+    }
+}
+
+fn common_name(name: &DerNode) -> Result<String, String> {
+    for rdn in name.expect(TAG_SEQUENCE)?.children()? {
+        for attr in rdn.children()? {
+            let attr = attr.children()?;
+            if attr.len() == 2 && attr[0].tag == TAG_OID && attr[0].content == COMMON_NAME_OID {
+                return Ok(String::from_utf8_lossy(attr[1].content).into());
+            }
+        }
+    }
+    Err("cert: common name not found".into())
+}
+
+impl PckInfo {
+    fn find(extensions: &DerNode) -> Result<Self, String> {
+        let extensions = match extensions.children()?.first() {
+            Some(node) => node.expect(TAG_SEQUENCE)?.children()?,
+            None => return Err("cert: empty extensions".into()),
+        };
+        for ext in extensions {
+            let ext = ext.children()?;
+            if ext.len() < 2 || ext[0].expect(TAG_OID)?.content != SGX_EXTENSION_OID {
+                continue;
+            }
+            let value = ext[ext.len() - 1].expect(TAG_OCTET_STRING)?;
+            return Self::decode_sgx_extension(value.content);
+        }
+        Err("cert: sgx extension not found".into())
+    }
+
+    fn decode_sgx_extension(data: &[u8]) -> Result<Self, String> {
+        let mut fmspc = None;
+        let mut pceid = None;
+        let mut tcb = None;
+        for item in DerNode::parse_all(data)?.expect(TAG_SEQUENCE)?.children()? {
+            let item = item.children()?;
+            if item.len() != 2 {
+                continue;
+            }
+            let oid = item[0].expect(TAG_OID)?.content;
+            if oid == TCB_OID {
+                tcb = Some(Self::decode_tcb(&item[1])?);
+            } else if oid == PCEID_OID {
+                pceid = Some(hex_string(item[1].expect(TAG_OCTET_STRING)?.content));
+            } else if oid == FMSPC_OID {
+                fmspc = Some(hex_string(item[1].expect(TAG_OCTET_STRING)?.content));
+            }
+        }
+        match (fmspc, pceid, tcb) {
+            (Some(fmspc), Some(pceid), Some((pcesvn, cpusvns))) => Ok(Self {
+                fmspc,
+                pceid,
+                pcesvn,
+                cpusvns,
+            }),
+            _ => Err("cert: incomplete sgx extension".into()),
+        }
+    }
+
+    fn decode_tcb(node: &DerNode) -> Result<(u16, [u16; CPUSVN_LENGTH]), String> {
+        let mut pcesvn = None;
+        let mut cpusvns = [0_u16; CPUSVN_LENGTH];
+        let mut found = 0;
+        for item in node.expect(TAG_SEQUENCE)?.children()? {
+            let item = item.children()?;
+            if item.len() != 2 || item[1].tag != TAG_INTEGER {
+                // the cpusvn is also encoded as an octet string, skip it
+                continue;
+            }
+            let oid = item[0].expect(TAG_OID)?.content;
+            let svn = item[1].uint()?;
+            if svn > u16::MAX as u64 {
+                return Err(format!("cert: svn overflow: {}", svn));
+            }
+            if oid == PCESVN_OID {
+                pcesvn = Some(svn as u16);
+            } else if oid.len() == TCB_OID.len() + 1 && oid.starts_with(TCB_OID) {
+                // sgxtcbcomp01svn ... sgxtcbcomp16svn
+                let idx = oid[TCB_OID.len()] as usize;
+                if idx >= 1 && idx <= CPUSVN_LENGTH {
+                    cpusvns[idx - 1] = svn as u16;
+                    found += 1;
+                }
+            }
+        }
+        match pcesvn {
+            Some(pcesvn) if found == CPUSVN_LENGTH => Ok((pcesvn, cpusvns)),
+            _ => Err("cert: incomplete tcb".into()),
+        }
+    }
+}
+
+fn hex_string(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Splits the PEM encoded chain and decodes the certificates, the first one
+/// must be the PCK certificate.
+pub fn decode_cert_chain(pem: &[u8]) -> Result<Vec<Certificate>, String> {
+    let pem = String::from_utf8_lossy(pem);
+    let mut certs = Vec::new();
+    let mut rest: &str = &pem;
+    while let Some(begin) = rest.find(PEM_HEADER) {
+        let body = &rest[begin + PEM_HEADER.len()..];
+        let end = match body.find(PEM_FOOTER) {
+            Some(end) => end,
+            None => return Err("cert: missing pem footer".into()),
+        };
+        let der = base64_decode(&body[..end])?;
+        certs.push(Certificate::decode(&der, certs.is_empty())?);
+        rest = &body[end + PEM_FOOTER.len()..];
+    }
+    if certs.is_empty() {
+        return Err("cert: no certificate found".into());
+    }
+    Ok(certs)
+}
+
+fn base64_decode(data: &str) -> Result<Vec<u8>, String> {
+    let mut out = Vec::with_capacity(data.len() * 3 / 4);
+    let mut acc = 0_u32;
+    let mut bits = 0;
+    for c in data.bytes() {
+        let val = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            b'=' => break,
+            b'\r' | b'\n' | b' ' => continue,
+            _ => return Err(format!("cert: invalid base64 char {:?}", c as char)),
+        };
+        acc = (acc << 6) | val as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((acc >> bits) as u8);
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_base64_decode() {
+        assert_eq!(base64_decode("").unwrap(), b"");
+        assert_eq!(base64_decode("Zm9v\nYmFy").unwrap(), b"foobar");
+        assert_eq!(base64_decode("Zm9vYg==").unwrap(), b"foob");
+        assert!(base64_decode("Zm9v!").is_err());
+    }
+}
@@ -0,0 +1,251 @@
+use std::prelude::v1::*;
+
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+use crate::{EnclaveReport, PckInfo, CPUSVN_LENGTH};
+
+/// See `TCBInfoStruct.sol`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TcbStatus {
+    Ok,
+    SwHardeningNeeded,
+    ConfigurationAndSwHardeningNeeded,
+    ConfigurationNeeded,
+    OutOfDate,
+    OutOfDateConfigurationNeeded,
+    Revoked,
+    Unrecognized,
+}
+
+impl TcbStatus {
+    /// Same as `deploy.ts` does when configuring the contract.
+    pub fn from_str(status: &str) -> Self {
+        match status {
+            "UpToDate" => Self::Ok,
+            "SWHardeningNeeded" => Self::SwHardeningNeeded,
+            "ConfigurationAndSWHardeningNeeded" => Self::ConfigurationAndSwHardeningNeeded,
+            "ConfigurationNeeded" => Self::ConfigurationNeeded,
+            "OutOfDate" => Self::OutOfDate,
+            "OutOfDateConfigurationNeeded" => Self::OutOfDateConfigurationNeeded,
+            "Revoked" => Self::Revoked,
+            _ => Self::Unrecognized,
+        }
+    }
+
+    /// See `_attestationTcbIsValid`.
+    pub fn is_valid(&self) -> bool {
+        matches!(self, Self::Ok | Self::SwHardeningNeeded)
+    }
+}
+
+/// See `EnclaveIdStruct.sol`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EnclaveIdStatus {
+    Ok,
+    IsvSvnRevoked,
+}
+
+impl EnclaveIdStatus {
+    /// `deploy.ts` treats everything except "UpToDate" as revoked.
+    pub fn from_str(status: &str) -> Self {
+        match status {
+            "UpToDate" => Self::Ok,
+            _ => Self::IsvSvnRevoked,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct TcbLevel {
+    pub pcesvn: u16,
+    pub cpusvns: [u16; CPUSVN_LENGTH],
+    pub status: TcbStatus,
+}
+
+/// The tcbInfo.json of a fmspc, from the Intel PCS.
+#[derive(Clone, Debug)]
+pub struct TcbInfo {
+    pub fmspc: String,
+    pub pceid: String,
+    pub tcb_levels: Vec<TcbLevel>,
+}
+
+#[derive(Deserialize)]
+struct TcbInfoJson {
+    #[serde(rename = "tcbInfo")]
+    tcb_info: TcbInfoBody,
+}
+
+#[derive(Deserialize)]
+struct TcbInfoBody {
+    fmspc: String,
+    #[serde(rename = "pceId")]
+    pce_id: String,
+    #[serde(rename = "tcbLevels")]
+    tcb_levels: Vec<TcbLevelJson>,
+}
+
+#[derive(Deserialize)]
+struct TcbLevelJson {
+    tcb: BTreeMap<String, u16>,
+    #[serde(rename = "tcbStatus")]
+    tcb_status: String,
+}
+
+impl TcbInfo {
+    pub fn from_json(data: &str) -> Result<Self, String> {
+        let info: TcbInfoJson =
+            serde_json::from_str(data).map_err(|err| format!("tcbInfo: {:?}", err))?;
+        let info = info.tcb_info;
+        let mut tcb_levels = Vec::with_capacity(info.tcb_levels.len());
+        for level in info.tcb_levels {
+            let pcesvn = match level.tcb.get("pcesvn") {
+                Some(svn) => *svn,
+                None => return Err("tcbInfo: missing pcesvn".into()),
+            };
+            let mut cpusvns = [0_u16; CPUSVN_LENGTH];
+            for (i, svn) in cpusvns.iter_mut().enumerate() {
+                let key = format!("sgxtcbcomp{:02}svn", i + 1);
+                *svn = match level.tcb.get(&key) {
+                    Some(svn) => *svn,
+                    None => return Err(format!("tcbInfo: missing {}", key)),
+                };
+            }
+            tcb_levels.push(TcbLevel {
+                pcesvn,
+                cpusvns,
+                status: TcbStatus::from_str(&level.tcb_status),
+            });
+        }
+        Ok(Self {
+            fmspc: info.fmspc.to_lowercase(),
+            pceid: info.pce_id.to_lowercase(),
+            tcb_levels,
+        })
+    }
+
+    /// Returns the status of the first level the pck reaches, `None` if
+    /// it's revoked. See `_checkTcbLevels`.
+    pub fn check_tcb_levels(&self, pck: &PckInfo) -> Option<TcbStatus> {
+        for level in &self.tcb_levels {
+            let pcesvn_reached = pck.pcesvn >= level.pcesvn;
+            let cpusvns_reached = pck
+                .cpusvns
+                .iter()
+                .zip(level.cpusvns.iter())
+                .all(|(pck, tcb)| pck >= tcb);
+            if pcesvn_reached && cpusvns_reached {
+                if level.status == TcbStatus::Revoked {
+                    return None;
+                }
+                return Some(level.status);
+            }
+        }
+        Some(TcbStatus::Unrecognized)
+    }
+}
+
+/// The QE identity.json from the Intel PCS.
+#[derive(Clone, Debug)]
+pub struct EnclaveIdentity {
+    pub miscselect: [u8; 4],
+    pub miscselect_mask: [u8; 4],
+    pub isvprodid: u16,
+    pub attributes: [u8; 16],
+    pub attributes_mask: [u8; 16],
+    pub mrsigner: [u8; 32],
+    /// (isvsvn, status), from the newest to the oldest.
+    pub tcb_levels: Vec<(u16, EnclaveIdStatus)>,
+}
+
+#[derive(Deserialize)]
+struct EnclaveIdentityJson {
+    #[serde(rename = "enclaveIdentity")]
+    enclave_identity: EnclaveIdentityBody,
+}
+
+#[derive(Deserialize)]
+struct EnclaveIdentityBody {
+    miscselect: String,
+    #[serde(rename = "miscselectMask")]
+    miscselect_mask: String,
+    isvprodid: u16,
+    attributes: String,
+    #[serde(rename = "attributesMask")]
+    attributes_mask: String,
+    mrsigner: String,
+    #[serde(rename = "tcbLevels")]
+    tcb_levels: Vec<EnclaveTcbLevelJson>,
+}
+
+#[derive(Deserialize)]
+struct EnclaveTcbLevelJson {
+    tcb: EnclaveTcbJson,
+    #[serde(rename = "tcbStatus")]
+    tcb_status: String,
+}
+
+#[derive(Deserialize)]
+struct EnclaveTcbJson {
+    isvsvn: u16,
+}
+
+impl EnclaveIdentity {
+    pub fn from_json(data: &str) -> Result<Self, String> {
+        let id: EnclaveIdentityJson =
+            serde_json::from_str(data).map_err(|err| format!("identity: {:?}", err))?;
+        let id = id.enclave_identity;
+        Ok(Self {
+            miscselect: hex_array(&id.miscselect)?,
+            miscselect_mask: hex_array(&id.miscselect_mask)?,
+            isvprodid: id.isvprodid,
+            attributes: hex_array(&id.attributes)?,
+            attributes_mask: hex_array(&id.attributes_mask)?,
+            mrsigner: hex_array(&id.mrsigner)?,
+            tcb_levels: id
+                .tcb_levels
+                .iter()
+                .map(|level| (level.tcb.isvsvn, EnclaveIdStatus::from_str(&level.tcb_status)))
+                .collect(),
+        })
+    }
+
+    /// Returns `None` if the QE report doesn't match the identity. See
+    /// `_verifyQEReportWithIdentity`.
+    pub fn verify_qe_report(&self, report: &EnclaveReport) -> Option<EnclaveIdStatus> {
+        let masked_eq = |value: &[u8], mask: &[u8], expect: &[u8]| {
+            value
+                .iter()
+                .zip(mask.iter())
+                .zip(expect.iter())
+                .all(|((v, m), e)| v & m == *e)
+        };
+        if !masked_eq(&report.misc_select, &self.miscselect_mask, &self.miscselect) {
+            return None;
+        }
+        if !masked_eq(&report.attributes, &self.attributes_mask, &self.attributes) {
+            return None;
+        }
+        if report.mr_signer != self.mrsigner || report.isv_prod_id != self.isvprodid {
+            return None;
+        }
+        self.tcb_levels
+            .iter()
+            .find(|(isvsvn, _)| *isvsvn <= report.isv_svn)
+            .map(|(_, status)| *status)
+    }
+}
+
+fn hex_array<const N: usize>(data: &str) -> Result<[u8; N], String> {
+    let data = data.as_bytes();
+    if data.len() != N * 2 {
+        return Err(format!("expect {} hex chars, got {}", N * 2, data.len()));
+    }
+    let mut out = [0_u8; N];
+    for (i, b) in out.iter_mut().enumerate() {
+        let s = core::str::from_utf8(&data[i * 2..i * 2 + 2]).map_err(|err| format!("{:?}", err))?;
+        *b = u8::from_str_radix(s, 16).map_err(|err| format!("invalid hex {:?}: {:?}", s, err))?;
+    }
+    Ok(out)
+}
@@ -0,0 +1,196 @@
+use std::prelude::v1::*;
+
+pub const TAG_BOOLEAN: u8 = 0x01;
+pub const TAG_INTEGER: u8 = 0x02;
+pub const TAG_BIT_STRING: u8 = 0x03;
+pub const TAG_OCTET_STRING: u8 = 0x04;
+pub const TAG_OID: u8 = 0x06;
+pub const TAG_UTC_TIME: u8 = 0x17;
+pub const TAG_GENERALIZED_TIME: u8 = 0x18;
+pub const TAG_SEQUENCE: u8 = 0x30;
+pub const TAG_SET: u8 = 0x31;
+
+/// A node of the DER encoded ASN.1 tree, see `Asn1Decode.sol`.
+#[derive(Clone, Copy, Debug)]
+pub struct DerNode<'a> {
+    pub tag: u8,
+    /// The whole node including the tag and the length.
+    pub raw: &'a [u8],
+    pub content: &'a [u8],
+}
+
+impl<'a> DerNode<'a> {
+    /// Parses the node at the beginning of `data`, returns the node and the
+    /// remaining data.
+    pub fn parse(data: &'a [u8]) -> Result<(Self, &'a [u8]), String> {
+        if data.len() < 2 {
+            return Err("der: unexpected eof".into());
+        }
+        let tag = data[0];
+        let (len, header_len) = if data[1] & 0x80 == 0 {
+            (data[1] as usize, 2)
+        } else {
+            let n = (data[1] & 0x7f) as usize;
+            if n == 0 || n > 4 || data.len() < 2 + n {
+                return Err(format!("der: invalid length at tag 0x{:02x}", tag));
+            }
+            let mut len = 0_usize;
+            for b in &data[2..2 + n] {
+                len = (len << 8) | (*b as usize);
+            }
+            (len, 2 + n)
+        };
+        let end = header_len + len;
+        if data.len() < end {
+            return Err(format!(
+                "der: content out of range, tag=0x{:02x}, len={}, remain={}",
+                tag,
+                len,
+                data.len() - header_len
+            ));
+        }
+        let node = Self {
+            tag,
+            raw: &data[..end],
+            content: &data[header_len..end],
+        };
+        Ok((node, &data[end..]))
+    }
+
+    pub fn parse_all(data: &'a [u8]) -> Result<Self, String> {
+        let (node, remain) = Self::parse(data)?;
+        if remain.len() > 0 {
+            return Err(format!("der: {} trailing bytes", remain.len()));
+        }
+        Ok(node)
+    }
+
+    pub fn expect(self, tag: u8) -> Result<Self, String> {
+        if self.tag != tag {
+            return Err(format!("der: expect tag 0x{:02x}, got 0x{:02x}", tag, self.tag));
+        }
+        Ok(self)
+    }
+
+    pub fn is_constructed(&self) -> bool {
+        self.tag & 0x20 != 0
+    }
+
+    pub fn children(&self) -> Result<Vec<DerNode<'a>>, String> {
+        if !self.is_constructed() {
+            return Err(format!("der: tag 0x{:02x} is not constructed", self.tag));
+        }
+        let mut out = Vec::new();
+        let mut data = self.content;
+        while data.len() > 0 {
+            let (node, remain) = Self::parse(data)?;
+            out.push(node);
+            data = remain;
+        }
+        Ok(out)
+    }
+
+    /// The content of a BIT STRING without the unused-bits byte.
+    pub fn bit_string(&self) -> Result<&'a [u8], String> {
+        self.expect(TAG_BIT_STRING)?;
+        match self.content.split_first() {
+            Some((0, data)) => Ok(data),
+            _ => Err("der: unsupported bit string".into()),
+        }
+    }
+
+    pub fn uint(&self) -> Result<u64, String> {
+        self.expect(TAG_INTEGER)?;
+        let data = trim_leading_zeros(self.content);
+        if data.len() > 8 {
+            return Err(format!("der: integer overflow: {} bytes", data.len()));
+        }
+        Ok(data.iter().fold(0, |acc, b| (acc << 8) | (*b as u64)))
+    }
+}
+
+pub fn trim_leading_zeros(data: &[u8]) -> &[u8] {
+    let start = data.iter().position(|b| *b != 0).unwrap_or(data.len());
+    &data[start..]
+}
+
+/// Left pads or trims the big-endian integer to `len` bytes.
+pub fn fixed_bytes(data: &[u8], len: usize) -> Result<Vec<u8>, String> {
+    let data = trim_leading_zeros(data);
+    if data.len() > len {
+        return Err(format!("der: {} bytes integer exceeds {}", data.len(), len));
+    }
+    let mut out = vec![0_u8; len - data.len()];
+    out.extend_from_slice(data);
+    Ok(out)
+}
+
+fn digits(data: &[u8]) -> Result<u64, String> {
+    let mut out = 0;
+    for b in data {
+        if !b.is_ascii_digit() {
+            return Err(format!("der: invalid time: {:?}", String::from_utf8_lossy(data)));
+        }
+        out = out * 10 + (*b - b'0') as u64;
+    }
+    Ok(out)
+}
+
+// https://howardhinnant.github.io/date_algorithms.html#days_from_civil
+fn days_from_civil(year: u64, month: u64, day: u64) -> u64 {
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y / 400;
+    let yoe = y - era * 400;
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+/// Converts the UTCTime or GeneralizedTime to the unix timestamp, see
+/// `X509DateUtils.sol`.
+pub fn x509_time(node: &DerNode) -> Result<u64, String> {
+    let (year, rest) = match node.tag {
+        TAG_UTC_TIME if node.content.len() == 13 => {
+            let year = digits(&node.content[..2])?;
+            // RFC 5280: YY >= 50 means 19YY
+            let year = if year >= 50 { 1900 + year } else { 2000 + year };
+            (year, &node.content[2..])
+        }
+        TAG_GENERALIZED_TIME if node.content.len() == 15 => {
+            (digits(&node.content[..4])?, &node.content[4..])
+        }
+        tag => return Err(format!("der: unsupported time tag 0x{:02x}", tag)),
+    };
+    if rest[10] != b'Z' {
+        return Err("der: only the UTC time is supported".into());
+    }
+    let month = digits(&rest[0..2])?;
+    let day = digits(&rest[2..4])?;
+    let hour = digits(&rest[4..6])?;
+    let minute = digits(&rest[6..8])?;
+    let second = digits(&rest[8..10])?;
+    if year < 1970 || month == 0 || month > 12 || day == 0 || day > 31 {
+        return Err(format!("der: invalid date {}-{}-{}", year, month, day));
+    }
+    let days = days_from_civil(year, month, day);
+    Ok(days * 86400 + hour * 3600 + minute * 60 + second)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_x509_time() {
+        let mut data = vec![TAG_UTC_TIME, 13];
+        data.extend_from_slice(b"300824213532Z");
+        let (node, _) = DerNode::parse(&data).unwrap();
+        assert_eq!(x509_time(&node).unwrap(), 1913837732);
+
+        let mut data = vec![TAG_GENERALIZED_TIME, 15];
+        data.extend_from_slice(b"20491231235959Z");
+        let (node, _) = DerNode::parse(&data).unwrap();
+        assert_eq!(x509_time(&node).unwrap(), 2524607999);
+    }
+}
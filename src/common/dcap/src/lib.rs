@@ -0,0 +1,22 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+#[cfg(feature = "tstd")]
+#[macro_use]
+extern crate sgxlib as std;
+
+// A port of the AutomataDcapV3Attestation contract, the quote is verified
+// with the same steps so the result can be checked before paying gas.
+
+mod der;
+pub use der::*;
+
+mod quote;
+pub use quote::*;
+
+mod cert;
+pub use cert::*;
+
+mod collateral;
+pub use collateral::*;
+
+mod verifier;
+pub use verifier::*;
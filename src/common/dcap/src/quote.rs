@@ -0,0 +1,203 @@
+use std::prelude::v1::*;
+
+// See `V3Parser.sol` and `V3Struct.sol`.
+pub const HEADER_LENGTH: usize = 48;
+pub const ENCLAVE_REPORT_LENGTH: usize = 384;
+pub const SIGNED_QUOTE_LENGTH: usize = HEADER_LENGTH + ENCLAVE_REPORT_LENGTH;
+pub const MINIMUM_QUOTE_LENGTH: usize = 1020;
+
+pub const SUPPORTED_QUOTE_VERSION: u16 = 3;
+pub const SUPPORTED_ATTESTATION_KEY_TYPE: u16 = 2;
+pub const SUPPORTED_TEE_TYPE: u32 = 0;
+pub const VALID_QE_VENDOR_ID: [u8; 16] = [
+    0x93, 0x9a, 0x72, 0x33, 0xf7, 0x9c, 0x4c, 0xa9, 0x94, 0x0a, 0x0d, 0xb3, 0x95, 0x7f, 0x06, 0x07,
+];
+
+fn le_u16(data: &[u8]) -> u16 {
+    u16::from_le_bytes([data[0], data[1]])
+}
+
+fn le_u32(data: &[u8]) -> u32 {
+    u32::from_le_bytes([data[0], data[1], data[2], data[3]])
+}
+
+fn array<const N: usize>(data: &[u8]) -> [u8; N] {
+    let mut out = [0_u8; N];
+    out.copy_from_slice(&data[..N]);
+    out
+}
+
+#[derive(Clone, Debug)]
+pub struct QuoteHeader {
+    pub version: u16,
+    pub attestation_key_type: u16,
+    pub tee_type: u32,
+    pub qe_svn: u16,
+    pub pce_svn: u16,
+    pub qe_vendor_id: [u8; 16],
+    pub user_data: [u8; 20],
+}
+
+impl QuoteHeader {
+    pub fn parse(data: &[u8]) -> Result<Self, String> {
+        if data.len() != HEADER_LENGTH {
+            return Err(format!("quote: invalid header length {}", data.len()));
+        }
+        let header = Self {
+            version: le_u16(&data[0..2]),
+            attestation_key_type: le_u16(&data[2..4]),
+            tee_type: le_u32(&data[4..8]),
+            qe_svn: le_u16(&data[8..10]),
+            pce_svn: le_u16(&data[10..12]),
+            qe_vendor_id: array(&data[12..28]),
+            user_data: array(&data[28..48]),
+        };
+        if header.version != SUPPORTED_QUOTE_VERSION {
+            return Err(format!("quote: unsupported version {}", header.version));
+        }
+        if header.attestation_key_type != SUPPORTED_ATTESTATION_KEY_TYPE {
+            return Err(format!(
+                "quote: unsupported attestation key type {}",
+                header.attestation_key_type
+            ));
+        }
+        if header.tee_type != SUPPORTED_TEE_TYPE {
+            return Err(format!("quote: unsupported tee type {}", header.tee_type));
+        }
+        if header.qe_vendor_id != VALID_QE_VENDOR_ID {
+            return Err("quote: invalid qe vendor id".into());
+        }
+        Ok(header)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct EnclaveReport {
+    pub cpu_svn: [u8; 16],
+    pub misc_select: [u8; 4],
+    pub attributes: [u8; 16],
+    pub mr_enclave: [u8; 32],
+    pub mr_signer: [u8; 32],
+    pub isv_prod_id: u16,
+    pub isv_svn: u16,
+    pub report_data: [u8; 64],
+}
+
+impl EnclaveReport {
+    pub fn parse(data: &[u8]) -> Result<Self, String> {
+        if data.len() != ENCLAVE_REPORT_LENGTH {
+            return Err(format!("quote: invalid enclave report length {}", data.len()));
+        }
+        Ok(Self {
+            cpu_svn: array(&data[0..16]),
+            misc_select: array(&data[16..20]),
+            attributes: array(&data[48..64]),
+            mr_enclave: array(&data[64..96]),
+            mr_signer: array(&data[128..160]),
+            isv_prod_id: le_u16(&data[256..258]),
+            isv_svn: le_u16(&data[258..260]),
+            report_data: array(&data[320..384]),
+        })
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct CertificationData {
+    pub cert_type: u16,
+    pub data: Vec<u8>,
+}
+
+#[derive(Clone, Debug)]
+pub struct AuthData {
+    /// r || s of the quote signature.
+    pub ecdsa256_signature: [u8; 64],
+    /// x || y of the attestation key.
+    pub attestation_key: [u8; 64],
+    pub raw_qe_report: Vec<u8>,
+    pub qe_report_signature: [u8; 64],
+    pub qe_auth_data: Vec<u8>,
+    pub certification: CertificationData,
+}
+
+impl AuthData {
+    pub fn parse(data: &[u8]) -> Result<Self, String> {
+        let qe_report_end = 128 + ENCLAVE_REPORT_LENGTH;
+        if data.len() < qe_report_end + 64 + 2 {
+            return Err("quote: auth data too short".into());
+        }
+        let ecdsa256_signature = array(&data[0..64]);
+        let attestation_key = array(&data[64..128]);
+        let raw_qe_report = data[128..qe_report_end].to_vec();
+        let qe_report_signature = array(&data[qe_report_end..qe_report_end + 64]);
+
+        let mut offset = qe_report_end + 64;
+        let qe_auth_size = le_u16(&data[offset..]) as usize;
+        offset += 2;
+        if data.len() < offset + qe_auth_size + 6 {
+            return Err("quote: qe auth data out of range".into());
+        }
+        let qe_auth_data = data[offset..offset + qe_auth_size].to_vec();
+        offset += qe_auth_size;
+
+        let cert_type = le_u16(&data[offset..]);
+        let cert_size = le_u32(&data[offset + 2..]) as usize;
+        offset += 6;
+        if !(1..=5).contains(&cert_type) {
+            return Err(format!("quote: unsupported cert type {}", cert_type));
+        }
+        if data.len() != offset + cert_size {
+            return Err(format!(
+                "quote: cert data size mismatch, expect {}, got {}",
+                cert_size,
+                data.len() - offset
+            ));
+        }
+        Ok(Self {
+            ecdsa256_signature,
+            attestation_key,
+            raw_qe_report,
+            qe_report_signature,
+            qe_auth_data,
+            certification: CertificationData {
+                cert_type,
+                data: data[offset..].to_vec(),
+            },
+        })
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Quote {
+    pub header: QuoteHeader,
+    pub local_enclave_report: EnclaveReport,
+    /// The header and the enclave report, it's signed by the attestation key.
+    pub signed_quote_data: Vec<u8>,
+    pub auth_data: AuthData,
+}
+
+impl Quote {
+    pub fn parse(quote: &[u8]) -> Result<Self, String> {
+        if quote.len() <= MINIMUM_QUOTE_LENGTH {
+            return Err(format!("quote: too short: {} bytes", quote.len()));
+        }
+        let auth_size = le_u32(&quote[SIGNED_QUOTE_LENGTH..]) as usize;
+        let auth_start = SIGNED_QUOTE_LENGTH + 4;
+        if auth_size != quote.len() - auth_start {
+            return Err(format!(
+                "quote: auth data size mismatch, expect {}, got {}",
+                auth_size,
+                quote.len() - auth_start
+            ));
+        }
+        Ok(Self {
+            header: QuoteHeader::parse(&quote[..HEADER_LENGTH])?,
+            local_enclave_report: EnclaveReport::parse(&quote[HEADER_LENGTH..SIGNED_QUOTE_LENGTH])?,
+            signed_quote_data: quote[..SIGNED_QUOTE_LENGTH].to_vec(),
+            auth_data: AuthData::parse(&quote[auth_start..])?,
+        })
+    }
+
+    pub fn qe_report(&self) -> Result<EnclaveReport, String> {
+        EnclaveReport::parse(&self.auth_data.raw_qe_report)
+    }
+}
@@ -0,0 +1,242 @@
+use std::prelude::v1::*;
+
+use crypto::{keccak_hash, sha256_sum, Secp256r1PublicKey, Secp256r1Signature};
+use std::collections::BTreeMap;
+
+use crate::{
+    decode_cert_chain, Certificate, EnclaveIdStatus, EnclaveIdentity, EnclaveReport, Quote,
+    TcbInfo, TcbStatus,
+};
+
+/// keccak256 of the Intel SGX Root CA public key (without the 0x04 prefix).
+pub const ROOTCA_PUBKEY_HASH: [u8; 32] = [
+    0x89, 0xf7, 0x2d, 0x7c, 0x48, 0x8e, 0x5b, 0x53, 0xa7, 0x7c, 0x23, 0xeb, 0xcb, 0x36, 0x97, 0x0e,
+    0xf7, 0xeb, 0x5b, 0xcf, 0x66, 0x58, 0xe9, 0xb8, 0x29, 0x2c, 0xfb, 0xe4, 0x70, 0x3a, 0x84, 0x73,
+];
+
+pub const QUOTE_CERT_CHAIN_LENGTH: usize = 3;
+
+#[derive(Clone, Debug)]
+pub struct VerifiedQuote {
+    pub enclave_report: EnclaveReport,
+    pub fmspc: String,
+    pub qe_status: EnclaveIdStatus,
+    pub tcb_status: TcbStatus,
+}
+
+impl VerifiedQuote {
+    /// What `verifyAttestation` returns as the first value.
+    pub fn is_valid(&self) -> bool {
+        self.tcb_status.is_valid()
+    }
+
+    pub fn report_data(&self) -> &[u8; 64] {
+        &self.enclave_report.report_data
+    }
+}
+
+/// Verifies the DCAP v3 quote with the same steps as
+/// `AutomataDcapV3Attestation._verify`, the collaterals are the ones the
+/// contract is configured with.
+#[derive(Clone, Debug)]
+pub struct DcapVerifier {
+    tcb_infos: BTreeMap<String, TcbInfo>,
+    qe_identity: EnclaveIdentity,
+    /// `checkLocalEnclaveReport`, (mrenclave, mrsigner) when enabled.
+    trusted_enclaves: Option<(Vec<[u8; 32]>, Vec<[u8; 32]>)>,
+    revoked_pck_serials: Vec<Vec<u8>>,
+    revoked_root_serials: Vec<Vec<u8>>,
+}
+
+impl DcapVerifier {
+    pub fn new(qe_identity: EnclaveIdentity) -> Self {
+        Self {
+            tcb_infos: BTreeMap::new(),
+            qe_identity,
+            trusted_enclaves: None,
+            revoked_pck_serials: Vec::new(),
+            revoked_root_serials: Vec::new(),
+        }
+    }
+
+    pub fn add_tcb_info(&mut self, tcb_info: TcbInfo) {
+        self.tcb_infos.insert(tcb_info.fmspc.clone(), tcb_info);
+    }
+
+    pub fn trust_enclave(&mut self, mrenclave: [u8; 32], mrsigner: [u8; 32]) {
+        let (mrenclaves, mrsigners) = self.trusted_enclaves.get_or_insert_with(Default::default);
+        mrenclaves.push(mrenclave);
+        mrsigners.push(mrsigner);
+    }
+
+    pub fn revoke_pck_serial(&mut self, serial: Vec<u8>) {
+        self.revoked_pck_serials.push(serial);
+    }
+
+    pub fn revoke_root_serial(&mut self, serial: Vec<u8>) {
+        self.revoked_root_serials.push(serial);
+    }
+
+    /// `now` is the unix timestamp used to check the certificate validity.
+    pub fn verify(&self, quote: &[u8], now: u64) -> Result<VerifiedQuote, String> {
+        // Step 1: Parse the quote input
+        let quote = Quote::parse(quote)?;
+        let report = &quote.local_enclave_report;
+
+        // Step 2: Verify application enclave report MRENCLAVE and MRSIGNER
+        if let Some((mrenclaves, mrsigners)) = &self.trusted_enclaves {
+            if !mrenclaves.contains(&report.mr_enclave) || !mrsigners.contains(&report.mr_signer) {
+                return Err("dcap: untrusted mrenclave or mrsigner".into());
+            }
+        }
+
+        // Step 3: Verify enclave identity
+        let qe_report = quote.qe_report()?;
+        let qe_status = match self.qe_identity.verify_qe_report(&qe_report) {
+            Some(EnclaveIdStatus::IsvSvnRevoked) => {
+                return Err(format!("dcap: qe isvsvn {} revoked", qe_report.isv_svn))
+            }
+            Some(status) => status,
+            None => return Err("dcap: qe report mismatch the identity".into()),
+        };
+
+        // Step 4: Parse Quote CertChain
+        let certs = decode_cert_chain(&quote.auth_data.certification.data)?;
+        if certs.len() < QUOTE_CERT_CHAIN_LENGTH {
+            return Err(format!("dcap: expect {} certs, got {}", QUOTE_CERT_CHAIN_LENGTH, certs.len()));
+        }
+        let certs = &certs[..QUOTE_CERT_CHAIN_LENGTH];
+        let pck = match &certs[0].pck {
+            Some(pck) => pck,
+            None => return Err("dcap: missing pck certificate".into()),
+        };
+
+        // Step 5: basic PCK and TCB check
+        let tcb_info = match self.tcb_infos.get(&pck.fmspc) {
+            Some(tcb_info) => tcb_info,
+            None => return Err(format!("dcap: tcbInfo not configured for fmspc {}", pck.fmspc)),
+        };
+        if tcb_info.pceid != pck.pceid {
+            return Err(format!("dcap: pceid mismatch: {} != {}", pck.pceid, tcb_info.pceid));
+        }
+
+        // Step 6: Verify TCB Level
+        let tcb_status = match tcb_info.check_tcb_levels(pck) {
+            Some(status) => status,
+            None => return Err("dcap: tcb revoked".into()),
+        };
+
+        // Step 7: Verify cert chain for PCK
+        self.verify_cert_chain(certs, now)?;
+
+        // Step 8: Verify the local attestation sig and qe report sig
+        let mut attest_key_and_auth = quote.auth_data.attestation_key.to_vec();
+        attest_key_and_auth.extend_from_slice(&quote.auth_data.qe_auth_data);
+        if sha256_sum(&attest_key_and_auth)[..] != qe_report.report_data[..32] {
+            return Err("dcap: qe report data mismatch".into());
+        }
+        if !verify_es256(
+            &quote.auth_data.raw_qe_report,
+            &quote.auth_data.qe_report_signature,
+            &certs[0].pub_key,
+        ) {
+            return Err("dcap: invalid qe report signature".into());
+        }
+        if !verify_es256(
+            &quote.signed_quote_data,
+            &quote.auth_data.ecdsa256_signature,
+            &quote.auth_data.attestation_key,
+        ) {
+            return Err("dcap: invalid quote signature".into());
+        }
+
+        Ok(VerifiedQuote {
+            enclave_report: quote.local_enclave_report,
+            fmspc: pck.fmspc.clone(),
+            qe_status,
+            tcb_status,
+        })
+    }
+
+    /// See `_verifyCertChain`, the chain must end with the trusted root CA.
+    fn verify_cert_chain(&self, certs: &[Certificate], now: u64) -> Result<(), String> {
+        let n = certs.len();
+        for (i, cert) in certs.iter().enumerate() {
+            let issuer = if i == n - 1 { cert } else { &certs[i + 1] };
+            if i == n - 2 && self.revoked_root_serials.contains(&cert.serial_number) {
+                return Err(format!("dcap: cert[{}] revoked by root crl", i));
+            }
+            if i < n - 2 && cert.is_pck() && self.revoked_pck_serials.contains(&cert.serial_number)
+            {
+                return Err(format!("dcap: cert[{}] revoked by pck crl", i));
+            }
+            if now <= cert.not_before || now >= cert.not_after {
+                return Err(format!(
+                    "dcap: cert[{}] expired, valid from {} to {}, now {}",
+                    i, cert.not_before, cert.not_after, now
+                ));
+            }
+            if !verify_es256(&cert.tbs_certificate, &cert.signature, &issuer.pub_key) {
+                return Err(format!("dcap: invalid signature of cert[{}]", i));
+            }
+            if keccak_hash(&issuer.pub_key) == ROOTCA_PUBKEY_HASH {
+                return Ok(());
+            }
+        }
+        Err("dcap: cert chain is not issued by the trusted root ca".into())
+    }
+}
+
+/// ECDSA P-256 with SHA-256, `sig` is r || s and `pubkey` is x || y.
+fn verify_es256(msg: &[u8], sig: &[u8; 64], pubkey: &[u8; 64]) -> bool {
+    let pubkey = Secp256r1PublicKey::from_raw_bytes(pubkey);
+    let sig = Secp256r1Signature::from_raw_bytes(sig);
+    pubkey.verify(msg, &sig)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const TCB_INFO: &str = include_str!("../../../../verifier/contracts/assets/tcbInfo.json");
+    const QE_IDENTITY: &str = include_str!("../../../../verifier/contracts/assets/identity.json");
+    const QUOTE: &str = include_str!("../testdata/quote.hex");
+
+    fn hex_decode(data: &str) -> Vec<u8> {
+        let data = data.trim().trim_start_matches("0x");
+        (0..data.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&data[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    fn new_verifier() -> DcapVerifier {
+        let mut verifier = DcapVerifier::new(EnclaveIdentity::from_json(QE_IDENTITY).unwrap());
+        verifier.add_tcb_info(TcbInfo::from_json(TCB_INFO).unwrap());
+        verifier
+    }
+
+    #[test]
+    fn test_verify_quote() {
+        let quote = hex_decode(QUOTE);
+        let now = 1700000000;
+        let result = new_verifier().verify(&quote, now).unwrap();
+        assert_eq!(result.fmspc, "00606a000000");
+        assert_eq!(result.qe_status, EnclaveIdStatus::Ok);
+        assert_eq!(result.tcb_status, TcbStatus::SwHardeningNeeded);
+        assert!(result.is_valid());
+        let expect: Vec<u8> = (0..64).collect();
+        assert_eq!(&result.report_data()[..], &expect[..]);
+
+        // the pck certificate expires at 2030-08-24 21:35:32
+        assert!(new_verifier().verify(&quote, 1913837732).is_err());
+
+        let mut verifier = new_verifier();
+        verifier.trust_enclave([0_u8; 32], [0_u8; 32]);
+        assert!(verifier.verify(&quote, now).is_err());
+
+        let mut tampered = quote.clone();
+        tampered[crate::HEADER_LENGTH + 320] ^= 1;
+        assert!(new_verifier().verify(&tampered, now).is_err());
+    }
+}
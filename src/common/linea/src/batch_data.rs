@@ -0,0 +1,116 @@
+use std::prelude::v1::*;
+
+use crypto::keccak_hash;
+use eth_types::{Block, SH256};
+
+use crate::AbiReader;
+
+pub const SUBMIT_DATA_SIG: &str =
+    "submitData((bytes32,bytes32,bytes32,uint256,uint256,bytes32,bytes))";
+
+/// The `SubmissionData` passed to `LineaRollup.submitData`, the calldata
+/// submission before the blobs.
+///
+/// The `compressedData` is the output of Linea's compressor, so the data hash
+/// is taken from the calldata instead of being rebuilt from the L2 blocks, the
+/// blocks are bound to it by the state roots.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SubmitDataCall {
+    pub parent_state_root: SH256,
+    pub data_parent_hash: SH256,
+    pub final_state_root: SH256,
+    pub first_block: u64,
+    pub final_block: u64,
+    pub snark_hash: SH256,
+    pub compressed_data: Vec<u8>,
+}
+
+impl SubmitDataCall {
+    pub fn selector() -> [u8; 4] {
+        let mut out = [0_u8; 4];
+        out.copy_from_slice(&keccak_hash(SUBMIT_DATA_SIG.as_bytes())[..4]);
+        out
+    }
+
+    pub fn decode(input: &[u8]) -> Result<Self, String> {
+        if input.len() < 4 || input[..4] != Self::selector() {
+            return Err("not a submitData call".into());
+        }
+        let args = AbiReader::new(&input[4..]);
+        let data = args.slice(args.offset(0)?)?;
+        Ok(Self {
+            parent_state_root: data.hash(0)?,
+            data_parent_hash: data.hash(32)?,
+            final_state_root: data.hash(64)?,
+            first_block: data.uint(96)? as u64,
+            final_block: data.uint(128)? as u64,
+            snark_hash: data.hash(160)?,
+            compressed_data: data.bytes(data.offset(192)?)?.into(),
+        })
+    }
+
+    /// keccak256(compressedData), the `dataHash` of the `DataSubmitted` event.
+    pub fn data_hash(&self) -> SH256 {
+        keccak_hash(&self.compressed_data).into()
+    }
+}
+
+/// The batch hash of the blocks which are not submitted on L1, e.g. the
+/// ranges proved by the RPC, it's only an identifier of the blocks.
+pub fn range_batch_hash(blocks: &[Block]) -> SH256 {
+    let mut buf = Vec::with_capacity(blocks.len() * 32);
+    for block in blocks {
+        buf.extend_from_slice(block.header.hash().as_bytes());
+    }
+    keccak_hash(&buf).into()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn hex(val: &str) -> Vec<u8> {
+        (0..val.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&val[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_submit_data_call() {
+        assert_eq!(SubmitDataCall::selector(), [0x7a, 0x77, 0x63, 0x15]);
+
+        // submitData((0x11.., 0x22.., 0x33.., 100, 105, 0x44.., data))
+        let input = hex(concat!(
+            "7a776315",
+            "0000000000000000000000000000000000000000000000000000000000000020",
+            "1111111111111111111111111111111111111111111111111111111111111111",
+            "2222222222222222222222222222222222222222222222222222222222222222",
+            "3333333333333333333333333333333333333333333333333333333333333333",
+            "0000000000000000000000000000000000000000000000000000000000000064",
+            "0000000000000000000000000000000000000000000000000000000000000069",
+            "4444444444444444444444444444444444444444444444444444444444444444",
+            "00000000000000000000000000000000000000000000000000000000000000e0",
+            "0000000000000000000000000000000000000000000000000000000000000046",
+            "030a11181f262d343b424950575e656c737a81888f969da4abb2b9c0c7ced5dc",
+            "e3eaf1f8ff060d141b222930373e454c535a61686f767d848b9299a0a7aeb5bc",
+            "c3cad1d8dfe60000000000000000000000000000000000000000000000000000",
+        ));
+        let call = SubmitDataCall::decode(&input).unwrap();
+        assert_eq!(call.parent_state_root, SH256::from([0x11; 32]));
+        assert_eq!(call.data_parent_hash, SH256::from([0x22; 32]));
+        assert_eq!(call.final_state_root, SH256::from([0x33; 32]));
+        assert_eq!((call.first_block, call.final_block), (100, 105));
+        assert_eq!(call.snark_hash, SH256::from([0x44; 32]));
+        assert_eq!(call.compressed_data.len(), 70);
+        assert_eq!(
+            call.data_hash(),
+            "0x2b504c75d9f361e9911972d470bec200502db90a09f051162ca1ec2ff3b1a42b".into()
+        );
+
+        assert!(SubmitDataCall::decode(&input[..input.len() - 32]).is_err());
+        let mut other = input.clone();
+        other[0] = 0;
+        assert!(SubmitDataCall::decode(&other).is_err());
+    }
+}
@@ -1,22 +1,84 @@
 use std::prelude::v1::*;
 
+use base::format::debug;
 use base::trace::Alive;
-use eth_tools::{EthLogSubscriber, ExecutionClient, LogFilter, RpcClient, RpcError};
-use eth_types::{Log, SH160, SH256, SU256};
-use std::sync::{
-    atomic::{AtomicU64, Ordering},
-    Arc,
-};
+use eth_tools::{ExecutionClient, LogFilter, RpcClient, RpcError};
+use eth_types::{BlockSelector, Log, Transaction, SH160, SH256, SU256};
+use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Debug)]
+use crate::{CheckpointStore, PendingTask, SubmitBlobsCall, SubmitDataCall, SubscriberCheckpoint};
+
+pub const DATA_SUBMITTED_SIG: &str = "DataSubmitted(bytes32,uint256,uint256)";
+pub const DATA_SUBMITTED_V2_SIG: &str = "DataSubmittedV2(bytes32,uint256,uint256)";
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct BatchTask {
+    /// The hash identifies the batch on L1, it's the data hash for the
+    /// calldata submission and the shnarf for the blob submission.
     pub data_hash: SH256,
     pub blocks: Vec<u64>,
+    pub blob: Option<BlobSubmission>,
+    #[serde(default)]
+    pub calldata: Option<CalldataSubmission>,
+}
+
+impl BatchTask {
+    pub fn is_blob(&self) -> bool {
+        self.blob.is_some()
+    }
+
+    /// Whether the task is a submission on L1, otherwise it's a block range
+    /// requested by the RPC.
+    pub fn is_l1(&self) -> bool {
+        self.blob.is_some() || self.calldata.is_some()
+    }
+}
+
+/// The zktrie roots of a calldata submission, the data hash has been checked
+/// against the `submitData` calldata.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct CalldataSubmission {
+    /// The data hash of the previous submission.
+    pub data_parent_hash: SH256,
+    pub parent_state_root: SH256,
+    pub final_state_root: SH256,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct BlobSubmission {
+    pub parent_shnarf: SH256,
+    pub shnarf: SH256,
+    pub final_state_root: SH256,
+    pub versioned_hashes: Vec<SH256>,
+}
+
+/// Why a submission log can't be turned into a task.
+#[derive(Debug)]
+pub enum SubmissionError {
+    /// The submission tx can't be fetched, the log should be parsed again.
+    Rpc(RpcError),
+    /// The submission doesn't match its calldata, the log is skipped.
+    Invalid(String),
+}
+
+impl From<RpcError> for SubmissionError {
+    fn from(err: RpcError) -> Self {
+        Self::Rpc(err)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum BatchTaskEvent {
+    New(BatchTask),
+    /// The task was emitted from an L1 block which is orphaned by a reorg.
+    Reorged(BatchTask),
 }
 
 pub struct BatchTaskSubscriber<C: RpcClient> {
-    subscriber: EthLogSubscriber<C>,
+    alive: Alive,
+    el: ExecutionClient<C>,
     cfg: BatchTaskSubscriberConfig,
+    store: Box<dyn CheckpointStore>,
 }
 
 pub struct BatchTaskSubscriberConfig {
@@ -24,60 +86,438 @@ pub struct BatchTaskSubscriberConfig {
     pub contract: SH160,
     pub max_block: u64,
     pub wait_block: u64,
+    /// How many recent blocks are tracked for the reorg detection.
+    pub reorg_window: usize,
 }
 
 impl<C> BatchTaskSubscriber<C>
 where
     C: RpcClient + Clone + Send + 'static,
 {
-    pub fn new(alive: Alive, cfg: BatchTaskSubscriberConfig, el: ExecutionClient<C>) -> Self {
-        let subscriber = EthLogSubscriber::new(alive, el, cfg.max_block, cfg.wait_block);
-        Self { subscriber, cfg }
+    pub fn new(
+        alive: Alive,
+        cfg: BatchTaskSubscriberConfig,
+        el: ExecutionClient<C>,
+        store: Box<dyn CheckpointStore>,
+    ) -> Self {
+        Self {
+            alive,
+            el,
+            cfg,
+            store,
+        }
     }
 
-    pub fn subscribe<F>(&self, f: F) -> Result<(), RpcError>
+    /// Only starts from the head if there's no checkpoint, a checkpoint which
+    /// can't be loaded is an error, or the batches in between are skipped.
+    fn init_checkpoint(&self) -> Result<SubscriberCheckpoint, String> {
+        if let Some(cp) = self.store.load()? {
+            glog::info!("[{}] resume from block {}", self.cfg.tag, cp.number);
+            return Ok(cp);
+        }
+        let head = self
+            .el
+            .get_block_header(BlockSelector::Latest)
+            .map_err(debug)?;
+        let number = head.number.as_u64().saturating_sub(self.cfg.wait_block);
+        let header = self.el.get_block_header(number.into()).map_err(debug)?;
+        glog::info!("[{}] no checkpoint found, start from block {}", self.cfg.tag, number);
+        Ok(SubscriberCheckpoint::new(number, header.hash()))
+    }
+
+    fn save_checkpoint(&self, cp: &SubscriberCheckpoint) {
+        if let Err(err) = self.store.save(cp) {
+            glog::error!("[{}] save checkpoint fail: {}", self.cfg.tag, err);
+        }
+    }
+
+    /// Checks whether the checkpoint is still on the canonical chain, and
+    /// rewinds to the common ancestor if not.
+    ///
+    /// Returns true if a reorg happened.
+    fn check_reorg<F>(&self, cp: &mut SubscriberCheckpoint, f: &F) -> Result<bool, RpcError>
     where
-        F: Fn(BatchTask),
+        F: Fn(BatchTaskEvent),
     {
+        let header = self.el.get_block_header(cp.number.into())?;
+        if header.hash() == cp.hash {
+            return Ok(false);
+        }
+        let mut ancestor = None;
+        for (number, hash) in cp.recent.iter().rev() {
+            let header = self.el.get_block_header((*number).into())?;
+            if &header.hash() == hash {
+                ancestor = Some((*number, *hash));
+                break;
+            }
+        }
+        let (number, hash) = match ancestor {
+            Some(ancestor) => ancestor,
+            None => {
+                // the reorg is deeper than the tracked window, the emitted
+                // tasks can't be trusted anymore.
+                let oldest = cp.recent.first().map(|n| n.0).unwrap_or(cp.number);
+                let number = oldest.saturating_sub(1);
+                let header = self.el.get_block_header(number.into())?;
+                glog::warn!(
+                    "[{}] reorg deeper than the window, rewind to {}",
+                    self.cfg.tag,
+                    number
+                );
+                (number, header.hash())
+            }
+        };
+        glog::warn!(
+            "[{}] reorg detected at block {}, rewind to {}",
+            self.cfg.tag,
+            cp.number,
+            number
+        );
+        for task in cp.rewind(number, hash) {
+            f(BatchTaskEvent::Reorged(task));
+        }
+        if cp.recent.len() == 0 {
+            cp.recent.push((number, hash));
+        }
+        self.save_checkpoint(cp);
+        Ok(true)
+    }
+
+    fn poll<F>(&self, cp: &mut SubscriberCheckpoint, f: &F) -> Result<bool, RpcError>
+    where
+        F: Fn(BatchTaskEvent),
+    {
+        let head = self.el.get_block_header(BlockSelector::Latest)?;
+        let head = head.number.as_u64().saturating_sub(self.cfg.wait_block);
+        if head <= cp.number {
+            return Ok(false);
+        }
+        if self.check_reorg(cp, f)? {
+            return Ok(true);
+        }
+
+        let start = cp.number + 1;
+        let end = head.min(cp.number + self.cfg.max_block);
+        let data_submitted = solidity::encode_eventsig(DATA_SUBMITTED_SIG);
+        let data_submitted_v2 = solidity::encode_eventsig(DATA_SUBMITTED_V2_SIG);
         let filter = LogFilter {
             address: vec![self.cfg.contract],
-            topics: vec![vec![solidity::encode_eventsig(
-                "DataSubmitted(bytes32,uint256,uint256)",
-            )]],
+            topics: vec![vec![data_submitted, data_submitted_v2]],
+            from_block: Some(start.into()),
+            to_block: Some(end.into()),
             ..Default::default()
         };
-        let handler = |block, logs: Vec<Log>| {
-            let mut task = BatchTask {
-                data_hash: SH256::default(),
-                blocks: Vec::new(),
-            };
-            let mut tmp_tx = None;
-            for log in logs {
-                let current_tx = match tmp_tx {
-                    Some(hash) => hash,
-                    None => {
-                        tmp_tx = Some(log.transaction_hash);
-                        log.transaction_hash
-                    }
-                };
-                let start_block: SU256 = (&log.topics[2]).into();
-                let end_block: SU256 = (&log.topics[3]).into();
-                if log.transaction_hash != current_tx {
-                    task = BatchTask {
-                        data_hash: log.topics[1],
-                        blocks: (start_block.as_u64()..=end_block.as_u64()).collect(),
-                    };
-                    tmp_tx = Some(log.transaction_hash);
-                } else {
-                    task.data_hash = log.topics[1];
-                    task.blocks = (start_block.as_u64()..=end_block.as_u64()).collect();
-                }
+        let logs = self.el.get_logs(&filter)?;
+        let end_hash = self.el.get_block_header(end.into())?.hash();
+        // the range is scanned again from the checkpoint if any tx can't be
+        // fetched, so nothing is emitted before all the logs are parsed.
+        let tasks = parse_logs(&self.cfg.tag, logs, &data_submitted, |hash| {
+            self.el.get_transaction(hash)
+        })?;
+        for pending in tasks {
+            f(BatchTaskEvent::New(pending.task.clone()));
+            cp.pending.push(pending);
+        }
+        cp.advance(end, end_hash, self.cfg.reorg_window);
+        self.save_checkpoint(cp);
+        Ok(end < head)
+    }
+
+    pub fn subscribe<F>(&self, f: F) -> Result<(), String>
+    where
+        F: Fn(BatchTaskEvent),
+    {
+        let mut cp = self.init_checkpoint()?;
+        while self.alive.is_alive() {
+            match self.poll(&mut cp, &f) {
+                // keep catching up
+                Ok(true) => continue,
+                Ok(false) => {}
+                Err(err) => glog::error!("[{}] poll fail: {:?}", self.cfg.tag, err),
+            }
+            self.alive.sleep_ms(3000);
+        }
+        Ok(())
+    }
+}
+
+fn parse_calldata_task(log: &Log, tx: &Transaction) -> Result<BatchTask, String> {
+    let data_hash = log.topics[1];
+    let start_block: SU256 = (&log.topics[2]).into();
+    let end_block: SU256 = (&log.topics[3]).into();
+
+    let call = SubmitDataCall::decode(&tx.input)?;
+    if call.data_hash() != data_hash {
+        return Err(format!(
+            "data hash mismatch, event: {:?}, calldata: {:?}",
+            data_hash,
+            call.data_hash()
+        ));
+    }
+    if call.first_block != start_block.as_u64() || call.final_block != end_block.as_u64() {
+        return Err(format!(
+            "block range mismatch, event: {}..{}, calldata: {}..{}",
+            start_block, end_block, call.first_block, call.final_block
+        ));
+    }
+
+    Ok(BatchTask {
+        data_hash,
+        blocks: (start_block.as_u64()..=end_block.as_u64()).collect(),
+        blob: None,
+        calldata: Some(CalldataSubmission {
+            data_parent_hash: call.data_parent_hash,
+            parent_state_root: call.parent_state_root,
+            final_state_root: call.final_state_root,
+        }),
+    })
+}
+
+fn parse_blob_task(log: &Log, tx: &Transaction) -> Result<BatchTask, String> {
+    let shnarf = log.topics[1];
+    let start_block: SU256 = (&log.topics[2]).into();
+    let end_block: SU256 = (&log.topics[3]).into();
+
+    let call = SubmitBlobsCall::decode(&tx.input)?;
+    let versioned_hashes = call.verify()?;
+    // the point evaluation binds each commitment to `blobhash(i)`
+    let tx_hashes = tx.blob_versioned_hashes.clone().unwrap_or_default();
+    if tx_hashes != versioned_hashes {
+        return Err(format!(
+            "versioned hashes mismatch, tx: {:?}, calldata: {:?}",
+            tx_hashes, versioned_hashes
+        ));
+    }
+    if call.final_shnarf != shnarf {
+        return Err(format!(
+            "shnarf mismatch, event: {:?}, calldata: {:?}",
+            shnarf, call.final_shnarf
+        ));
+    }
+    let first = call.blobs.first().unwrap();
+    let last = call.blobs.last().unwrap();
+    if first.first_block != start_block.as_u64() || last.final_block != end_block.as_u64() {
+        return Err(format!(
+            "block range mismatch, event: {}..{}, calldata: {}..{}",
+            start_block, end_block, first.first_block, last.final_block
+        ));
+    }
+
+    Ok(BatchTask {
+        data_hash: shnarf,
+        blocks: (start_block.as_u64()..=end_block.as_u64()).collect(),
+        blob: Some(BlobSubmission {
+            parent_shnarf: call.parent_shnarf,
+            shnarf,
+            final_state_root: last.final_state_root,
+            versioned_hashes,
+        }),
+        calldata: None,
+    })
+}
+
+fn parse_log<T>(log: &Log, data_submitted: &SH256, get_tx: &T) -> Result<BatchTask, SubmissionError>
+where
+    T: Fn(&SH256) -> Result<Transaction, RpcError>,
+{
+    let tx = get_tx(&log.transaction_hash)?;
+    let task = if &log.topics[0] == data_submitted {
+        parse_calldata_task(log, &tx)
+    } else {
+        parse_blob_task(log, &tx)
+    };
+    task.map_err(SubmissionError::Invalid)
+}
+
+/// Parses the submission logs in order, the invalid ones are skipped, while
+/// a failed tx fetch fails the whole range, otherwise the batch is lost and
+/// the following blob submissions can't find their parent shnarf.
+fn parse_logs<T>(
+    tag: &str,
+    mut logs: Vec<Log>,
+    data_submitted: &SH256,
+    get_tx: T,
+) -> Result<Vec<PendingTask>, RpcError>
+where
+    T: Fn(&SH256) -> Result<Transaction, RpcError>,
+{
+    logs.sort_by_key(|log| (log.block_number.as_u64(), log.log_index.as_u64()));
+    let mut tasks = Vec::with_capacity(logs.len());
+    for log in logs {
+        if log.removed {
+            continue;
+        }
+        match parse_log(&log, data_submitted, &get_tx) {
+            Ok(task) => tasks.push(PendingTask {
+                block: log.block_number.as_u64(),
+                task,
+            }),
+            Err(SubmissionError::Rpc(err)) => return Err(err),
+            Err(SubmissionError::Invalid(err)) => glog::error!(
+                "[{}] skip invalid submission[{:?}]: {}",
+                tag,
+                log.transaction_hash,
+                err
+            ),
+        }
+    }
+    Ok(tasks)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::blob::test::submit_blobs_input;
+
+    // submitData((0x11.., 0x22.., 0x33.., 100, 105, 0x44.., data)), the same
+    // calldata as `test_submit_data_call`.
+    const SUBMIT_DATA_INPUT: &str = concat!(
+        "0x7a776315",
+        "0000000000000000000000000000000000000000000000000000000000000020",
+        "1111111111111111111111111111111111111111111111111111111111111111",
+        "2222222222222222222222222222222222222222222222222222222222222222",
+        "3333333333333333333333333333333333333333333333333333333333333333",
+        "0000000000000000000000000000000000000000000000000000000000000064",
+        "0000000000000000000000000000000000000000000000000000000000000069",
+        "4444444444444444444444444444444444444444444444444444444444444444",
+        "00000000000000000000000000000000000000000000000000000000000000e0",
+        "0000000000000000000000000000000000000000000000000000000000000046",
+        "030a11181f262d343b424950575e656c737a81888f969da4abb2b9c0c7ced5dc",
+        "e3eaf1f8ff060d141b222930373e454c535a61686f767d848b9299a0a7aeb5bc",
+        "c3cad1d8dfe60000000000000000000000000000000000000000000000000000",
+    );
+    const DATA_HASH: &str = "0x2b504c75d9f361e9911972d470bec200502db90a09f051162ca1ec2ff3b1a42b";
+
+    fn word(n: u64) -> SH256 {
+        let mut hash = SH256::default();
+        hash.0[24..].copy_from_slice(&n.to_be_bytes());
+        hash
+    }
+
+    fn log(sig: &str, hash: SH256, start: u64, end: u64, tx: SH256, block: u64) -> Log {
+        serde_json::from_value(serde_json::json!({
+            "address": "0xd19d4b5d358258f05d7b411e21a1460d11b0876f",
+            "topics": [solidity::encode_eventsig(sig), hash, word(start), word(end)],
+            "data": "0x",
+            "blockNumber": format!("0x{:x}", block),
+            "blockHash": SH256::from([0xbb; 32]),
+            "transactionHash": tx,
+            "transactionIndex": "0x0",
+            "logIndex": "0x0",
+            "removed": false,
+        }))
+        .unwrap()
+    }
+
+    fn blob_tx(hash: SH256, input: &[u8], versioned_hashes: &[&str]) -> Transaction {
+        serde_json::from_value(serde_json::json!({
+            "type": "0x3",
+            "hash": hash,
+            "nonce": "0x1",
+            "blockHash": SH256::from([0xbb; 32]),
+            "blockNumber": "0x10",
+            "transactionIndex": "0x0",
+            "from": "0x1111111111111111111111111111111111111111",
+            "to": "0xd19d4b5d358258f05d7b411e21a1460d11b0876f",
+            "value": "0x0",
+            "chainId": "0x1",
+            "maxFeePerGas": "0x1",
+            "maxPriorityFeePerGas": "0x1",
+            "maxFeePerBlobGas": "0x1",
+            "gas": "0x100000",
+            "input": format!("0x{}", hex_encode(input)),
+            "accessList": [],
+            "blobVersionedHashes": versioned_hashes,
+            "v": "0x0",
+            "yParity": "0x0",
+            "r": "0x1",
+            "s": "0x1",
+        }))
+        .unwrap()
+    }
+
+    fn hex_encode(data: &[u8]) -> String {
+        data.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    fn tx(hash: SH256, input: &str) -> Transaction {
+        serde_json::from_value(serde_json::json!({
+            "type": "0x0",
+            "hash": hash,
+            "nonce": "0x1",
+            "blockHash": SH256::from([0xbb; 32]),
+            "blockNumber": "0x10",
+            "transactionIndex": "0x0",
+            "from": "0x1111111111111111111111111111111111111111",
+            "to": "0xd19d4b5d358258f05d7b411e21a1460d11b0876f",
+            "value": "0x0",
+            "gasPrice": "0x1",
+            "gas": "0x100000",
+            "input": input,
+            "v": "0x1b",
+            "r": "0x1",
+            "s": "0x1",
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_parse_logs() {
+        let data_submitted = solidity::encode_eventsig(DATA_SUBMITTED_SIG);
+        let valid = SH256::from([0xa1; 32]);
+        let invalid = SH256::from([0xa2; 32]);
+        let logs = vec![
+            // the event doesn't match the calldata
+            log(DATA_SUBMITTED_SIG, DATA_HASH.into(), 100, 106, invalid, 17),
+            log(DATA_SUBMITTED_SIG, DATA_HASH.into(), 100, 105, valid, 16),
+        ];
+        let get_tx = |hash: &SH256| Ok(tx(*hash, SUBMIT_DATA_INPUT));
+        let tasks = parse_logs("test", logs.clone(), &data_submitted, get_tx).unwrap();
+        assert_eq!(tasks.len(), 1);
+        assert_eq!(tasks[0].block, 16);
+        assert_eq!(tasks[0].task.data_hash, DATA_HASH.into());
+        assert_eq!(tasks[0].task.blocks, (100..=105).collect::<Vec<_>>());
+        let calldata = tasks[0].task.calldata.as_ref().unwrap();
+        assert_eq!(calldata.data_parent_hash, SH256::from([0x22; 32]));
+        assert_eq!(calldata.final_state_root, SH256::from([0x33; 32]));
+
+        // a tx which can't be fetched fails the range instead of being skipped
+        let failing = |hash: &SH256| {
+            if hash == &valid {
+                Err(RpcError::RecvResponseError("connection refused".into()))
+            } else {
+                Ok(tx(*hash, SUBMIT_DATA_INPUT))
             }
-            f(task);
-            Ok(())
         };
+        assert!(parse_logs("test", logs, &data_submitted, failing).is_err());
+    }
+    #[test]
+    fn test_parse_blob_logs() {
+        let data_submitted = solidity::encode_eventsig(DATA_SUBMITTED_SIG);
+        let shnarf: SH256 =
+            "0xf24fa3e3861662f12333b6571165fad7b8f1a5c631a5b096355cb5261055a84b".into();
+        let versioned_hashes = [
+            "0x014e1e1e7cd63c9fb7069154ccd855d827a3dd3eca076232b4217745a2b6db57",
+            "0x0128ec0de076c8c2e7d6d8222ada07a0da8ec45ea53160a39b5dd64b79d7bcc8",
+        ];
+        let submission = SH256::from([0xa3; 32]);
+        let logs = vec![log(DATA_SUBMITTED_V2_SIG, shnarf, 10, 20, submission, 16)];
+        let input = submit_blobs_input();
+        let get_tx = |hash: &SH256| Ok(blob_tx(*hash, &input, &versioned_hashes));
+        let tasks = parse_logs("test", logs.clone(), &data_submitted, get_tx).unwrap();
+        assert_eq!(tasks.len(), 1);
+        let blob = tasks[0].task.blob.as_ref().unwrap();
+        assert_eq!(blob.parent_shnarf, SH256::from([0x0f; 32]));
+        assert_eq!(blob.shnarf, shnarf);
+        assert_eq!(blob.final_state_root, SH256::from([0xa2; 32]));
+
+        // the blob commitments are not bound to the blobs of the tx
+        let get_tx = |hash: &SH256| Ok(blob_tx(*hash, &input, &versioned_hashes[..1]));
+        let tasks = parse_logs("test", logs.clone(), &data_submitted, get_tx).unwrap();
+        assert!(tasks.is_empty());
 
-        self.subscriber
-            .subscribe(&self.cfg.tag, 0, filter, handler)
+        // skipping it would break the shnarf chain of the next submission
+        let failing = |_: &SH256| Err(RpcError::RecvResponseError("timeout".into()));
+        assert!(parse_logs("test", logs, &data_submitted, failing).is_err());
     }
 }
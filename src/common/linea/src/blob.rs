@@ -0,0 +1,327 @@
+use std::prelude::v1::*;
+
+use crypto::{keccak_hash, sha256_sum};
+use eth_types::{SH256, SU256};
+
+pub const VERSIONED_HASH_VERSION_KZG: u8 = 0x01;
+pub const KZG_COMMITMENT_LENGTH: usize = 48;
+
+pub const SUBMIT_BLOBS_SIG: &str =
+    "submitBlobs(((bytes32,uint256,uint256,bytes32),uint256,bytes,bytes)[],bytes32,bytes32)";
+
+/// Computes the EIP-4844 versioned hash of a KZG commitment.
+pub fn kzg_to_versioned_hash(commitment: &[u8]) -> Result<SH256, String> {
+    if commitment.len() != KZG_COMMITMENT_LENGTH {
+        return Err(format!(
+            "invalid kzg commitment length: {}",
+            commitment.len()
+        ));
+    }
+    let mut hash: SH256 = sha256_sum(commitment).into();
+    hash.0[0] = VERSIONED_HASH_VERSION_KZG;
+    Ok(hash)
+}
+
+/// keccak256(abi.encode(snarkHash, versionedHash)), see `LineaRollup.submitBlobs`.
+pub fn data_evaluation_point(snark_hash: &SH256, versioned_hash: &SH256) -> SH256 {
+    let mut buf = [0_u8; 64];
+    buf[..32].copy_from_slice(snark_hash.as_bytes());
+    buf[32..].copy_from_slice(versioned_hash.as_bytes());
+    keccak_hash(&buf).into()
+}
+
+/// keccak256(abi.encode(parentShnarf, snarkHash, finalStateRootHash, x, y))
+pub fn compute_shnarf(
+    parent_shnarf: &SH256,
+    snark_hash: &SH256,
+    final_state_root: &SH256,
+    evaluation_point: &SH256,
+    evaluation_claim: &SH256,
+) -> SH256 {
+    let mut buf = Vec::with_capacity(32 * 5);
+    buf.extend_from_slice(parent_shnarf.as_bytes());
+    buf.extend_from_slice(snark_hash.as_bytes());
+    buf.extend_from_slice(final_state_root.as_bytes());
+    buf.extend_from_slice(evaluation_point.as_bytes());
+    buf.extend_from_slice(evaluation_claim.as_bytes());
+    keccak_hash(&buf).into()
+}
+
+#[derive(Clone, Debug)]
+pub struct BlobSubmissionData {
+    pub final_state_root: SH256,
+    pub first_block: u64,
+    pub final_block: u64,
+    pub snark_hash: SH256,
+    pub evaluation_claim: SH256,
+    pub kzg_commitment: Vec<u8>,
+    pub kzg_proof: Vec<u8>,
+}
+
+#[derive(Clone, Debug)]
+pub struct SubmitBlobsCall {
+    pub blobs: Vec<BlobSubmissionData>,
+    pub parent_shnarf: SH256,
+    pub final_shnarf: SH256,
+}
+
+impl SubmitBlobsCall {
+    pub fn selector() -> [u8; 4] {
+        let mut out = [0_u8; 4];
+        out.copy_from_slice(&keccak_hash(SUBMIT_BLOBS_SIG.as_bytes())[..4]);
+        out
+    }
+
+    pub fn decode(input: &[u8]) -> Result<Self, String> {
+        if input.len() < 4 || input[..4] != Self::selector() {
+            return Err("not a submitBlobs call".into());
+        }
+        let args = AbiReader::new(&input[4..]);
+        let array = args.slice(args.offset(0)?)?;
+        let parent_shnarf = args.hash(32)?;
+        let final_shnarf = args.hash(64)?;
+
+        let len = array.uint(0)?;
+        let items = array.slice(32)?;
+        let mut blobs = Vec::with_capacity(len);
+        for i in 0..len {
+            let item = items.slice(items.offset(i * 32)?)?;
+            blobs.push(BlobSubmissionData {
+                final_state_root: item.hash(0)?,
+                first_block: item.uint(32)? as u64,
+                final_block: item.uint(64)? as u64,
+                snark_hash: item.hash(96)?,
+                evaluation_claim: item.hash(128)?,
+                kzg_commitment: item.bytes(item.offset(160)?)?.into(),
+                kzg_proof: item.bytes(item.offset(192)?)?.into(),
+            });
+        }
+        Ok(Self {
+            blobs,
+            parent_shnarf,
+            final_shnarf,
+        })
+    }
+
+    /// Recomputes the shnarf chain from the kzg commitments, and returns the
+    /// versioned hashes of the blobs.
+    ///
+    /// The versioned hash is a part of the data evaluation point, so a
+    /// matching final shnarf means the commitments are the ones of the blobs
+    /// carried by the transaction.
+    pub fn verify(&self) -> Result<Vec<SH256>, String> {
+        if self.blobs.len() == 0 {
+            return Err("empty blob submission".into());
+        }
+        let mut shnarf = self.parent_shnarf;
+        let mut versioned_hashes = Vec::with_capacity(self.blobs.len());
+        let mut next_block = None;
+        for blob in &self.blobs {
+            if blob.first_block > blob.final_block {
+                return Err(format!(
+                    "invalid block range: {}..{}",
+                    blob.first_block, blob.final_block
+                ));
+            }
+            if let Some(next_block) = next_block {
+                if blob.first_block != next_block {
+                    return Err(format!(
+                        "blob data not continuous, expect: {}, got: {}",
+                        next_block, blob.first_block
+                    ));
+                }
+            }
+            next_block = Some(blob.final_block + 1);
+
+            let versioned_hash = kzg_to_versioned_hash(&blob.kzg_commitment)?;
+            let x = data_evaluation_point(&blob.snark_hash, &versioned_hash);
+            shnarf = compute_shnarf(
+                &shnarf,
+                &blob.snark_hash,
+                &blob.final_state_root,
+                &x,
+                &blob.evaluation_claim,
+            );
+            versioned_hashes.push(versioned_hash);
+        }
+        if shnarf != self.final_shnarf {
+            return Err(format!(
+                "final shnarf mismatch, expect: {:?}, calculated: {:?}",
+                self.final_shnarf, shnarf
+            ));
+        }
+        Ok(versioned_hashes)
+    }
+}
+
+pub(crate) struct AbiReader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> AbiReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+
+    pub fn word(&self, off: usize) -> Result<&'a [u8], String> {
+        match self.data.get(off..off + 32) {
+            Some(word) => Ok(word),
+            None => Err(format!("abi: out of range, off={}, len={}", off, self.data.len())),
+        }
+    }
+
+    pub fn hash(&self, off: usize) -> Result<SH256, String> {
+        Ok(SH256::from_slice(self.word(off)?))
+    }
+
+    pub fn uint(&self, off: usize) -> Result<usize, String> {
+        let val = SU256::from_big_endian(self.word(off)?);
+        if val > SU256::from(u32::max_value()) {
+            return Err(format!("abi: integer overflow at {}: {}", off, val));
+        }
+        Ok(val.as_u64() as usize)
+    }
+
+    pub fn offset(&self, off: usize) -> Result<usize, String> {
+        self.uint(off)
+    }
+
+    pub fn slice(&self, off: usize) -> Result<AbiReader<'a>, String> {
+        match self.data.get(off..) {
+            Some(data) => Ok(AbiReader { data }),
+            None => Err(format!("abi: out of range, off={}, len={}", off, self.data.len())),
+        }
+    }
+
+    pub fn bytes(&self, off: usize) -> Result<&'a [u8], String> {
+        let len = self.uint(off)?;
+        match self.data.get(off + 32..off + 32 + len) {
+            Some(data) => Ok(data),
+            None => Err(format!("abi: bytes out of range, off={}, len={}", off, len)),
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use super::*;
+
+    #[test]
+    fn test_kzg_to_versioned_hash() {
+        // the commitment of an empty blob (the point at infinity)
+        let mut commitment = [0_u8; KZG_COMMITMENT_LENGTH];
+        commitment[0] = 0xc0;
+        let hash = kzg_to_versioned_hash(&commitment).unwrap();
+        assert_eq!(
+            hash,
+            "0x010657f37554c781402a22917dee2f75def7ab966d7b770905398eba3c444014".into()
+        );
+        assert!(kzg_to_versioned_hash(&commitment[1..]).is_err());
+    }
+
+    fn hex(val: &str) -> Vec<u8> {
+        (0..val.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&val[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    /// Two continuous blobs on the parent shnarf 0x0f..
+    pub(crate) fn submit_blobs_input() -> Vec<u8> {
+        hex(concat!(
+            "42fbe842",
+            "0000000000000000000000000000000000000000000000000000000000000060",
+            "0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f",
+            "f24fa3e3861662f12333b6571165fad7b8f1a5c631a5b096355cb5261055a84b",
+            "0000000000000000000000000000000000000000000000000000000000000002",
+            "0000000000000000000000000000000000000000000000000000000000000040",
+            "00000000000000000000000000000000000000000000000000000000000001e0",
+            "a1a1a1a1a1a1a1a1a1a1a1a1a1a1a1a1a1a1a1a1a1a1a1a1a1a1a1a1a1a1a1a1",
+            "000000000000000000000000000000000000000000000000000000000000000a",
+            "000000000000000000000000000000000000000000000000000000000000000e",
+            "5151515151515151515151515151515151515151515151515151515151515151",
+            "6161616161616161616161616161616161616161616161616161616161616161",
+            "00000000000000000000000000000000000000000000000000000000000000e0",
+            "0000000000000000000000000000000000000000000000000000000000000140",
+            "0000000000000000000000000000000000000000000000000000000000000030",
+            "8181818181818181818181818181818181818181818181818181818181818181",
+            "8181818181818181818181818181818100000000000000000000000000000000",
+            "0000000000000000000000000000000000000000000000000000000000000030",
+            "9191919191919191919191919191919191919191919191919191919191919191",
+            "9191919191919191919191919191919100000000000000000000000000000000",
+            "a2a2a2a2a2a2a2a2a2a2a2a2a2a2a2a2a2a2a2a2a2a2a2a2a2a2a2a2a2a2a2a2",
+            "000000000000000000000000000000000000000000000000000000000000000f",
+            "0000000000000000000000000000000000000000000000000000000000000014",
+            "5252525252525252525252525252525252525252525252525252525252525252",
+            "6262626262626262626262626262626262626262626262626262626262626262",
+            "00000000000000000000000000000000000000000000000000000000000000e0",
+            "0000000000000000000000000000000000000000000000000000000000000140",
+            "0000000000000000000000000000000000000000000000000000000000000030",
+            "8282828282828282828282828282828282828282828282828282828282828282",
+            "8282828282828282828282828282828200000000000000000000000000000000",
+            "0000000000000000000000000000000000000000000000000000000000000030",
+            "9292929292929292929292929292929292929292929292929292929292929292",
+            "9292929292929292929292929292929200000000000000000000000000000000",
+        ))
+    }
+
+    #[test]
+    fn test_submit_blobs_decode() {
+        assert_eq!(SubmitBlobsCall::selector(), [0x42, 0xfb, 0xe8, 0x42]);
+        let call = SubmitBlobsCall::decode(&submit_blobs_input()).unwrap();
+        assert_eq!(call.parent_shnarf, SH256::from([0x0f; 32]));
+        assert_eq!(
+            call.final_shnarf,
+            "0xf24fa3e3861662f12333b6571165fad7b8f1a5c631a5b096355cb5261055a84b".into()
+        );
+        assert_eq!(call.blobs.len(), 2);
+        let blob = &call.blobs[1];
+        assert_eq!(blob.final_state_root, SH256::from([0xa2; 32]));
+        assert_eq!((blob.first_block, blob.final_block), (15, 20));
+        assert_eq!(blob.snark_hash, SH256::from([0x52; 32]));
+        assert_eq!(blob.evaluation_claim, SH256::from([0x62; 32]));
+        assert_eq!(blob.kzg_commitment, vec![0x82; KZG_COMMITMENT_LENGTH]);
+        assert_eq!(blob.kzg_proof, vec![0x92; 48]);
+
+        let mut input = submit_blobs_input();
+        input[0] = 0;
+        assert!(SubmitBlobsCall::decode(&input).is_err());
+        let input = submit_blobs_input();
+        assert!(SubmitBlobsCall::decode(&input[..input.len() - 64]).is_err());
+    }
+
+    #[test]
+    fn test_submit_blobs_shnarf_chain() {
+        let call = SubmitBlobsCall::decode(&submit_blobs_input()).unwrap();
+        assert_eq!(
+            call.verify().unwrap(),
+            vec![
+                "0x014e1e1e7cd63c9fb7069154ccd855d827a3dd3eca076232b4217745a2b6db57".into(),
+                "0x0128ec0de076c8c2e7d6d8222ada07a0da8ec45ea53160a39b5dd64b79d7bcc8".into(),
+            ]
+        );
+
+        // the shnarf of the first blob is the parent of the second one
+        let mut first = call.clone();
+        first.blobs.truncate(1);
+        first.final_shnarf =
+            "0xb978edac2c6b66cd85b987e529554088d05a7d7506de508ef2e49f2226bef3b5".into();
+        assert!(first.verify().is_ok());
+
+        let mut bad_claim = call.clone();
+        bad_claim.blobs[0].evaluation_claim = SH256::default();
+        assert!(bad_claim.verify().unwrap_err().contains("final shnarf mismatch"));
+
+        let mut bad_parent = call.clone();
+        bad_parent.parent_shnarf = SH256::default();
+        assert!(bad_parent.verify().is_err());
+
+        let mut gap = call.clone();
+        gap.blobs[1].first_block = 16;
+        assert!(gap.verify().unwrap_err().contains("not continuous"));
+
+        let mut empty = call.clone();
+        empty.blobs.clear();
+        assert!(empty.verify().is_err());
+    }
+}
@@ -8,8 +8,8 @@ use crypto::keccak_hash;
 use eth_tools::{ExecutionClient, MixRpcClient, RpcClient, RpcError};
 use eth_types::Signer;
 use eth_types::{
-    Block, BlockSelector, FetchState, Transaction, TransactionAccessTuple, TransactionInner, SH256,
-    SU256, SU64,
+    Block, BlockSelector, FetchState, Receipt, Transaction, TransactionAccessTuple,
+    TransactionInner, SH256, SU256, SU64,
 };
 use evm_executor::{BlockBuilder, BlockHashGetter, Engine, Pob};
 use mpt::{Database, StateCollector};
@@ -196,32 +196,44 @@ impl BlockExecutor {
         db.commit();
     }
 
+    /// Executes the block on the zktrie state built from the shomei traces,
+    /// returns the receipts and the new zktrie top root hash.
     pub fn execute_v2<D: zktrie::Database<Node = zktrie::Node>>(
         &self,
         db: D,
         traces: &[zktrie::Trace],
         block: Block,
-    ) -> Result<(), String> {
-        let root = traces[0].old_top_hash();
-        
+    ) -> Result<(Vec<Receipt>, SH256), String> {
+        if traces.len() == 0 {
+            return Err(format!("block[{}] empty traces", block.header.number));
+        }
+        let number = block.header.number.as_u64();
         let statedb = ZkTrieState::new_from_trace(db, &traces[0]);
         let block_hash_cache = BlockHashCache::new(BTreeMap::new());
         let mut builder =
             BlockBuilder::new(self.engine.clone(), statedb, block_hash_cache, block.header)?;
         let txs = self.preprocess_txs(block.transactions)?;
-
+        let total = txs.len();
+        let mut receipts = Vec::with_capacity(total);
         for (idx, tx) in txs.into_iter().enumerate() {
-            glog::info!("[{}] {:?}", idx, tx);
             let tx = Arc::new(tx);
-            let receipt = builder.commit(tx.clone()).unwrap();
-            glog::info!("receipt: {:?}", receipt);
+            let receipt = builder.commit(tx.clone()).map_err(debug)?;
+            glog::info!(
+                "[{}][{}/{}]tx: {:?}, receipt:{}",
+                number,
+                idx + 1,
+                total,
+                tx.hash(),
+                receipt.status
+            );
+            receipts.push(receipt.clone());
         }
         let root_hash = builder.flush_state().map_err(debug)?;
-        glog::info!("final root hash: {:?}", root_hash);
-        Ok(())
+        glog::info!("[{}] zktrie root hash: {:?}", number, root_hash);
+        Ok((receipts, root_hash))
     }
 
-    pub fn execute(&self, db: &Database, pob: Pob) -> Result<Block, String> {
+    pub fn execute(&self, db: &Database, pob: Pob) -> Result<(Block, Vec<Receipt>), String> {
         if pob.data.chain_id != self.engine.signer().chain_id.as_u64() {
             return Err(format!(
                 "chain_id mismatch {}!={}",
@@ -247,9 +259,10 @@ impl BlockExecutor {
         )?;
         let txs = self.preprocess_txs(pob.block.transactions)?;
         let total = txs.len();
+        let mut receipts = Vec::with_capacity(total);
         for (idx, tx) in txs.into_iter().enumerate() {
             let tx = Arc::new(tx);
-            let receipt = builder.commit(tx.clone()).unwrap();
+            let receipt = builder.commit(tx.clone()).map_err(debug)?;
             glog::info!(
                 "[{}][{}/{}]tx: {:?}, receipt:{}",
                 number,
@@ -258,6 +271,7 @@ impl BlockExecutor {
                 tx.hash(),
                 receipt.status
             );
+            receipts.push(receipt.clone());
             // let expect_receipt = client.get_receipt(&tx.hash()).unwrap().unwrap();
             // if let Err(err) = Receipt::compare(&expect_receipt, receipt) {
             //     glog::info!("diff: {}", err);
@@ -267,7 +281,7 @@ impl BlockExecutor {
             builder.withdrawal(withdrawals).unwrap();
         }
         let block = builder.finalize().unwrap();
-        return Ok(block);
+        return Ok((block, receipts));
     }
 
     fn preprocess_txs(&self, txs: Vec<Transaction>) -> Result<Vec<TransactionInner>, String> {
@@ -0,0 +1,184 @@
+use std::prelude::v1::*;
+
+use base::format::debug;
+use eth_types::SH256;
+use serde::{Deserialize, Serialize};
+
+use crate::{read_file_opt, write_file_atomic, BatchTask};
+
+/// The progress of the `BatchTaskSubscriber`.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct SubscriberCheckpoint {
+    /// The last fully processed L1 block.
+    pub number: u64,
+    pub hash: SH256,
+    /// The recent processed blocks, used to find the common ancestor after
+    /// a reorg.
+    pub recent: Vec<(u64, SH256)>,
+    /// The tasks emitted from the recent blocks, they need to be cancelled if
+    /// their blocks are orphaned.
+    pub pending: Vec<PendingTask>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PendingTask {
+    pub block: u64,
+    pub task: BatchTask,
+}
+
+impl SubscriberCheckpoint {
+    pub fn new(number: u64, hash: SH256) -> Self {
+        Self {
+            number,
+            hash,
+            recent: vec![(number, hash)],
+            pending: Vec::new(),
+        }
+    }
+
+    pub fn advance(&mut self, number: u64, hash: SH256, window: usize) {
+        self.number = number;
+        self.hash = hash;
+        self.recent.push((number, hash));
+        if self.recent.len() > window {
+            let drop = self.recent.len() - window;
+            self.recent.drain(..drop);
+        }
+        let oldest = self.recent[0].0;
+        self.pending.retain(|n| n.block > oldest);
+    }
+
+    /// Rewinds to the ancestor and returns the tasks from the orphaned blocks.
+    pub fn rewind(&mut self, number: u64, hash: SH256) -> Vec<BatchTask> {
+        self.number = number;
+        self.hash = hash;
+        self.recent.retain(|(n, _)| *n <= number);
+        let mut orphaned = Vec::new();
+        let mut pending = Vec::with_capacity(self.pending.len());
+        for item in self.pending.drain(..) {
+            if item.block > number {
+                orphaned.push(item.task);
+            } else {
+                pending.push(item);
+            }
+        }
+        self.pending = pending;
+        orphaned
+    }
+}
+
+pub trait CheckpointStore: Send + Sync {
+    fn load(&self) -> Result<Option<SubscriberCheckpoint>, String>;
+    fn save(&self, cp: &SubscriberCheckpoint) -> Result<(), String>;
+}
+
+pub struct FileCheckpointStore {
+    path: String,
+}
+
+impl FileCheckpointStore {
+    pub fn new(path: String) -> Self {
+        Self { path }
+    }
+}
+
+impl CheckpointStore for FileCheckpointStore {
+    fn load(&self) -> Result<Option<SubscriberCheckpoint>, String> {
+        let data = match read_file_opt(&self.path)? {
+            Some(data) => data,
+            None => return Ok(None),
+        };
+        let cp = serde_json::from_slice(&data)
+            .map_err(|err| format!("checkpoint[{}] is corrupted: {}", self.path, err))?;
+        Ok(Some(cp))
+    }
+
+    fn save(&self, cp: &SubscriberCheckpoint) -> Result<(), String> {
+        let data = serde_json::to_vec(cp).map_err(debug)?;
+        write_file_atomic(&self.path, &data)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn hash(n: u64) -> SH256 {
+        let mut hash = SH256::default();
+        hash.0[24..].copy_from_slice(&n.to_be_bytes());
+        hash
+    }
+
+    fn task(block: u64) -> PendingTask {
+        PendingTask {
+            block,
+            task: BatchTask {
+                data_hash: hash(block),
+                blocks: vec![block * 10],
+                blob: None,
+                calldata: None,
+            },
+        }
+    }
+
+    #[test]
+    fn test_advance() {
+        let mut cp = SubscriberCheckpoint::new(100, hash(100));
+        for number in 101..=105 {
+            cp.pending.push(task(number));
+            cp.advance(number, hash(number), 3);
+        }
+        assert_eq!((cp.number, cp.hash), (105, hash(105)));
+        assert_eq!(
+            cp.recent,
+            vec![(103, hash(103)), (104, hash(104)), (105, hash(105))]
+        );
+        // the tasks out of the window can't be reorged anymore
+        let blocks: Vec<u64> = cp.pending.iter().map(|n| n.block).collect();
+        assert_eq!(blocks, vec![104, 105]);
+    }
+
+    #[test]
+    fn test_rewind() {
+        let mut cp = SubscriberCheckpoint::new(100, hash(100));
+        for number in 101..=105 {
+            cp.pending.push(task(number));
+            cp.advance(number, hash(number), 10);
+        }
+        let orphaned = cp.rewind(102, hash(102));
+        let orphaned: Vec<u64> = orphaned.iter().map(|n| n.blocks[0]).collect();
+        assert_eq!(orphaned, vec![1030, 1040, 1050]);
+        assert_eq!((cp.number, cp.hash), (102, hash(102)));
+        assert_eq!(cp.recent.last(), Some(&(102, hash(102))));
+        assert_eq!(cp.recent.len(), 3);
+        assert_eq!(cp.pending.len(), 2);
+
+        // continues from the ancestor on the new chain
+        cp.advance(103, hash(1003), 10);
+        assert_eq!(cp.recent.last(), Some(&(103, hash(1003))));
+    }
+
+    #[test]
+    fn test_file_store() {
+        let dir = std::env::temp_dir().join(format!("linea-checkpoint-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("batch-task.json");
+        let store = FileCheckpointStore::new(path.to_str().unwrap().into());
+        assert!(store.load().unwrap().is_none());
+
+        let mut cp = SubscriberCheckpoint::new(100, hash(100));
+        cp.pending.push(task(101));
+        cp.advance(101, hash(101), 10);
+        store.save(&cp).unwrap();
+        let loaded = store.load().unwrap().unwrap();
+        assert_eq!((loaded.number, loaded.hash), (101, hash(101)));
+        assert_eq!(loaded.recent, cp.recent);
+        assert_eq!(loaded.pending[0].task.data_hash, hash(101));
+
+        // a truncated file shouldn't restart the subscriber from the head
+        std::fs::write(&path, b"{\"number\":10").unwrap();
+        let err = store.load().unwrap_err();
+        assert!(err.contains("corrupted"), "{}", err);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,612 @@
+use std::prelude::v1::*;
+
+use eth_types::{BlockHeader, HexBytes, SH160, SH256, SU256};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+// Fixed number of extra-data prefix bytes reserved for signer vanity
+pub const EXTRA_VANITY: usize = 32;
+// Fixed number of extra-data suffix bytes reserved for signer seal
+pub const EXTRA_SEAL: usize = 65;
+
+const NONCE_AUTH_VOTE: [u8; 8] = [0xff; 8];
+const NONCE_DROP_VOTE: [u8; 8] = [0; 8];
+
+pub const DIFF_IN_TURN: u64 = 2;
+pub const DIFF_NO_TURN: u64 = 1;
+
+/// keccak256(rlp([])), clique blocks never have uncles.
+pub const EMPTY_UNCLE_HASH: [u8; 32] = [
+    0x1d, 0xcc, 0x4d, 0xe8, 0xde, 0xc7, 0x5d, 0x7a, 0xab, 0x85, 0xb5, 0x67, 0xb6, 0xcc, 0xd4, 0x1a,
+    0xd3, 0x12, 0x45, 0x1b, 0x94, 0x8a, 0x74, 0x13, 0xf0, 0xa1, 0x42, 0xfd, 0x40, 0xd4, 0x93, 0x47,
+];
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct CliqueConfig {
+    /// The minimum seconds between two blocks.
+    pub period: u64,
+    pub epoch: u64,
+}
+
+impl Default for CliqueConfig {
+    fn default() -> Self {
+        Self {
+            period: 2,
+            epoch: 30000,
+        }
+    }
+}
+
+pub fn seal_hash(header: &BlockHeader) -> [u8; 32] {
+    // Remove the last 65 bytes of extra_data
+    let extra_data: HexBytes = header.extra_data[..header.extra_data.len() - EXTRA_SEAL].into();
+    // May need to handle the case where base_fee_per_gas is nil
+    // https://github.com/ethereum/go-ethereum/blob/81fd1b3cf9c4c4c9f0e06f8bdcbaa8b29c81b052/consensus/clique/clique.go#L763
+    let mut s = rlp::RlpStream::new_list(16);
+    s.append(&header.parent_hash);
+    s.append(&header.sha3_uncles);
+    s.append(&header.miner);
+    s.append(&header.state_root);
+    s.append(&header.transactions_root);
+    s.append(&header.receipts_root);
+    s.append(&header.logs_bloom);
+    s.append(&header.difficulty);
+    s.append(&header.number);
+    s.append(&header.gas_limit);
+    s.append(&header.gas_used);
+    s.append(&header.timestamp);
+    s.append(&extra_data);
+    s.append(&header.mix_hash);
+    s.append(&header.nonce);
+    s.append(&header.base_fee_per_gas);
+
+    let data = s.out().to_vec();
+    return crypto::keccak_hash(&data);
+}
+
+pub fn recover_sealer(header: &BlockHeader) -> Result<SH160, String> {
+    let extra_data = header.extra_data.as_bytes();
+    if extra_data.len() < EXTRA_VANITY + EXTRA_SEAL {
+        return Err(format!(
+            "block[{}] extra-data too short: {}",
+            header.number,
+            extra_data.len()
+        ));
+    }
+    let mut sig_array = [0_u8; 65];
+    sig_array.copy_from_slice(&extra_data[extra_data.len() - EXTRA_SEAL..]);
+    let msg = seal_hash(header);
+    let pub_key_array =
+        crypto::secp256k1_ecdsa_recover(&sig_array, &msg).ok_or("fail to ecrecover")?;
+    let pub_key = crypto::Secp256k1PublicKey::from_raw_bytes(&pub_key_array);
+    Ok(pub_key.eth_accountid().into())
+}
+
+/// Parses the signer list embedded in the extra-data of a checkpoint header.
+pub fn checkpoint_signers(header: &BlockHeader) -> Result<Vec<SH160>, String> {
+    let extra_data = header.extra_data.as_bytes();
+    if extra_data.len() < EXTRA_VANITY + EXTRA_SEAL {
+        return Err(format!(
+            "block[{}] extra-data too short: {}",
+            header.number,
+            extra_data.len()
+        ));
+    }
+    let signers_bytes = &extra_data[EXTRA_VANITY..extra_data.len() - EXTRA_SEAL];
+    if signers_bytes.len() % 20 != 0 {
+        return Err(format!(
+            "block[{}] invalid signer list on checkpoint: {}",
+            header.number,
+            signers_bytes.len()
+        ));
+    }
+    Ok(signers_bytes
+        .chunks(20)
+        .map(|n| SH160::from_slice(n))
+        .collect())
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Vote {
+    pub signer: SH160,
+    pub block: u64,
+    pub address: SH160,
+    pub authorize: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Tally {
+    pub authorize: bool,
+    pub votes: usize,
+}
+
+/// The state of the clique authorization voting at a given point in time.
+///
+/// It mirrors the `Snapshot` of go-ethereum's clique engine, it's persisted
+/// by the `HeaderChainStore`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Snapshot {
+    config: CliqueConfig,
+    pub number: u64,
+    pub hash: SH256,
+    pub timestamp: u64,
+    pub signers: BTreeSet<SH160>,
+    pub recents: BTreeMap<u64, SH160>,
+    pub votes: Vec<Vote>,
+    pub tally: BTreeMap<SH160, Tally>,
+}
+
+impl Snapshot {
+    pub fn new(config: CliqueConfig, number: u64, hash: SH256, signers: Vec<SH160>) -> Self {
+        Self {
+            config,
+            number,
+            hash,
+            timestamp: 0,
+            signers: signers.into_iter().collect(),
+            recents: BTreeMap::new(),
+            votes: Vec::new(),
+            tally: BTreeMap::new(),
+        }
+    }
+
+    /// Creates the snapshot from a checkpoint header, the header should be an
+    /// epoch transition block which contains the full signer list.
+    pub fn from_checkpoint(config: CliqueConfig, header: &BlockHeader) -> Result<Self, String> {
+        let number = header.number.as_u64();
+        if number % config.epoch != 0 {
+            return Err(format!(
+                "block[{}] is not a checkpoint, epoch={}",
+                number, config.epoch
+            ));
+        }
+        let signers = checkpoint_signers(header)?;
+        if signers.len() == 0 {
+            return Err(format!("block[{}] empty signer list", number));
+        }
+        let mut snapshot = Self::new(config, number, header.hash(), signers);
+        snapshot.timestamp = header.timestamp.as_u64();
+        Ok(snapshot)
+    }
+
+    pub fn config(&self) -> &CliqueConfig {
+        &self.config
+    }
+
+    pub fn signer_list(&self) -> Vec<SH160> {
+        self.signers.iter().cloned().collect()
+    }
+
+    /// Returns whether the signer is the in-turn signer at the given block.
+    pub fn inturn(&self, number: u64, signer: &SH160) -> bool {
+        match self.signers.iter().position(|n| n == signer) {
+            Some(offset) => (number % self.signers.len() as u64) == offset as u64,
+            None => false,
+        }
+    }
+
+    fn valid_vote(&self, address: &SH160, authorize: bool) -> bool {
+        let signer = self.signers.contains(address);
+        (signer && !authorize) || (!signer && authorize)
+    }
+
+    fn cast(&mut self, address: SH160, authorize: bool) -> bool {
+        if !self.valid_vote(&address, authorize) {
+            return false;
+        }
+        let tally = self.tally.entry(address).or_insert(Tally {
+            authorize,
+            votes: 0,
+        });
+        tally.votes += 1;
+        true
+    }
+
+    fn uncast(&mut self, address: &SH160, authorize: bool) -> bool {
+        let tally = match self.tally.get_mut(address) {
+            Some(tally) => tally,
+            None => return false,
+        };
+        if tally.authorize != authorize {
+            return false;
+        }
+        if tally.votes > 1 {
+            tally.votes -= 1;
+        } else {
+            self.tally.remove(address);
+        }
+        true
+    }
+
+    /// Checks the header structure which doesn't depend on the snapshot
+    /// state, see `verifyHeader` in go-ethereum's clique engine.
+    pub fn verify_header_fields(&self, header: &BlockHeader) -> Result<(), String> {
+        let number = header.number.as_u64();
+        let checkpoint = number % self.config.epoch == 0;
+        let nonce = header.nonce.as_bytes();
+        if checkpoint && header.miner != SH160::default() {
+            return Err(format!("block[{}] checkpoint with non-zero beneficiary", number));
+        }
+        if nonce != &NONCE_AUTH_VOTE[..] && nonce != &NONCE_DROP_VOTE[..] {
+            return Err(format!("block[{}] invalid vote nonce", number));
+        }
+        if checkpoint && nonce != &NONCE_DROP_VOTE[..] {
+            return Err(format!("block[{}] checkpoint with vote nonce", number));
+        }
+
+        let extra_data = header.extra_data.as_bytes();
+        if extra_data.len() < EXTRA_VANITY + EXTRA_SEAL {
+            return Err(format!("block[{}] missing vanity or signature", number));
+        }
+        let signers_bytes = extra_data.len() - EXTRA_VANITY - EXTRA_SEAL;
+        if !checkpoint && signers_bytes != 0 {
+            return Err(format!("block[{}] non-checkpoint with signer list", number));
+        }
+        if checkpoint && signers_bytes % 20 != 0 {
+            return Err(format!("block[{}] invalid checkpoint signer list", number));
+        }
+        if header.mix_hash != SH256::default() {
+            return Err(format!("block[{}] non-zero mix digest", number));
+        }
+        if header.sha3_uncles != EMPTY_UNCLE_HASH.into() {
+            return Err(format!("block[{}] non-empty uncle hash", number));
+        }
+        let difficulty = header.difficulty;
+        if difficulty != SU256::from(DIFF_IN_TURN) && difficulty != SU256::from(DIFF_NO_TURN) {
+            return Err(format!("block[{}] invalid difficulty: {}", number, difficulty));
+        }
+        Ok(())
+    }
+
+    /// Verifies the seal and the difficulty of the next header against the
+    /// current signer set, and returns the sealer.
+    pub fn verify_seal(&self, header: &BlockHeader) -> Result<SH160, String> {
+        let number = header.number.as_u64();
+        let signer = recover_sealer(header)?;
+        if !self.signers.contains(&signer) {
+            return Err(format!("block[{}] unauthorized signer: {:?}", number, signer));
+        }
+        let limit = self.signers.len() as u64 / 2 + 1;
+        for (seen, recent) in &self.recents {
+            if recent == &signer && (number < limit || *seen > number - limit) {
+                return Err(format!(
+                    "block[{}] signer {:?} signed recently at {}",
+                    number, signer, seen
+                ));
+            }
+        }
+        let expect = if self.inturn(number, &signer) {
+            DIFF_IN_TURN
+        } else {
+            DIFF_NO_TURN
+        };
+        if header.difficulty != SU256::from(expect) {
+            return Err(format!(
+                "block[{}] wrong difficulty, signer: {:?}, want: {}, got: {}",
+                number, signer, expect, header.difficulty
+            ));
+        }
+        Ok(signer)
+    }
+
+    /// Verifies the next header and applies its vote to the snapshot.
+    pub fn apply(&mut self, header: &BlockHeader) -> Result<SH160, String> {
+        let number = header.number.as_u64();
+        if number != self.number + 1 {
+            return Err(format!(
+                "invalid voting chain, expect block {}, got {}",
+                self.number + 1,
+                number
+            ));
+        }
+        if header.parent_hash != self.hash {
+            return Err(format!(
+                "block[{}] unknown ancestor: {:?}, expect: {:?}",
+                number, header.parent_hash, self.hash
+            ));
+        }
+        self.verify_header_fields(header)?;
+        let timestamp = header.timestamp.as_u64();
+        if timestamp < self.timestamp + self.config.period {
+            return Err(format!(
+                "block[{}] invalid timestamp: {}, parent: {}, period: {}",
+                number, timestamp, self.timestamp, self.config.period
+            ));
+        }
+
+        let checkpoint = number % self.config.epoch == 0;
+        if checkpoint {
+            let signers = checkpoint_signers(header)?;
+            if signers.iter().cloned().collect::<BTreeSet<_>>() != self.signers {
+                return Err(format!("block[{}] mismatching signer list on checkpoint", number));
+            }
+            self.votes.clear();
+            self.tally.clear();
+        }
+
+        // Delete the oldest signer from the recent list to allow it signing again
+        let limit = self.signers.len() as u64 / 2 + 1;
+        if number >= limit {
+            self.recents.remove(&(number - limit));
+        }
+        let signer = self.verify_seal(header)?;
+        self.recents.insert(number, signer);
+
+        // Discard any previous votes from the signer
+        let address = header.miner;
+        if let Some(idx) = self
+            .votes
+            .iter()
+            .position(|vote| vote.signer == signer && vote.address == address)
+        {
+            let vote = self.votes.remove(idx);
+            self.uncast(&vote.address, vote.authorize);
+        }
+
+        let authorize = header.nonce.as_bytes() == &NONCE_AUTH_VOTE[..];
+        if self.cast(address, authorize) {
+            self.votes.push(Vote {
+                signer,
+                block: number,
+                address,
+                authorize,
+            });
+        }
+
+        // If the vote passed, update the list of signers
+        let passed = match self.tally.get(&address) {
+            Some(tally) => tally.votes > self.signers.len() / 2,
+            None => false,
+        };
+        if passed {
+            if authorize {
+                self.signers.insert(address);
+            } else {
+                self.signers.remove(&address);
+                // Signer list shrunk, delete any leftover recent caches
+                let limit = self.signers.len() as u64 / 2 + 1;
+                if number >= limit {
+                    self.recents.remove(&(number - limit));
+                }
+                // Discard any previous votes the deauthorized signer cast
+                let mut idx = 0;
+                while idx < self.votes.len() {
+                    if self.votes[idx].signer == address {
+                        let vote = self.votes.remove(idx);
+                        self.uncast(&vote.address, vote.authorize);
+                        continue;
+                    }
+                    idx += 1;
+                }
+            }
+            // Discard any previous votes around the just changed account
+            self.votes.retain(|vote| vote.address != address);
+            self.tally.remove(&address);
+        }
+
+        self.number = number;
+        self.hash = header.hash();
+        self.timestamp = timestamp;
+        Ok(signer)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use super::*;
+    use crypto::{secp256k1_gen_keypair, Secp256k1PrivateKey};
+
+    fn addr(n: u8) -> SH160 {
+        let mut addr = SH160::default();
+        addr.0[19] = n;
+        addr
+    }
+
+    /// Generates the signers sorted by the address, the same order as the
+    /// in-turn rotation.
+    pub(crate) fn signers(n: usize) -> Vec<(Secp256k1PrivateKey, SH160)> {
+        let mut signers: Vec<_> = (0..n)
+            .map(|_| {
+                let (prvkey, pubkey) = secp256k1_gen_keypair();
+                (prvkey, pubkey.eth_accountid().into())
+            })
+            .collect();
+        signers.sort_by_key(|(_, addr)| *addr);
+        signers
+    }
+
+    pub(crate) fn genesis_header(signers: &[(Secp256k1PrivateKey, SH160)]) -> BlockHeader {
+        let mut extra_data = vec![0_u8; EXTRA_VANITY];
+        for (_, addr) in signers {
+            extra_data.extend_from_slice(addr.as_bytes());
+        }
+        extra_data.extend_from_slice(&[0_u8; EXTRA_SEAL]);
+        BlockHeader {
+            extra_data: extra_data.into(),
+            sha3_uncles: EMPTY_UNCLE_HASH.into(),
+            timestamp: 100u64.into(),
+            ..Default::default()
+        }
+    }
+
+    fn genesis(signers: &[(Secp256k1PrivateKey, SH160)]) -> Snapshot {
+        Snapshot::from_checkpoint(CliqueConfig::default(), &genesis_header(signers)).unwrap()
+    }
+
+    /// The next block of the snapshot voting on `miner`, with the difficulty
+    /// of the signer, it should be sealed after the modification.
+    pub(crate) fn next_block(snap: &Snapshot, signer: &SH160, miner: SH160, authorize: bool) -> BlockHeader {
+        let number = snap.number + 1;
+        let mut extra_data = vec![0_u8; EXTRA_VANITY];
+        extra_data.extend_from_slice(&[0_u8; EXTRA_SEAL]);
+        let difficulty = match snap.inturn(number, signer) {
+            true => DIFF_IN_TURN,
+            false => DIFF_NO_TURN,
+        };
+        let nonce = match authorize {
+            true => NONCE_AUTH_VOTE,
+            false => NONCE_DROP_VOTE,
+        };
+        BlockHeader {
+            parent_hash: snap.hash,
+            sha3_uncles: EMPTY_UNCLE_HASH.into(),
+            miner,
+            number: number.into(),
+            timestamp: (snap.timestamp + snap.config().period).into(),
+            difficulty: difficulty.into(),
+            nonce: nonce.into(),
+            extra_data: extra_data.into(),
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn seal(prvkey: &Secp256k1PrivateKey, mut header: BlockHeader) -> BlockHeader {
+        let sig = crypto::secp256k1_rec_sign_bytes(prvkey, &seal_hash(&header));
+        let mut extra_data = header.extra_data.as_bytes().to_vec();
+        let len = extra_data.len();
+        extra_data[len - EXTRA_SEAL..].copy_from_slice(&sig.to_array());
+        header.extra_data = extra_data.into();
+        header
+    }
+
+    fn vote(
+        snap: &mut Snapshot,
+        signer: &(Secp256k1PrivateKey, SH160),
+        miner: SH160,
+        authorize: bool,
+    ) -> Result<SH160, String> {
+        let header = seal(&signer.0, next_block(snap, &signer.1, miner, authorize));
+        snap.apply(&header)
+    }
+
+    #[test]
+    fn test_checkpoint_signers() {
+        let mut extra_data = vec![0_u8; EXTRA_VANITY];
+        extra_data.extend_from_slice(addr(2).as_bytes());
+        extra_data.extend_from_slice(addr(1).as_bytes());
+        extra_data.extend_from_slice(&[0_u8; EXTRA_SEAL]);
+        let header = BlockHeader {
+            extra_data: extra_data.into(),
+            ..Default::default()
+        };
+        assert_eq!(checkpoint_signers(&header).unwrap(), vec![addr(2), addr(1)]);
+
+        let snap = Snapshot::from_checkpoint(CliqueConfig::default(), &header).unwrap();
+        assert_eq!(snap.signer_list(), vec![addr(1), addr(2)]);
+        assert!(snap.inturn(2, &addr(1)));
+        assert!(snap.inturn(3, &addr(2)));
+        assert!(!snap.inturn(3, &addr(1)));
+        assert!(!snap.inturn(3, &addr(3)));
+    }
+
+    #[test]
+    fn test_vote_add_and_remove_signer() {
+        let keys = signers(4);
+        let (a, b, c, d) = (&keys[0], &keys[1], &keys[2], &keys[3]);
+        let mut snap = genesis(&keys[..3]);
+
+        assert_eq!(vote(&mut snap, a, d.1, true), Ok(a.1));
+        assert_eq!(snap.tally[&d.1].votes, 1);
+        // no vote on the zero address
+        assert_eq!(vote(&mut snap, b, SH160::default(), false), Ok(b.1));
+        assert_eq!(snap.votes.len(), 1);
+        // the second vote of the same signer replaces the first one
+        assert_eq!(vote(&mut snap, a, d.1, true), Ok(a.1));
+        assert_eq!(snap.tally[&d.1].votes, 1);
+        assert_eq!(snap.votes.len(), 1);
+        // a vote on the change which is not valid is ignored
+        assert_eq!(vote(&mut snap, c, a.1, true), Ok(c.1));
+        assert!(snap.tally.get(&a.1).is_none());
+
+        // the majority of 3 signers is 2 votes
+        assert_eq!(vote(&mut snap, b, d.1, true), Ok(b.1));
+        assert_eq!(snap.signers.len(), 4);
+        assert!(snap.signers.contains(&d.1));
+        assert!(snap.votes.is_empty());
+        assert!(snap.tally.is_empty());
+
+        // the majority of 4 signers is 3 votes
+        assert_eq!(vote(&mut snap, d, b.1, false), Ok(d.1));
+        assert_eq!(vote(&mut snap, c, b.1, false), Ok(c.1));
+        assert_eq!(snap.tally[&b.1].votes, 2);
+        assert!(snap.signers.contains(&b.1));
+        assert_eq!(vote(&mut snap, a, b.1, false), Ok(a.1));
+        assert_eq!(snap.signer_list().len(), 3);
+        assert!(!snap.signers.contains(&b.1));
+        assert!(snap.tally.is_empty());
+
+        // the removed signer can't seal any more
+        let err = vote(&mut snap, b, SH160::default(), false).unwrap_err();
+        assert!(err.contains("unauthorized signer"), "{}", err);
+    }
+
+    #[test]
+    fn test_recent_signer() {
+        let keys = signers(3);
+        let mut snap = genesis(&keys);
+        assert!(vote(&mut snap, &keys[0], SH160::default(), false).is_ok());
+        // floor(3 / 2) + 1 = 2, the signer should wait for one block
+        let err = vote(&mut snap, &keys[0], SH160::default(), false).unwrap_err();
+        assert!(err.contains("signed recently"), "{}", err);
+
+        let mut snap = genesis(&keys);
+        for idx in [0, 1, 0, 2, 1] {
+            assert!(vote(&mut snap, &keys[idx], SH160::default(), false).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_inturn_difficulty() {
+        let keys = signers(3);
+        let snap = genesis(&keys);
+        // block 1 is in turn for the second signer
+        let (inturn, noturn) = (&keys[1], &keys[0]);
+        assert!(snap.inturn(1, &inturn.1));
+
+        for (signer, difficulty, ok) in [
+            (inturn, DIFF_IN_TURN, true),
+            (inturn, DIFF_NO_TURN, false),
+            (noturn, DIFF_NO_TURN, true),
+            (noturn, DIFF_IN_TURN, false),
+        ] {
+            let mut header = next_block(&snap, &signer.1, SH160::default(), false);
+            header.difficulty = difficulty.into();
+            let result = snap.clone().apply(&seal(&signer.0, header));
+            match ok {
+                true => assert_eq!(result, Ok(signer.1)),
+                false => assert!(result.unwrap_err().contains("wrong difficulty")),
+            }
+        }
+    }
+
+    #[test]
+    fn test_header_rules() {
+        let keys = signers(2);
+        let snap = genesis(&keys);
+        let signer = &keys[1];
+        let block = || next_block(&snap, &signer.1, SH160::default(), false);
+
+        let mut header = block();
+        header.timestamp = (snap.timestamp + snap.config().period - 1).into();
+        let err = snap.clone().apply(&seal(&signer.0, header)).unwrap_err();
+        assert!(err.contains("invalid timestamp"), "{}", err);
+
+        let mut header = block();
+        header.sha3_uncles = SH256::default();
+        let err = snap.clone().apply(&seal(&signer.0, header)).unwrap_err();
+        assert!(err.contains("uncle hash"), "{}", err);
+
+        let mut header = block();
+        header.parent_hash = SH256::default();
+        let err = snap.clone().apply(&seal(&signer.0, header)).unwrap_err();
+        assert!(err.contains("unknown ancestor"), "{}", err);
+
+        // the seal doesn't cover the modified field
+        let mut header = seal(&signer.0, block());
+        header.gas_limit = 1u64.into();
+        let err = snap.clone().apply(&header).unwrap_err();
+        assert!(err.contains("unauthorized signer"), "{}", err);
+
+        assert!(snap.clone().apply(&seal(&signer.0, block())).is_ok());
+    }
+}
@@ -0,0 +1,95 @@
+use std::prelude::v1::*;
+
+use std::io::{ErrorKind, Read, Write};
+
+#[cfg(not(feature = "tstd"))]
+use std::fs;
+#[cfg(feature = "tstd")]
+use std::untrusted::fs;
+
+/// Returns None if the file doesn't exist, the other errors are returned so
+/// an unreadable state file isn't mistaken for a fresh start.
+pub fn read_file_opt(path: &str) -> Result<Option<Vec<u8>>, String> {
+    let mut file = match fs::File::open(path) {
+        Ok(file) => file,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(format!("open {} fail: {:?}", path, err)),
+    };
+    let mut data = Vec::new();
+    file.read_to_end(&mut data)
+        .map_err(|err| format!("read {} fail: {:?}", path, err))?;
+    Ok(Some(data))
+}
+
+/// Writes the data to `<path>.tmp` and renames it over the path, a crash in
+/// the middle leaves either the old or the new content.
+pub fn write_file_atomic(path: &str, data: &[u8]) -> Result<(), String> {
+    write_atomic(path, data, false)
+}
+
+/// The same as `write_file_atomic`, but the file is only accessible by the
+/// owner (0600), for the plaintext secrets.
+pub fn write_private_file_atomic(path: &str, data: &[u8]) -> Result<(), String> {
+    write_atomic(path, data, true)
+}
+
+fn write_atomic(path: &str, data: &[u8], private: bool) -> Result<(), String> {
+    let tmp = format!("{}.tmp", path);
+    // the mode only applies to a new file
+    let _ = fs::remove_file(&tmp);
+    let mut opts = fs::OpenOptions::new();
+    opts.write(true).create(true).truncate(true);
+    #[cfg(all(unix, not(feature = "tstd")))]
+    if private {
+        use std::os::unix::fs::OpenOptionsExt;
+        opts.mode(0o600);
+    }
+    #[cfg(not(all(unix, not(feature = "tstd"))))]
+    let _ = private;
+
+    let result = opts
+        .open(&tmp)
+        .and_then(|mut file| {
+            file.write_all(data)?;
+            file.sync_all()
+        })
+        .and_then(|_| fs::rename(&tmp, path));
+    if let Err(err) = result {
+        let _ = fs::remove_file(&tmp);
+        return Err(format!("write {} fail: {:?}", path, err));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_write_file_atomic() {
+        let dir = std::env::temp_dir().join(format!("linea-file-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("state.json");
+        let path = path.to_str().unwrap();
+
+        assert_eq!(read_file_opt(path).unwrap(), None);
+        write_file_atomic(path, b"first").unwrap();
+        write_file_atomic(path, b"second").unwrap();
+        assert_eq!(read_file_opt(path).unwrap(), Some(b"second".to_vec()));
+        assert!(read_file_opt(&format!("{}.tmp", path)).unwrap().is_none());
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let secret = dir.join("secret.json");
+            let secret = secret.to_str().unwrap();
+            write_private_file_atomic(secret, b"key").unwrap();
+            let mode = fs::metadata(secret).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        // a directory can't be read as a file
+        assert!(read_file_opt(dir.to_str().unwrap()).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
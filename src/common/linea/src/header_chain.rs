@@ -0,0 +1,479 @@
+use std::prelude::v1::*;
+
+use base::{format::debug, trace::Alive};
+use eth_tools::{ExecutionClient, RpcClient};
+use eth_types::{BlockHeader, SH256};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+use crate::{read_file_opt, write_file_atomic, BatchTask, CliqueConfig, Snapshot};
+
+const MAX_CACHED_HASHES: usize = 65536;
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HeaderChainConfig {
+    pub checkpoint_number: u64,
+    pub checkpoint_hash: SH256,
+    #[serde(default = "default_epoch")]
+    pub epoch: u64,
+    #[serde(default = "default_period")]
+    pub period: u64,
+}
+
+fn default_epoch() -> u64 {
+    CliqueConfig::default().epoch
+}
+
+fn default_period() -> u64 {
+    CliqueConfig::default().period
+}
+
+impl HeaderChainConfig {
+    pub fn clique(&self) -> CliqueConfig {
+        CliqueConfig {
+            period: self.period,
+            epoch: self.epoch,
+        }
+    }
+}
+
+/// The verified part of the header chain, it's saved after each walk so a
+/// restart continues from the last verified block instead of the checkpoint.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct HeaderChainSnapshot {
+    /// The trusted checkpoint the snapshot is derived from, a snapshot of
+    /// another checkpoint is discarded.
+    pub checkpoint_number: u64,
+    pub checkpoint_hash: SH256,
+    pub snapshot: Snapshot,
+    /// The verified block hashes, they're kept for the batches behind the
+    /// snapshot.
+    pub hashes: Vec<(u64, SH256)>,
+}
+
+pub struct HeaderChainStore {
+    path: String,
+}
+
+impl HeaderChainStore {
+    pub fn new(path: String) -> Self {
+        Self { path }
+    }
+
+    pub fn load(&self) -> Result<Option<HeaderChainSnapshot>, String> {
+        let data = match read_file_opt(&self.path)? {
+            Some(data) => data,
+            None => return Ok(None),
+        };
+        let snapshot = serde_json::from_slice(&data)
+            .map_err(|err| format!("header chain[{}] is corrupted: {}", self.path, err))?;
+        Ok(Some(snapshot))
+    }
+
+    pub fn save(&self, snapshot: &HeaderChainSnapshot) -> Result<(), String> {
+        let data = serde_json::to_vec(snapshot).map_err(debug)?;
+        write_file_atomic(&self.path, &data)
+    }
+}
+
+struct HeaderChainState {
+    max_cached: usize,
+    snapshot: Option<Snapshot>,
+    hashes: BTreeMap<u64, SH256>,
+}
+
+impl HeaderChainState {
+    fn new(max_cached: usize) -> Self {
+        Self {
+            max_cached,
+            snapshot: None,
+            hashes: BTreeMap::new(),
+        }
+    }
+
+    /// Takes the saved snapshot if it's derived from the same checkpoint and
+    /// clique config.
+    fn restore(&mut self, cfg: &HeaderChainConfig, saved: HeaderChainSnapshot) -> bool {
+        if saved.checkpoint_number != cfg.checkpoint_number
+            || saved.checkpoint_hash != cfg.checkpoint_hash
+            || saved.snapshot.config() != &cfg.clique()
+        {
+            return false;
+        }
+        self.snapshot = Some(saved.snapshot);
+        self.hashes = saved.hashes.into_iter().collect();
+        self.evict();
+        true
+    }
+
+    fn save(&self, cfg: &HeaderChainConfig) -> Option<HeaderChainSnapshot> {
+        Some(HeaderChainSnapshot {
+            checkpoint_number: cfg.checkpoint_number,
+            checkpoint_hash: cfg.checkpoint_hash,
+            snapshot: self.snapshot.clone()?,
+            hashes: self.hashes.iter().map(|(n, h)| (*n, *h)).collect(),
+        })
+    }
+
+    /// The hashes of the blocks if they're all verified.
+    fn lookup(&self, blocks: &[u64]) -> Option<BTreeMap<u64, SH256>> {
+        let mut out = BTreeMap::new();
+        for blk in blocks {
+            out.insert(*blk, *self.hashes.get(blk)?);
+        }
+        Some(out)
+    }
+
+    /// The snapshot to continue from, it can only go forward, so the blocks
+    /// before the cached ones are verified from the checkpoint again.
+    fn base(&self, start: u64) -> Option<Snapshot> {
+        let oldest = *self.hashes.keys().next()?;
+        match &self.snapshot {
+            Some(snapshot) if start >= oldest => Some(snapshot.clone()),
+            _ => None,
+        }
+    }
+
+    /// Keeps the furthest snapshot, the walks may run concurrently, and
+    /// returns the hashes of the blocks.
+    fn merge(
+        &mut self,
+        snapshot: Snapshot,
+        hashes: BTreeMap<u64, SH256>,
+        blocks: &[u64],
+    ) -> Result<BTreeMap<u64, SH256>, String> {
+        let advanced = match &self.snapshot {
+            Some(current) => snapshot.number > current.number,
+            None => true,
+        };
+        if advanced {
+            self.snapshot = Some(snapshot);
+        }
+        self.hashes.extend(hashes);
+        let out = match blocks.iter().find(|n| !self.hashes.contains_key(n)) {
+            Some(blk) => Err(format!("block[{}] not in the verified chain", blk)),
+            None => Ok(self.lookup(blocks).unwrap()),
+        };
+        self.evict();
+        out
+    }
+
+    fn evict(&mut self) {
+        while self.hashes.len() > self.max_cached {
+            let oldest = *self.hashes.keys().next().unwrap();
+            self.hashes.remove(&oldest);
+        }
+    }
+}
+
+fn checkpoint<F>(cfg: &HeaderChainConfig, fetch: &F) -> Result<Snapshot, String>
+where
+    F: Fn(u64) -> Result<BlockHeader, String>,
+{
+    let header = fetch(cfg.checkpoint_number)?;
+    let hash = header.hash();
+    if hash != cfg.checkpoint_hash {
+        return Err(format!(
+            "checkpoint[{}] hash mismatch, trusted: {:?}, remote: {:?}",
+            cfg.checkpoint_number, cfg.checkpoint_hash, hash
+        ));
+    }
+    Snapshot::from_checkpoint(cfg.clique(), &header)
+}
+
+/// Applies the headers after the snapshot up to `end`, the snapshot is a copy
+/// so a rejected header doesn't affect the shared one.
+fn walk<F>(
+    alive: &Alive,
+    mut snapshot: Snapshot,
+    end: u64,
+    fetch: &F,
+) -> Result<(Snapshot, BTreeMap<u64, SH256>), String>
+where
+    F: Fn(u64) -> Result<BlockHeader, String>,
+{
+    let mut hashes = BTreeMap::new();
+    hashes.insert(snapshot.number, snapshot.hash);
+    while snapshot.number < end {
+        if !alive.is_alive() {
+            return Err("header chain verification canceled".into());
+        }
+        let number = snapshot.number + 1;
+        let header = fetch(number)?;
+        snapshot.apply(&header)?;
+        hashes.insert(number, snapshot.hash);
+        if number % 1000 == 0 {
+            glog::info!("header chain verified to block {}", number);
+        }
+    }
+    Ok((snapshot, hashes))
+}
+
+/// Verifies the blocks against the shared state. The lock is only held to
+/// read and merge the state, the headers are fetched without it, so a long
+/// walk doesn't block the other jobs.
+///
+/// Returns the trusted hashes and whether the state is changed.
+fn verify<F>(
+    state: &Mutex<HeaderChainState>,
+    alive: &Alive,
+    cfg: &HeaderChainConfig,
+    blocks: &[u64],
+    fetch: F,
+) -> Result<(BTreeMap<u64, SH256>, bool), String>
+where
+    F: Fn(u64) -> Result<BlockHeader, String>,
+{
+    let start = match blocks.iter().min() {
+        Some(n) => *n,
+        None => return Ok((BTreeMap::new(), false)),
+    };
+    let end = *blocks.iter().max().unwrap();
+    if start < cfg.checkpoint_number {
+        return Err(format!(
+            "block[{}] is before the trusted checkpoint[{}]",
+            start, cfg.checkpoint_number
+        ));
+    }
+
+    let base = {
+        let state = state.lock().unwrap();
+        if let Some(out) = state.lookup(blocks) {
+            return Ok((out, false));
+        }
+        state.base(start)
+    };
+    let base = match base {
+        Some(base) => base,
+        None => checkpoint(cfg, &fetch)?,
+    };
+    let (snapshot, hashes) = walk(alive, base, end, &fetch)?;
+    let out = state.lock().unwrap().merge(snapshot, hashes, blocks)?;
+    Ok((out, true))
+}
+
+/// A light client of the L2 header chain.
+///
+/// Starting from the trusted checkpoint, every header returned by the L2
+/// endpoint is linked by `parent_hash` and checked against the clique signer
+/// set, so the headers used for proving can't be forged by the endpoint.
+pub struct HeaderChain<C: RpcClient> {
+    alive: Alive,
+    cfg: HeaderChainConfig,
+    client: ExecutionClient<C>,
+    store: HeaderChainStore,
+    state: Mutex<HeaderChainState>,
+}
+
+impl<C: RpcClient> HeaderChain<C> {
+    /// The saved snapshot is only a cache, one which can't be used is
+    /// dropped and the chain is verified from the checkpoint again.
+    pub fn new(
+        alive: &Alive,
+        cfg: HeaderChainConfig,
+        client: ExecutionClient<C>,
+        store: HeaderChainStore,
+    ) -> Self {
+        let mut state = HeaderChainState::new(MAX_CACHED_HASHES);
+        match store.load() {
+            Ok(Some(saved)) => {
+                let number = saved.snapshot.number;
+                match state.restore(&cfg, saved) {
+                    true => glog::info!("header chain resume from block {}", number),
+                    false => glog::warn!("header chain snapshot of another checkpoint, dropped"),
+                }
+            }
+            Ok(None) => {}
+            Err(err) => glog::warn!("{}, verify from the checkpoint", err),
+        }
+        Self {
+            alive: alive.clone(),
+            cfg,
+            client,
+            store,
+            state: Mutex::new(state),
+        }
+    }
+
+    pub fn verify_task(&self, task: &BatchTask) -> Result<BTreeMap<u64, SH256>, String> {
+        self.verify_blocks(&task.blocks)
+    }
+
+    /// Verifies the header chain up to the given blocks and returns their
+    /// trusted block hashes.
+    pub fn verify_blocks(&self, blocks: &[u64]) -> Result<BTreeMap<u64, SH256>, String> {
+        let (out, changed) = verify(&self.state, &self.alive, &self.cfg, blocks, |number| {
+            self.client.get_block_header(number.into()).map_err(debug)
+        })?;
+        if changed {
+            let saved = self.state.lock().unwrap().save(&self.cfg);
+            if let Some(saved) = saved {
+                if let Err(err) = self.store.save(&saved) {
+                    glog::error!("save header chain fail: {}", err);
+                }
+            }
+        }
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::clique::test::{genesis_header, next_block, seal, signers};
+
+    /// A chain of `len` blocks after the genesis, sealed by the signers in
+    /// turn.
+    fn chain(len: usize) -> (HeaderChainConfig, Vec<BlockHeader>) {
+        let keys = signers(3);
+        let genesis = genesis_header(&keys);
+        let cfg = HeaderChainConfig {
+            checkpoint_number: 0,
+            checkpoint_hash: genesis.hash(),
+            epoch: CliqueConfig::default().epoch,
+            period: CliqueConfig::default().period,
+        };
+        let mut snap = Snapshot::from_checkpoint(cfg.clique(), &genesis).unwrap();
+        let mut headers = vec![genesis];
+        for number in 1..=len {
+            let (prvkey, addr) = &keys[number % keys.len()];
+            let header = seal(prvkey, next_block(&snap, addr, Default::default(), false));
+            snap.apply(&header).unwrap();
+            headers.push(header);
+        }
+        (cfg, headers)
+    }
+
+    fn fetch(headers: &[BlockHeader]) -> impl Fn(u64) -> Result<BlockHeader, String> + '_ {
+        |number| match headers.get(number as usize) {
+            Some(header) => Ok(header.clone()),
+            None => Err(format!("block[{}] not found", number)),
+        }
+    }
+
+    fn cached(state: &Mutex<HeaderChainState>) -> Vec<u64> {
+        state.lock().unwrap().hashes.keys().cloned().collect()
+    }
+
+    #[test]
+    fn test_verify_from_checkpoint() {
+        let (cfg, headers) = chain(10);
+        let alive = Alive::new();
+        let state = Mutex::new(HeaderChainState::new(MAX_CACHED_HASHES));
+        let (hashes, changed) = verify(&state, &alive, &cfg, &[3, 5], fetch(&headers)).unwrap();
+        assert!(changed);
+        assert_eq!(hashes.len(), 2);
+        assert_eq!(hashes[&3], headers[3].hash());
+        assert_eq!(hashes[&5], headers[5].hash());
+
+        // the verified blocks don't need the headers again
+        let (hashes, changed) = verify(&state, &alive, &cfg, &[4], fetch(&[])).unwrap();
+        assert_eq!((hashes[&4], changed), (headers[4].hash(), false));
+
+        // a failed fetch keeps the verified part
+        let err = verify(&state, &alive, &cfg, &[10], fetch(&headers[..7])).unwrap_err();
+        assert!(err.contains("not found"), "{}", err);
+        assert_eq!(state.lock().unwrap().snapshot.as_ref().unwrap().number, 5);
+
+        // continues from the cached snapshot
+        let (hashes, _) = verify(&state, &alive, &cfg, &[10], fetch(&headers)).unwrap();
+        assert_eq!(hashes[&10], headers[10].hash());
+        assert!(verify(&state, &alive, &cfg, &[11], fetch(&headers)).is_err());
+
+        let mut bad_cfg = cfg.clone();
+        bad_cfg.checkpoint_hash = SH256::default();
+        let state = Mutex::new(HeaderChainState::new(MAX_CACHED_HASHES));
+        let err = verify(&state, &alive, &bad_cfg, &[3], fetch(&headers)).unwrap_err();
+        assert!(err.contains("hash mismatch"), "{}", err);
+    }
+
+    #[test]
+    fn test_broken_parent_hash() {
+        let (cfg, mut headers) = chain(5);
+        // a forged block 3 which is linked to a different block 2
+        headers[3].parent_hash = headers[1].hash();
+        let alive = Alive::new();
+        let state = Mutex::new(HeaderChainState::new(MAX_CACHED_HASHES));
+        let err = verify(&state, &alive, &cfg, &[5], fetch(&headers)).unwrap_err();
+        assert!(err.contains("unknown ancestor"), "{}", err);
+        assert!(state.lock().unwrap().snapshot.is_none());
+
+        // the rejected header doesn't reset the verified part
+        assert!(verify(&state, &alive, &cfg, &[2], fetch(&headers)).is_ok());
+        assert!(verify(&state, &alive, &cfg, &[5], fetch(&headers)).is_err());
+        assert_eq!(state.lock().unwrap().snapshot.as_ref().unwrap().number, 2);
+        assert_eq!(cached(&state), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn test_bad_seal() {
+        let (cfg, mut headers) = chain(5);
+        // the state root is not covered by the original seal
+        headers[4].state_root = SH256::default();
+        let alive = Alive::new();
+        let state = Mutex::new(HeaderChainState::new(MAX_CACHED_HASHES));
+        let err = verify(&state, &alive, &cfg, &[4], fetch(&headers)).unwrap_err();
+        assert!(err.contains("unauthorized signer"), "{}", err);
+    }
+
+    #[test]
+    fn test_cache_eviction() {
+        let (cfg, headers) = chain(12);
+        let alive = Alive::new();
+        let state = Mutex::new(HeaderChainState::new(4));
+        verify(&state, &alive, &cfg, &[12], fetch(&headers)).unwrap();
+        assert_eq!(cached(&state), vec![9, 10, 11, 12]);
+        assert!(verify(&state, &alive, &cfg, &[10], fetch(&[])).is_ok());
+
+        // the evicted blocks are verified again from the checkpoint, the
+        // snapshot stays at the tip
+        let (hashes, _) = verify(&state, &alive, &cfg, &[2], fetch(&headers)).unwrap();
+        assert_eq!(hashes[&2], headers[2].hash());
+        assert_eq!(cached(&state), vec![9, 10, 11, 12]);
+        assert_eq!(state.lock().unwrap().snapshot.as_ref().unwrap().number, 12);
+    }
+
+    #[test]
+    fn test_restore() {
+        let (cfg, headers) = chain(8);
+        let alive = Alive::new();
+        let state = Mutex::new(HeaderChainState::new(MAX_CACHED_HASHES));
+        verify(&state, &alive, &cfg, &[6], fetch(&headers)).unwrap();
+        let saved = state.lock().unwrap().save(&cfg).unwrap();
+
+        let dir = std::env::temp_dir().join(format!("linea-header-chain-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("header-chain.json");
+        let store = HeaderChainStore::new(path.to_str().unwrap().into());
+        assert!(store.load().unwrap().is_none());
+        store.save(&saved).unwrap();
+        let loaded = store.load().unwrap().unwrap();
+
+        // continues from the saved snapshot without the earlier headers
+        let mut restored = HeaderChainState::new(MAX_CACHED_HASHES);
+        assert!(restored.restore(&cfg, loaded.clone()));
+        let restored = Mutex::new(restored);
+        let (hashes, _) = verify(&restored, &alive, &cfg, &[3, 8], |number| match number {
+            7 | 8 => Ok(headers[number as usize].clone()),
+            _ => Err(format!("block[{}] fetched again", number)),
+        })
+        .unwrap();
+        assert_eq!(hashes[&3], headers[3].hash());
+        assert_eq!(hashes[&8], headers[8].hash());
+
+        // the snapshot of another checkpoint is dropped
+        let mut other = cfg.clone();
+        other.checkpoint_hash = SH256::default();
+        assert!(!HeaderChainState::new(MAX_CACHED_HASHES).restore(&other, loaded.clone()));
+        other = cfg.clone();
+        other.period += 1;
+        assert!(!HeaderChainState::new(MAX_CACHED_HASHES).restore(&other, loaded));
+
+        std::fs::write(&path, b"{\"checkpoint_number\":0").unwrap();
+        let err = store.load().unwrap_err();
+        assert!(err.contains("corrupted"), "{}", err);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
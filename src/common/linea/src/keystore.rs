@@ -0,0 +1,212 @@
+use std::prelude::v1::*;
+
+use base::format::debug;
+use crypto::Secp256k1PrivateKey;
+use serde::{Deserialize, Serialize};
+
+use crate::{read_file_opt, write_private_file_atomic};
+#[cfg(feature = "tstd")]
+use crate::write_file_atomic;
+
+/// The prover keys which survive the restart.
+#[derive(Clone, Default, Deserialize, Serialize)]
+pub struct ProverKeys {
+    /// The key which is attested on-chain.
+    pub attested: Option<Secp256k1PrivateKey>,
+    /// The key whose attestation report is submitted but not approved yet.
+    pub staging: Option<Secp256k1PrivateKey>,
+}
+
+pub trait KeyStore: Send + Sync {
+    fn load(&self) -> Result<Option<ProverKeys>, String>;
+    fn save(&self, keys: &ProverKeys) -> Result<(), String>;
+}
+
+/// Stores the keys in plaintext, only for the std build. The file is only
+/// readable by the owner.
+pub struct FileKeyStore {
+    path: String,
+}
+
+impl FileKeyStore {
+    pub fn new(path: String) -> Self {
+        Self { path }
+    }
+}
+
+impl KeyStore for FileKeyStore {
+    fn load(&self) -> Result<Option<ProverKeys>, String> {
+        // an unreadable keystore is an error, or the attested key is replaced
+        let data = match read_file_opt(&self.path)? {
+            Some(data) => data,
+            None => {
+                glog::info!("keystore[{}] not found", self.path);
+                return Ok(None);
+            }
+        };
+        let keys = serde_json::from_slice(&data)
+            .map_err(|err| format!("keystore[{}] is corrupted: {}", self.path, err))?;
+        Ok(Some(keys))
+    }
+
+    fn save(&self, keys: &ProverKeys) -> Result<(), String> {
+        let data = serde_json::to_vec(keys).map_err(debug)?;
+        write_private_file_atomic(&self.path, &data)
+    }
+}
+
+/// Seals the keys with the MRENCLAVE-bound seal key, so only the same
+/// enclave on the same CPU can read them back.
+#[cfg(feature = "tstd")]
+pub struct SealedKeyStore {
+    path: String,
+}
+
+#[cfg(feature = "tstd")]
+impl SealedKeyStore {
+    pub fn new(path: String) -> Self {
+        Self { path }
+    }
+}
+
+#[cfg(feature = "tstd")]
+impl KeyStore for SealedKeyStore {
+    fn load(&self) -> Result<Option<ProverKeys>, String> {
+        // an unreadable keystore is an error, or the attested key is replaced
+        let data = match read_file_opt(&self.path)? {
+            Some(data) => data,
+            None => {
+                glog::info!("keystore[{}] not found", self.path);
+                return Ok(None);
+            }
+        };
+        let data = sealing::unseal(&data)?;
+        let keys = serde_json::from_slice(&data)
+            .map_err(|err| format!("keystore[{}] is corrupted: {}", self.path, err))?;
+        Ok(Some(keys))
+    }
+
+    fn save(&self, keys: &ProverKeys) -> Result<(), String> {
+        let data = serde_json::to_vec(keys).map_err(debug)?;
+        let data = sealing::seal(&data)?;
+        write_file_atomic(&self.path, &data)
+    }
+}
+
+#[cfg(feature = "tstd")]
+mod sealing {
+    use std::prelude::v1::*;
+
+    use sgxlib::sgx_tcrypto::{rsgx_rijndael128GCM_decrypt, rsgx_rijndael128GCM_encrypt};
+    use sgxlib::sgx_trts::trts::rsgx_read_rand;
+    use sgxlib::sgx_types::*;
+
+    // the same masks as sgx_tseal
+    const SEAL_FLAGS_MASK: u64 = 0xFF00_0000_0000_000B;
+    const SEAL_MISC_MASK: u32 = 0xF000_0000;
+    const AAD: &[u8] = b"linea-prover-keys";
+
+    const KEY_ID_SIZE: usize = 32;
+    const IV_SIZE: usize = 12;
+    const MAC_SIZE: usize = 16;
+
+    fn seal_key(key_id: &[u8; KEY_ID_SIZE]) -> Result<sgx_key_128bit_t, String> {
+        let report = unsafe { &*sgx_self_report() };
+        let mut req = sgx_key_request_t::default();
+        req.key_name = SGX_KEYSELECT_SEAL;
+        req.key_policy = SGX_KEYPOLICY_MRENCLAVE;
+        req.isv_svn = report.body.isv_svn;
+        req.config_svn = report.body.config_svn;
+        req.cpu_svn = report.body.cpu_svn;
+        req.attribute_mask.flags = SEAL_FLAGS_MASK;
+        req.attribute_mask.xfrm = 0;
+        req.misc_mask = SEAL_MISC_MASK;
+        req.key_id.id = *key_id;
+        let mut key = sgx_key_128bit_t::default();
+        let ret = unsafe { sgx_get_key(&req, &mut key) };
+        if ret != sgx_status_t::SGX_SUCCESS {
+            return Err(format!("get seal key fail: {:?}", ret));
+        }
+        Ok(key)
+    }
+
+    fn random<const N: usize>() -> Result<[u8; N], String> {
+        let mut out = [0_u8; N];
+        rsgx_read_rand(&mut out).map_err(|err| format!("read rand fail: {:?}", err))?;
+        Ok(out)
+    }
+
+    /// key_id || iv || mac || ciphertext
+    pub fn seal(data: &[u8]) -> Result<Vec<u8>, String> {
+        let key_id = random::<KEY_ID_SIZE>()?;
+        let iv = random::<IV_SIZE>()?;
+        let key = seal_key(&key_id)?;
+        let mut ciphertext = vec![0_u8; data.len()];
+        let mut mac = [0_u8; MAC_SIZE];
+        rsgx_rijndael128GCM_encrypt(&key, data, &iv, AAD, &mut ciphertext, &mut mac)
+            .map_err(|err| format!("seal fail: {:?}", err))?;
+
+        let mut out = Vec::with_capacity(KEY_ID_SIZE + IV_SIZE + MAC_SIZE + data.len());
+        out.extend_from_slice(&key_id);
+        out.extend_from_slice(&iv);
+        out.extend_from_slice(&mac);
+        out.extend_from_slice(&ciphertext);
+        Ok(out)
+    }
+
+    pub fn unseal(data: &[u8]) -> Result<Vec<u8>, String> {
+        let header = KEY_ID_SIZE + IV_SIZE + MAC_SIZE;
+        if data.len() < header {
+            return Err(format!("sealed data too short: {} bytes", data.len()));
+        }
+        let mut key_id = [0_u8; KEY_ID_SIZE];
+        key_id.copy_from_slice(&data[..KEY_ID_SIZE]);
+        let mut mac = [0_u8; MAC_SIZE];
+        mac.copy_from_slice(&data[KEY_ID_SIZE + IV_SIZE..header]);
+        let iv = &data[KEY_ID_SIZE..KEY_ID_SIZE + IV_SIZE];
+
+        let key = seal_key(&key_id)?;
+        let mut plaintext = vec![0_u8; data.len() - header];
+        rsgx_rijndael128GCM_decrypt(&key, &data[header..], iv, AAD, &mac, &mut plaintext)
+            .map_err(|err| format!("unseal fail: {:?}", err))?;
+        Ok(plaintext)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crypto::secp256k1_gen_keypair;
+
+    #[test]
+    fn test_file_key_store() {
+        let dir = std::env::temp_dir().join(format!("linea-keystore-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("prover-keys.json");
+        let store = FileKeyStore::new(path.to_str().unwrap().into());
+        assert!(store.load().unwrap().is_none());
+
+        let (attested, pubkey) = secp256k1_gen_keypair();
+        let keys = ProverKeys {
+            attested: Some(attested),
+            staging: None,
+        };
+        store.save(&keys).unwrap();
+        let loaded = store.load().unwrap().unwrap();
+        let addr = loaded.attested.unwrap().public().eth_accountid();
+        assert_eq!(addr, pubkey.eth_accountid());
+        assert!(loaded.staging.is_none());
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        // a corrupted keystore shouldn't be replaced by a new key
+        std::fs::write(&path, b"{\"attested\":").unwrap();
+        assert!(store.load().is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
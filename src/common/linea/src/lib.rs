@@ -9,12 +9,50 @@ pub use block_executor::*;
 mod engine;
 pub use engine::*;
 
+mod clique;
+pub use clique::*;
+
+mod header_chain;
+pub use header_chain::*;
+
 mod batch_task;
 pub use batch_task::*;
 
+mod checkpoint;
+pub use checkpoint::*;
+
+mod file;
+pub use file::*;
+
+mod batch_data;
+pub use batch_data::*;
+
+mod blob;
+pub use blob::*;
+
+mod message;
+pub use message::*;
+
 mod verifier;
 pub use verifier::*;
 
+mod verifier_error;
+pub use verifier_error::*;
+
+#[cfg(feature = "sim-attestation")]
+mod sim_attestation;
+#[cfg(feature = "sim-attestation")]
+pub use sim_attestation::*;
+
+mod poe;
+pub use poe::*;
+
+mod rollup;
+pub use rollup::*;
+
+mod keystore;
+pub use keystore::*;
+
 mod prover;
 pub use prover::*;
 
@@ -25,4 +63,7 @@ mod cache;
 pub use cache::*;
 
 mod trie;
-pub use trie::*;
\ No newline at end of file
+pub use trie::*;
+
+mod witness;
+pub use witness::*;
\ No newline at end of file
@@ -0,0 +1,415 @@
+use std::prelude::v1::*;
+
+use core::fmt;
+use crypto::keccak_hash;
+use eth_types::{Receipt, SH160, SH256, SU256};
+use serde::Deserialize;
+
+use crate::AbiReader;
+
+pub const MESSAGE_SENT_SIG: &str =
+    "MessageSent(address,address,uint256,uint256,uint256,bytes,bytes32)";
+pub const L1L2_MESSAGE_HASHES_ADDED_SIG: &str = "L1L2MessageHashesAddedToInbox(bytes32[])";
+pub const ROLLING_HASH_UPDATED_SIG: &str = "RollingHashUpdated(uint256,bytes32)";
+
+/// The verifier keeps one withdrawal root per batch, so a batch whose
+/// messages don't fit in one tree can never be committed, retrying won't help.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MessageTreeError {
+    Overflow { messages: usize, depth: usize },
+}
+
+impl fmt::Display for MessageTreeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Overflow { messages, depth } => write!(
+                f,
+                "message tree overflow: {} messages exceed a single tree of depth {}",
+                messages, depth
+            ),
+        }
+    }
+}
+
+impl From<MessageTreeError> for String {
+    fn from(err: MessageTreeError) -> String {
+        format!("{}", err)
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MessageServiceConfig {
+    /// The L2MessageService contract on L2
+    pub l2_contract: SH160,
+    #[serde(default = "default_tree_depth")]
+    pub tree_depth: usize,
+}
+
+fn default_tree_depth() -> usize {
+    5
+}
+
+/// Collects the hashes of the L2->L1 messages sent in the receipts.
+pub fn collect_l2_messages(service: &SH160, receipts: &[Receipt]) -> Vec<SH256> {
+    let topic = solidity::encode_eventsig(MESSAGE_SENT_SIG);
+    let mut out = Vec::new();
+    for receipt in receipts {
+        for log in &receipt.logs {
+            // _from, _to and _messageHash are indexed
+            if &log.address == service && log.topics.len() == 4 && log.topics[0] == topic {
+                out.push(log.topics[3]);
+            }
+        }
+    }
+    out
+}
+
+fn hash_pair(left: &SH256, right: &SH256) -> SH256 {
+    let mut buf = [0_u8; 64];
+    buf[..32].copy_from_slice(left.as_bytes());
+    buf[32..].copy_from_slice(right.as_bytes());
+    keccak_hash(&buf).into()
+}
+
+/// Builds the sparse merkle tree of the message hashes, the leaves are padded
+/// with zero hashes to `2^depth`.
+pub fn message_merkle_root(hashes: &[SH256], depth: usize) -> Result<SH256, String> {
+    let capacity = 1_usize << depth;
+    if hashes.len() > capacity {
+        return Err(format!(
+            "too many messages for the merkle tree: {} > {}",
+            hashes.len(),
+            capacity
+        ));
+    }
+    let mut level = hashes.to_vec();
+    level.resize(capacity, SH256::default());
+    for _ in 0..depth {
+        level = level
+            .chunks(2)
+            .map(|pair| hash_pair(&pair[0], &pair[1]))
+            .collect();
+    }
+    Ok(level[0])
+}
+
+/// Splits the message hashes into trees of `2^depth` leaves and returns their
+/// roots, this is how the messages of a batch are anchored on L1.
+pub fn message_merkle_roots(hashes: &[SH256], depth: usize) -> Result<Vec<SH256>, String> {
+    let capacity = 1_usize << depth;
+    let mut roots = Vec::with_capacity((hashes.len() + capacity - 1) / capacity);
+    for chunk in hashes.chunks(capacity) {
+        roots.push(message_merkle_root(chunk, depth)?);
+    }
+    Ok(roots)
+}
+
+/// Computes the withdrawal root committed by the Poe, it's the message tree
+/// root of the whole batch, or zero if no message is sent.
+///
+/// Linea splits the messages into several roots when they exceed `2^depth`,
+/// but the Poe only carries one, such a batch fails with
+/// `MessageTreeError::Overflow`.
+pub fn withdrawal_root(hashes: &[SH256], depth: usize) -> Result<SH256, MessageTreeError> {
+    if hashes.len() == 0 {
+        return Ok(SH256::default());
+    }
+    match message_merkle_root(hashes, depth) {
+        Ok(root) => Ok(root),
+        Err(_) => Err(MessageTreeError::Overflow {
+            messages: hashes.len(),
+            depth,
+        }),
+    }
+}
+
+/// The L1->L2 messages anchored on L2 by one `anchorL1L2MessageHashes` call.
+#[derive(Clone, Debug)]
+pub struct L1MessageAnchor {
+    pub message_hashes: Vec<SH256>,
+    /// The number of the last anchored message.
+    pub message_number: u64,
+    pub rolling_hash: SH256,
+}
+
+impl L1MessageAnchor {
+    pub fn first_message_number(&self) -> u64 {
+        self.message_number + 1 - self.message_hashes.len() as u64
+    }
+}
+
+/// Collects the L1->L2 message anchoring events in the receipts.
+pub fn collect_l1_anchors(
+    service: &SH160,
+    receipts: &[Receipt],
+) -> Result<Vec<L1MessageAnchor>, String> {
+    let added_topic = solidity::encode_eventsig(L1L2_MESSAGE_HASHES_ADDED_SIG);
+    let updated_topic = solidity::encode_eventsig(ROLLING_HASH_UPDATED_SIG);
+    let mut out = Vec::new();
+    for receipt in receipts {
+        let mut hashes = None;
+        let mut updated = None;
+        for log in &receipt.logs {
+            if &log.address != service || log.topics.len() == 0 {
+                continue;
+            }
+            if log.topics[0] == added_topic {
+                let data = AbiReader::new(&log.data);
+                let array = data.slice(data.offset(0)?)?;
+                let len = array.uint(0)?;
+                // each hash takes a word, don't trust the length for the allocation
+                if len > log.data.len() / 32 {
+                    return Err(format!(
+                        "tx[{:?}] message hashes out of range: {}",
+                        receipt.transaction_hash, len
+                    ));
+                }
+                let mut list = Vec::with_capacity(len);
+                for i in 0..len {
+                    list.push(array.hash(32 + i * 32)?);
+                }
+                hashes = Some(list);
+            } else if log.topics[0] == updated_topic && log.topics.len() == 3 {
+                let number = SU256::from_big_endian(log.topics[1].as_bytes());
+                updated = Some((number.as_u64(), log.topics[2]));
+            }
+        }
+        match (hashes, updated) {
+            (Some(message_hashes), Some((message_number, rolling_hash))) => {
+                if message_hashes.len() == 0 || message_hashes.len() as u64 > message_number {
+                    return Err(format!(
+                        "tx[{:?}] invalid anchoring, messages: {}, number: {}",
+                        receipt.transaction_hash,
+                        message_hashes.len(),
+                        message_number
+                    ));
+                }
+                out.push(L1MessageAnchor {
+                    message_hashes,
+                    message_number,
+                    rolling_hash,
+                });
+            }
+            (None, None) => {}
+            _ => {
+                return Err(format!(
+                    "tx[{:?}] incomplete message anchoring events",
+                    receipt.transaction_hash
+                ))
+            }
+        }
+    }
+    Ok(out)
+}
+
+/// keccak256(abi.encode(rollingHash, messageHash)) for each message.
+pub fn rolling_hash(prev: &SH256, hashes: &[SH256]) -> SH256 {
+    let mut rolling_hash = *prev;
+    for hash in hashes {
+        rolling_hash = hash_pair(&rolling_hash, hash);
+    }
+    rolling_hash
+}
+
+/// Replays the anchored messages from the previous rolling hash, and returns
+/// the final message number and its rolling hash.
+pub fn verify_l1_anchors(
+    prev_rolling_hash: &SH256,
+    anchors: &[L1MessageAnchor],
+) -> Result<(u64, SH256), String> {
+    let mut current = *prev_rolling_hash;
+    let mut next_number = match anchors.first() {
+        Some(anchor) => anchor.first_message_number(),
+        None => return Err("no message anchored".into()),
+    };
+    for anchor in anchors {
+        if anchor.first_message_number() != next_number {
+            return Err(format!(
+                "message number not continuous, expect: {}, got: {}",
+                next_number,
+                anchor.first_message_number()
+            ));
+        }
+        current = rolling_hash(&current, &anchor.message_hashes);
+        if current != anchor.rolling_hash {
+            return Err(format!(
+                "message[{}] rolling hash mismatch, event: {:?}, calculated: {:?}",
+                anchor.message_number, anchor.rolling_hash, current
+            ));
+        }
+        next_number = anchor.message_number + 1;
+    }
+    Ok((next_number - 1, current))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_message_merkle_root() {
+        let mut h1 = SH256::default();
+        h1.0[31] = 1;
+        let mut h2 = SH256::default();
+        h2.0[31] = 2;
+        let root = message_merkle_root(&[h1, h2], 2).unwrap();
+        assert_eq!(
+            root,
+            "0x893760ec5b5bee236f29e85aef64f17139c3c1b7ff24ce64eb6315fca0f2485b".into()
+        );
+        assert!(message_merkle_root(&[h1, h2, h1], 1).is_err());
+        assert_eq!(message_merkle_roots(&[h1, h2, h1], 1).unwrap().len(), 2);
+        assert_eq!(withdrawal_root(&[], 5).unwrap(), SH256::default());
+        assert_eq!(withdrawal_root(&[h1, h2], 1).unwrap(), hash_pair(&h1, &h2));
+        assert_eq!(
+            withdrawal_root(&[h1, h2, h1], 1),
+            Err(MessageTreeError::Overflow {
+                messages: 3,
+                depth: 1
+            })
+        );
+    }
+
+    #[test]
+    fn test_rolling_hash() {
+        let mut h1 = SH256::default();
+        h1.0[31] = 1;
+        let mut h2 = SH256::default();
+        h2.0[31] = 2;
+        let expect: SH256 =
+            "0x6b7cf3666cb877c85468a54a4b1507c9e2cdc58f77e1c2709d37b70cc337c4e9".into();
+        assert_eq!(rolling_hash(&SH256::default(), &[h1, h2]), expect);
+
+        let anchors = vec![
+            L1MessageAnchor {
+                message_hashes: vec![h1],
+                message_number: 1,
+                rolling_hash: rolling_hash(&SH256::default(), &[h1]),
+            },
+            L1MessageAnchor {
+                message_hashes: vec![h2],
+                message_number: 2,
+                rolling_hash: expect,
+            },
+        ];
+        assert_eq!(
+            verify_l1_anchors(&SH256::default(), &anchors).unwrap(),
+            (2, expect)
+        );
+        assert!(verify_l1_anchors(&SH256::default(), &anchors[1..]).is_err());
+    }
+
+    const SERVICE: &str = "0x508ca82df566dcd1b0de8296e70a96332cd644ec";
+
+    fn word(n: u64) -> SH256 {
+        let mut hash = SH256::default();
+        hash.0[24..].copy_from_slice(&n.to_be_bytes());
+        hash
+    }
+
+    fn hex(data: &[u8]) -> String {
+        let hex: String = data.iter().map(|b| format!("{:02x}", b)).collect();
+        format!("0x{}", hex)
+    }
+
+    fn log(address: &str, topics: Vec<SH256>, data: Vec<u8>) -> serde_json::Value {
+        serde_json::json!({
+            "address": address,
+            "topics": topics,
+            "data": hex(&data),
+            "blockNumber": "0x10",
+            "blockHash": SH256::from([0xbb; 32]),
+            "transactionHash": SH256::from([0xaa; 32]),
+            "transactionIndex": "0x0",
+            "logIndex": "0x0",
+            "removed": false,
+        })
+    }
+
+    fn receipt(logs: Vec<serde_json::Value>) -> Receipt {
+        serde_json::from_value(serde_json::json!({
+            "type": "0x2",
+            "transactionHash": SH256::from([0xaa; 32]),
+            "transactionIndex": "0x0",
+            "blockHash": SH256::from([0xbb; 32]),
+            "blockNumber": "0x10",
+            "from": "0x1111111111111111111111111111111111111111",
+            "to": SERVICE,
+            "cumulativeGasUsed": "0x5208",
+            "gasUsed": "0x5208",
+            "effectiveGasPrice": "0x1",
+            "contractAddress": null,
+            "logs": logs,
+            "logsBloom": hex(&[0_u8; 256]),
+            "status": "0x1",
+        }))
+        .unwrap()
+    }
+
+    /// abi.encode(bytes32[]) of the `L1L2MessageHashesAddedToInbox` data.
+    fn hashes_data(hashes: &[SH256]) -> Vec<u8> {
+        let mut data = word(0x20).0.to_vec();
+        data.extend_from_slice(word(hashes.len() as u64).as_bytes());
+        for hash in hashes {
+            data.extend_from_slice(hash.as_bytes());
+        }
+        data
+    }
+
+    #[test]
+    fn test_collect_l2_messages() {
+        let service: SH160 = SERVICE.into();
+        let topic = solidity::encode_eventsig(MESSAGE_SENT_SIG);
+        let hash = SH256::from([0x33; 32]);
+        let receipts = vec![receipt(vec![
+            log(SERVICE, vec![topic, word(1), word(2), hash], vec![]),
+            // not from the message service
+            log(
+                "0x1111111111111111111111111111111111111111",
+                vec![topic, word(1), word(2), word(3)],
+                vec![],
+            ),
+            // _messageHash is not indexed
+            log(SERVICE, vec![topic, word(1), word(2)], vec![]),
+        ])];
+        assert_eq!(collect_l2_messages(&service, &receipts), vec![hash]);
+    }
+
+    #[test]
+    fn test_collect_l1_anchors() {
+        let service: SH160 = SERVICE.into();
+        let added = solidity::encode_eventsig(L1L2_MESSAGE_HASHES_ADDED_SIG);
+        let updated = solidity::encode_eventsig(ROLLING_HASH_UPDATED_SIG);
+        let (h1, h2) = (word(1), word(2));
+        let rolling = rolling_hash(&SH256::default(), &[h1, h2]);
+        let added_log = log(SERVICE, vec![added], hashes_data(&[h1, h2]));
+        // messageNumber and rollingHash are indexed
+        let updated_log = log(SERVICE, vec![updated, word(2), rolling], vec![]);
+
+        let receipts = vec![receipt(vec![added_log.clone(), updated_log.clone()])];
+        let anchors = collect_l1_anchors(&service, &receipts).unwrap();
+        assert_eq!(anchors.len(), 1);
+        assert_eq!(anchors[0].message_hashes, vec![h1, h2]);
+        assert_eq!(anchors[0].message_number, 2);
+        assert_eq!(anchors[0].first_message_number(), 1);
+        assert_eq!(anchors[0].rolling_hash, rolling);
+        assert_eq!(
+            verify_l1_anchors(&SH256::default(), &anchors).unwrap(),
+            (2, rolling)
+        );
+
+        // only one of the two events is emitted
+        for logs in [vec![added_log.clone()], vec![updated_log.clone()]] {
+            let err = collect_l1_anchors(&service, &[receipt(logs)]).unwrap_err();
+            assert!(err.contains("incomplete"), "{}", err);
+        }
+
+        // the array length exceeds the data
+        let mut data = hashes_data(&[h1, h2]);
+        data[63] = 0xff;
+        let overflow = log(SERVICE, vec![added], data);
+        let err = collect_l1_anchors(&service, &[receipt(vec![overflow, updated_log])]);
+        assert!(err.unwrap_err().contains("out of range"));
+    }
+}
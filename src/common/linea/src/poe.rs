@@ -0,0 +1,113 @@
+use std::prelude::v1::*;
+
+use crypto::keccak_hash;
+use eth_types::{SH160, SH256, SU256};
+use evm_executor::Poe;
+
+use crate::AbiReader;
+
+pub const POE_SIGNATURE_LENGTH: usize = 65;
+
+/// The fields decoded from the Poe report passed to `SGXVerifier.commitBatch`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PoeReport {
+    pub batch_hash: SH256,
+    pub state_hash: SH256,
+    pub prev_state_root: SH256,
+    pub new_state_root: SH256,
+    pub withdrawal_root: SH256,
+    pub signature: Vec<u8>,
+}
+
+impl PoeReport {
+    /// abi.decode(poe, (bytes32, bytes32, bytes32, bytes32, bytes32, bytes))
+    pub fn decode(data: &[u8]) -> Result<Self, String> {
+        let data = AbiReader::new(data);
+        Ok(Self {
+            batch_hash: data.hash(0)?,
+            state_hash: data.hash(32)?,
+            prev_state_root: data.hash(64)?,
+            new_state_root: data.hash(96)?,
+            withdrawal_root: data.hash(128)?,
+            signature: data.bytes(data.offset(160)?)?.into(),
+        })
+    }
+
+    /// keccak256(abi.encode(layer2ChainId, batchHash, stateHash,
+    /// prevStateRoot, newStateRoot, withdrawalRoot, new bytes(65)))
+    pub fn message_hash(&self, chain_id: u64) -> [u8; 32] {
+        let mut buf = Vec::with_capacity(32 * 11);
+        let mut word = [0_u8; 32];
+        SU256::from(chain_id).to_big_endian(&mut word);
+        buf.extend_from_slice(&word);
+        buf.extend_from_slice(self.batch_hash.as_bytes());
+        buf.extend_from_slice(self.state_hash.as_bytes());
+        buf.extend_from_slice(self.prev_state_root.as_bytes());
+        buf.extend_from_slice(self.new_state_root.as_bytes());
+        buf.extend_from_slice(self.withdrawal_root.as_bytes());
+        // the offset and the length of the empty signature
+        SU256::from(32 * 7).to_big_endian(&mut word);
+        buf.extend_from_slice(&word);
+        SU256::from(POE_SIGNATURE_LENGTH).to_big_endian(&mut word);
+        buf.extend_from_slice(&word);
+        buf.resize(buf.len() + 96, 0);
+        keccak_hash(&buf)
+    }
+
+    /// Recovers the prover who signed the report, the same as `SGXVerifier.recoverPoe`.
+    pub fn recover(&self, chain_id: u64) -> Result<SH160, String> {
+        if self.signature.len() != POE_SIGNATURE_LENGTH {
+            return Err("invalid signature length".into());
+        }
+        let mut sig = [0_u8; POE_SIGNATURE_LENGTH];
+        sig.copy_from_slice(&self.signature);
+        sig[64] = match sig[64] {
+            0 | 1 => sig[64],
+            27 | 28 => sig[64] - 27,
+            _ => return Err("invalid v value".into()),
+        };
+        let msg = self.message_hash(chain_id);
+        let pub_key_array =
+            crypto::secp256k1_ecdsa_recover(&sig, &msg).ok_or("ECDSA: invalid signature")?;
+        let pub_key = crypto::Secp256k1PublicKey::from_raw_bytes(&pub_key_array);
+        Ok(pub_key.eth_accountid().into())
+    }
+}
+
+/// Recovers the signer of the Poe without touching the chain.
+pub fn recover_poe_signer(chain_id: u64, poe: &Poe) -> Result<SH160, String> {
+    PoeReport::decode(&poe.encode())?.recover(chain_id)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn word(n: u8) -> SH256 {
+        let mut hash = SH256::default();
+        hash.0[31] = n;
+        hash
+    }
+
+    #[test]
+    fn test_poe_message_hash() {
+        let report = PoeReport {
+            batch_hash: word(1),
+            state_hash: word(2),
+            prev_state_root: word(3),
+            new_state_root: word(4),
+            withdrawal_root: word(5),
+            signature: vec![0; POE_SIGNATURE_LENGTH],
+        };
+        let expect: SH256 =
+            "0xd358fc88110e4b144ff0ebfd7f5e07b7d3aeb6e1ac3200f1956b98da68991610".into();
+        assert_eq!(SH256::from(report.message_hash(1)), expect);
+
+        let mut bad_v = report.clone();
+        bad_v.signature[64] = 29;
+        assert!(bad_v.recover(1).is_err());
+        let mut bad_len = report.clone();
+        bad_len.signature.pop();
+        assert!(bad_len.recover(1).is_err());
+    }
+}
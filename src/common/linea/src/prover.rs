@@ -10,16 +10,25 @@ use std::sync::{
 };
 use std::time::Duration;
 
-use crate::Verifier;
+use crate::{KeyStore, ProverKeys, Verifier};
 
 pub struct Prover {
     alive: Alive,
     prvkey: Mutex<Secp256k1PrivateKey>,
     attested: AtomicBool,
+    store: Option<Box<dyn KeyStore>>,
+    attested_hooks: Mutex<Vec<Box<dyn Fn(&SH160) + Send + Sync>>>,
+    /// The key waiting for the attestation approval.
+    staging: Mutex<Option<SH160>>,
+    /// Set by the operator to attest a new key before the current one
+    /// expires.
+    rotate: AtomicBool,
 }
 
 impl Prover {
-    pub fn new(alive: Alive) -> Prover {
+    /// The keys are persisted to the `store` if provided, so an attested key
+    /// can be reused after the restart.
+    pub fn new(alive: Alive, store: Option<Box<dyn KeyStore>>) -> Prover {
         let (prvkey, pubkey) = secp256k1_gen_keypair();
         let prover_pubkey: SH160 = pubkey.eth_accountid().into();
         glog::info!("prover pubkey: {:?}", prover_pubkey);
@@ -27,6 +36,10 @@ impl Prover {
             alive,
             prvkey: Mutex::new(prvkey),
             attested: AtomicBool::new(false),
+            store,
+            attested_hooks: Mutex::new(Vec::new()),
+            staging: Mutex::new(None),
+            rotate: AtomicBool::new(false),
         }
     }
 
@@ -35,6 +48,14 @@ impl Prover {
         prvkey.clone()
     }
 
+    pub fn address(&self) -> SH160 {
+        self.get_prvkey().public().eth_accountid().into()
+    }
+
+    pub fn staging_address(&self) -> Option<SH160> {
+        *self.staging.lock().unwrap()
+    }
+
     pub fn wait_attested(&self, alive: &Alive) -> bool {
         if self.is_attested() {
             return true;
@@ -51,10 +72,132 @@ impl Prover {
         self.attested.load(Ordering::SeqCst)
     }
 
+    /// Called when the verifier rejects the key, the tasks will wait for the
+    /// monitor to get a new key attested.
+    pub fn mark_unattested(&self) {
+        self.attested.store(false, Ordering::SeqCst);
+    }
+
+    /// Asks the monitor to attest a new key, the current key keeps signing
+    /// until the new one is approved.
+    pub fn request_rotation(&self) -> Result<(), String> {
+        if let Some(staging) = self.staging_address() {
+            return Err(format!("prover[{:?}] is waiting for the approval", staging));
+        }
+        if !self.is_attested() {
+            return Err("prover is not attested yet".into());
+        }
+        self.rotate.store(true, Ordering::SeqCst);
+        Ok(())
+    }
+
+    /// Sleeps up to `ms`, wakes up early if a rotation is requested.
+    fn sleep_until_rotation(&self, ms: u64) {
+        let mut slept = 0;
+        while slept < ms && !self.rotate.load(Ordering::SeqCst) {
+            if !self.alive.sleep_ms(1000.min(ms - slept)) {
+                return;
+            }
+            slept += 1000;
+        }
+    }
+
+    /// Called with the prover address whenever a new key becomes attested.
+    pub fn on_attested<F>(&self, f: F)
+    where
+        F: Fn(&SH160) + Send + Sync + 'static,
+    {
+        self.attested_hooks.lock().unwrap().push(Box::new(f));
+    }
+
     fn update_prvkey(&self, new: Secp256k1PrivateKey) {
-        let mut prvkey = self.prvkey.lock().unwrap();
-        *prvkey = new;
-        self.attested.store(true, Ordering::SeqCst);
+        {
+            let mut prvkey = self.prvkey.lock().unwrap();
+            *prvkey = new;
+            self.attested.store(true, Ordering::SeqCst);
+        }
+        let prover: SH160 = new.public().eth_accountid().into();
+        for hook in self.attested_hooks.lock().unwrap().iter() {
+            hook(&prover);
+        }
+    }
+
+    fn save_keys(&self, staging: Option<&Secp256k1PrivateKey>) {
+        *self.staging.lock().unwrap() = staging.map(|key| key.public().eth_accountid().into());
+        let store = match &self.store {
+            Some(store) => store,
+            None => return,
+        };
+        let keys = ProverKeys {
+            attested: match self.is_attested() {
+                true => Some(self.get_prvkey()),
+                false => None,
+            },
+            staging: staging.cloned(),
+        };
+        if let Err(err) = store.save(&keys) {
+            glog::error!("save prover keys fail: {}", err);
+        }
+    }
+
+    /// Reloads the persisted keys, the one still attested on-chain becomes
+    /// the current key. Returns the staging key which is not approved yet.
+    fn restore_keys(
+        &self,
+        verifier: &Verifier<Arc<MixRpcClient>, EthereumEngineTypes>,
+    ) -> Option<Secp256k1PrivateKey> {
+        let keys = match self.store.as_ref()?.load() {
+            Ok(Some(keys)) => keys,
+            Ok(None) => return None,
+            Err(err) => {
+                // a new key would overwrite the attested one, let the
+                // operator fix the keystore
+                glog::error!("load prover keys fail: {}", err);
+                self.alive.shutdown();
+                return None;
+            }
+        };
+        let validity_secs = match verifier.attest_validity_seconds() {
+            Ok(secs) => secs,
+            Err(err) => {
+                glog::error!("getting attest_validity_seconds fail: {:?}", err);
+                return keys.staging;
+            }
+        };
+        let now = base::time::now().as_secs();
+        let is_attested = |key: &Secp256k1PrivateKey| {
+            let prover: SH160 = key.public().eth_accountid().into();
+            match verifier.prover_status(&prover) {
+                Ok(attested_time) => attested_time + validity_secs > now,
+                Err(err) => {
+                    glog::error!("getting prover[{:?}] status fail: {:?}", prover, err);
+                    false
+                }
+            }
+        };
+
+        // the staging key is newer, prefer it if it's approved
+        if let Some(staging) = keys.staging {
+            if is_attested(&staging) {
+                self.update_prvkey(staging);
+                self.save_keys(None);
+                glog::info!(
+                    "restored prover[{:?}] from the staging key",
+                    staging.public().eth_accountid()
+                );
+                return None;
+            }
+        }
+        if let Some(attested) = keys.attested {
+            let prover = attested.public().eth_accountid();
+            if is_attested(&attested) {
+                self.update_prvkey(attested);
+                glog::info!("restored prover[{:?}]", prover);
+            } else {
+                glog::info!("prover[{:?}] is expired, discarded", prover);
+            }
+        }
+        keys.staging
     }
 
     pub fn monitor_attested<F>(
@@ -68,7 +211,12 @@ impl Prover {
         let mut attested_validity_secs;
         let mut last_submit = None;
         let mut submit_cooldown = Duration::from_secs(180);
-        let mut staging_key = None;
+        let mut staging_key = self.restore_keys(verifier);
+        if let Some(key) = &staging_key {
+            *self.staging.lock().unwrap() = Some(key.public().eth_accountid().into());
+            // the report was submitted before the restart, wait for it
+            last_submit = Some(Time::now());
+        }
         while self.alive.is_alive() {
             let prvkey = self.get_prvkey();
             let prvkey = staging_key.as_ref().unwrap_or(&prvkey);
@@ -103,9 +251,11 @@ impl Prover {
             }
 
             let need_attestation = attested_time + attested_validity_secs / 2 < now;
-            if !need_attestation {
+            let rotate = staging_key.is_none() && self.rotate.load(Ordering::SeqCst);
+            if !need_attestation && !rotate {
                 if let Some(staging_key) = staging_key.take() {
                     self.update_prvkey(staging_key);
+                    self.save_keys(None);
                     glog::info!(
                         "prover[{:?}] is attested...",
                         staging_key.public().eth_accountid()
@@ -113,12 +263,14 @@ impl Prover {
                 } else {
                     glog::info!("prover[{:?}] is attested...", prover);
                 }
-                self.alive
-                    .sleep_ms(60.min(attested_validity_secs / 2) * 1000);
+                self.sleep_until_rotation(60.min(attested_validity_secs / 2) * 1000);
                 continue;
             }
 
-            let need_attestation = if let Some(last_submit) = &last_submit {
+            let need_attestation = if rotate {
+                glog::info!("rotating prover[{:?}]", prover);
+                true
+            } else if let Some(last_submit) = &last_submit {
                 Time::now() > *last_submit + submit_cooldown
             } else {
                 true
@@ -137,12 +289,14 @@ impl Prover {
                     }
                 };
                 if let Err(err) = verifier.submit_attestation_report(relay, &new_prover, &report) {
-                    glog::info!("submit attestation report fail: {:?}", err);
+                    glog::info!("submit attestation report fail: {}", err);
                     self.alive.sleep_ms(1000);
                     continue;
                 }
                 last_submit = Some(Time::now());
+                self.rotate.store(false, Ordering::SeqCst);
                 staging_key = Some(new_prover_prvkey);
+                self.save_keys(staging_key.as_ref());
                 glog::info!("attestation report submitted -> {:?}", new_prover);
             } else {
                 glog::info!(
@@ -0,0 +1,29 @@
+use std::prelude::v1::*;
+
+use eth_tools::{EthCall, ExecutionClient, RpcClient, RpcError};
+use eth_types::{BlockSelector, SH160, SH256, SU256};
+
+/// The read-only client of the LineaRollup contract on L1.
+#[derive(Debug, Clone)]
+pub struct Rollup<C: RpcClient> {
+    el: ExecutionClient<C>,
+    to: SH160,
+}
+
+impl<C: RpcClient> Rollup<C> {
+    pub fn new(el: ExecutionClient<C>, contract: SH160) -> Self {
+        Self { el, to: contract }
+    }
+
+    /// The rolling hash of the L1->L2 messages recorded at `rollingHashes(number)`.
+    pub fn rolling_hash(&self, message_number: u64) -> Result<SH256, RpcError> {
+        let mut encoder = solidity::Encoder::new("rollingHashes");
+        encoder.add(&SU256::from(message_number));
+        let call = EthCall {
+            to: self.to.clone(),
+            data: encoder.encode().into(),
+            ..Default::default()
+        };
+        self.el.eth_call(call, BlockSelector::Latest)
+    }
+}
@@ -0,0 +1,104 @@
+use std::prelude::v1::*;
+
+use crypto::keccak_hash;
+
+// The layout of the DCAP v3 quote:
+//   header (48) | isv enclave report body (384) | auth data size (4) | auth data
+pub const QUOTE_HEADER_SIZE: usize = 48;
+pub const ENCLAVE_REPORT_SIZE: usize = 384;
+pub const REPORT_DATA_OFFSET: usize = QUOTE_HEADER_SIZE + 320;
+pub const REPORT_DATA_SIZE: usize = 64;
+
+const QUOTE_VERSION: u16 = 3;
+const ATT_KEY_TYPE_ECDSA_P256: u16 = 2;
+const INTEL_QE_VENDOR_ID: [u8; 16] = [
+    0x93, 0x9a, 0x72, 0x33, 0xf7, 0x9c, 0x4c, 0xa9, 0x94, 0x0a, 0x0d, 0xb3, 0x95, 0x7f, 0x06, 0x07,
+];
+const CERT_DATA_TYPE_PCK_CHAIN: u16 = 5;
+
+/// The mrenclave and mrsigner of the simulated quote, they're derived from
+/// fixed tags so they never collide with a real enclave.
+pub fn sim_mrenclave() -> [u8; 32] {
+    keccak_hash(b"sim-attestation:mrenclave")
+}
+
+pub fn sim_mrsigner() -> [u8; 32] {
+    keccak_hash(b"sim-attestation:mrsigner")
+}
+
+fn enclave_report(
+    report_data: &[u8; REPORT_DATA_SIZE],
+    mrenclave: &[u8; 32],
+    mrsigner: &[u8; 32],
+) -> Vec<u8> {
+    let mut report = vec![0_u8; ENCLAVE_REPORT_SIZE];
+    // cpusvn(16) miscselect(4) reserved(28) attributes(16)
+    report[64..96].copy_from_slice(mrenclave);
+    // reserved(32)
+    report[128..160].copy_from_slice(mrsigner);
+    // reserved(96) isvprodid(2) isvsvn(2) reserved(60)
+    report[320..].copy_from_slice(report_data);
+    report
+}
+
+/// Generates a well-formed DCAP v3 quote with the zero signatures, only the
+/// simulated attestation contract accepts it.
+pub fn sim_dcap_quote(report_data: [u8; REPORT_DATA_SIZE]) -> Vec<u8> {
+    let mut quote = Vec::with_capacity(1024);
+    quote.extend_from_slice(&QUOTE_VERSION.to_le_bytes());
+    quote.extend_from_slice(&ATT_KEY_TYPE_ECDSA_P256.to_le_bytes());
+    quote.extend_from_slice(&0_u32.to_le_bytes()); // tee type: sgx
+    quote.extend_from_slice(&0_u16.to_le_bytes()); // qe svn
+    quote.extend_from_slice(&0_u16.to_le_bytes()); // pce svn
+    quote.extend_from_slice(&INTEL_QE_VENDOR_ID);
+    quote.extend_from_slice(&[0_u8; 20]); // user data
+    quote.extend_from_slice(&enclave_report(&report_data, &sim_mrenclave(), &sim_mrsigner()));
+
+    let qe_report = enclave_report(&[0_u8; REPORT_DATA_SIZE], &[0_u8; 32], &[0_u8; 32]);
+    let mut auth_data = Vec::new();
+    auth_data.extend_from_slice(&[0_u8; 64]); // isv report signature
+    auth_data.extend_from_slice(&[0_u8; 64]); // attestation key
+    auth_data.extend_from_slice(&qe_report);
+    auth_data.extend_from_slice(&[0_u8; 64]); // qe report signature
+    auth_data.extend_from_slice(&0_u16.to_le_bytes()); // qe auth data
+    auth_data.extend_from_slice(&CERT_DATA_TYPE_PCK_CHAIN.to_le_bytes());
+    auth_data.extend_from_slice(&0_u32.to_le_bytes()); // cert data
+
+    quote.extend_from_slice(&(auth_data.len() as u32).to_le_bytes());
+    quote.extend_from_slice(&auth_data);
+    quote
+}
+
+/// Extracts the report data from a quote, it's what the simulated
+/// attestation contract returns after checking the layout.
+pub fn sim_report_data(quote: &[u8]) -> Option<[u8; REPORT_DATA_SIZE]> {
+    if quote.len() < QUOTE_HEADER_SIZE + ENCLAVE_REPORT_SIZE + 4 {
+        return None;
+    }
+    if quote[..2] != QUOTE_VERSION.to_le_bytes() {
+        return None;
+    }
+    let mut report_data = [0_u8; REPORT_DATA_SIZE];
+    let end = REPORT_DATA_OFFSET + REPORT_DATA_SIZE;
+    report_data.copy_from_slice(&quote[REPORT_DATA_OFFSET..end]);
+    Some(report_data)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_sim_dcap_quote() {
+        let mut report_data = [0_u8; REPORT_DATA_SIZE];
+        report_data[44..].copy_from_slice(&[0x11; 20]);
+        let quote = sim_dcap_quote(report_data);
+        assert_eq!(sim_report_data(&quote), Some(report_data));
+
+        let auth_size = QUOTE_HEADER_SIZE + ENCLAVE_REPORT_SIZE;
+        let mut size = [0_u8; 4];
+        size.copy_from_slice(&quote[auth_size..auth_size + 4]);
+        assert_eq!(u32::from_le_bytes(size) as usize, quote.len() - auth_size - 4);
+        assert_eq!(sim_report_data(&quote[..100]), None);
+    }
+}
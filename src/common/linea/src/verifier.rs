@@ -1,17 +1,117 @@
 use std::prelude::v1::*;
 
 use base::{format::debug, trace::Alive};
+use serde::Serialize;
 use crypto::Secp256k1PrivateKey;
-use eth_tools::{EthCall, ExecutionClient, RpcClient, RpcError, TxSender};
-use eth_types::{BlockSelector, EngineTypes, ReceiptTrait, SH160, SH256, SU256};
+use eth_tools::{EthCall, ExecutionClient, LogFilter, RpcClient, RpcError, TxSender};
+use eth_types::{BlockSelector, EngineTypes, HexBytes, Log, ReceiptTrait, SH160, SH256, SU256};
 use solidity::EncodeArg;
 use std::time::Duration;
 
+use crate::{AbiReader, VerifierError};
+
 pub struct VerifierConfig {
     pub addr: SH160,
     pub endpoint: String,
 }
 
+/// The `BatchInfo` stored in `SGXVerifier.batches`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BatchInfo {
+    pub batch_index: u64,
+    pub new_state_root: SH256,
+    pub prev_state_root: SH256,
+    pub withdrawal_root: SH256,
+}
+
+impl BatchInfo {
+    pub fn decode(data: &[u8]) -> Result<Self, String> {
+        let data = AbiReader::new(data);
+        Ok(Self {
+            batch_index: data.uint(0)? as u64,
+            new_state_root: data.hash(32)?,
+            prev_state_root: data.hash(64)?,
+            withdrawal_root: data.hash(96)?,
+        })
+    }
+
+    /// The contract uses a zero `newStateRoot` as the uncommitted marker.
+    pub fn is_committed(&self) -> bool {
+        self.new_state_root != SH256::default()
+    }
+}
+
+pub const COMMIT_BATCH_SIG: &str = "CommitBatch(uint256,bytes32,bytes32,bytes32)";
+pub const PROVER_APPROVED_SIG: &str = "ProverApproved(address)";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CommitBatchEvent {
+    pub batch_index: u64,
+    pub batch_hash: SH256,
+    pub prev_state_root: SH256,
+    pub new_state_root: SH256,
+    pub block_number: u64,
+    pub tx_hash: SH256,
+}
+
+impl CommitBatchEvent {
+    pub fn decode(log: &Log) -> Result<Self, String> {
+        let topic = solidity::encode_eventsig(COMMIT_BATCH_SIG);
+        // batchIndex and batchHash are indexed
+        if log.topics.len() != 3 || log.topics[0] != topic {
+            return Err(format!("not a CommitBatch event: {:?}", log.topics));
+        }
+        let data = AbiReader::new(&log.data);
+        let batch_index = SU256::from_big_endian(log.topics[1].as_bytes());
+        Ok(Self {
+            batch_index: batch_index.as_u64(),
+            batch_hash: log.topics[2],
+            prev_state_root: data.hash(0)?,
+            new_state_root: data.hash(32)?,
+            block_number: log.block_number.as_u64(),
+            tx_hash: log.transaction_hash,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProverApprovedEvent {
+    pub prover: SH160,
+    pub block_number: u64,
+    pub tx_hash: SH256,
+}
+
+impl ProverApprovedEvent {
+    pub fn decode(log: &Log) -> Result<Self, String> {
+        let topic = solidity::encode_eventsig(PROVER_APPROVED_SIG);
+        if log.topics.len() != 1 || log.topics[0] != topic {
+            return Err(format!("not a ProverApproved event: {:?}", log.topics));
+        }
+        let data = AbiReader::new(&log.data);
+        let word = data.word(0)?;
+        let mut prover = SH160::default();
+        prover.as_bytes_mut().copy_from_slice(&word[12..]);
+        Ok(Self {
+            prover,
+            block_number: log.block_number.as_u64(),
+            tx_hash: log.transaction_hash,
+        })
+    }
+}
+
+/// Reads the `status` and `blockNumber` of a mined receipt.
+fn receipt_outcome<R: Serialize>(receipt: &R) -> Result<(bool, u64), String> {
+    let val = serde_json::to_value(receipt).map_err(debug)?;
+    let quantity = |name: &str| -> Result<u64, String> {
+        let field = val[name]
+            .as_str()
+            .ok_or_else(|| format!("receipt without {}: {}", name, val))?;
+        u64::from_str_radix(field.trim_start_matches("0x"), 16)
+            .map_err(|err| format!("invalid receipt {}[{}]: {}", name, field, err))
+    };
+    Ok((quantity("status")? == 1, quantity("blockNumber")?))
+}
+
 #[derive(Debug, Clone)]
 pub struct Verifier<C, E>
 where
@@ -110,7 +210,7 @@ where
         relay: &Secp256k1PrivateKey,
         prover: &SH160,
         report: &[u8],
-    ) -> Result<SH256, String> {
+    ) -> Result<SH256, VerifierError> {
         let mut encoder = solidity::Encoder::new("submitAttestationReport");
         encoder.add(prover);
         encoder.add(report);
@@ -124,17 +224,60 @@ where
         tag: &str,
         signer: &Secp256k1PrivateKey,
         data: Vec<u8>,
-    ) -> Result<E::Receipt, String> {
+    ) -> Result<E::Receipt, VerifierError> {
+        self.send_tx_to(tag, signer, self.to, data)
+    }
+
+    /// Simulates the tx before sending it, so the revert reason can be
+    /// decoded from the rpc error instead of a failed receipt. The tx can still
+    /// fail on chain, it's replayed at its block to find out the reason.
+    fn send_tx_to(
+        &self,
+        tag: &str,
+        signer: &Secp256k1PrivateKey,
+        to: SH160,
+        data: Vec<u8>,
+    ) -> Result<E::Receipt, VerifierError> {
+        let call = EthCall {
+            from: Some(signer.public().eth_accountid().into()),
+            to,
+            data: data.clone().into(),
+            ..Default::default()
+        };
+        if let Err(err) = self.el.eth_call::<HexBytes>(call.clone(), BlockSelector::Latest) {
+            let err = VerifierError::from_rpc_error(&err);
+            glog::error!("[{}] tx simulation fail: {}", tag, err);
+            return Err(err);
+        }
         let mut receipt = self
             .tx_sender
-            .send(signer, Some(self.to), SU256::zero(), data.into())
-            .map_err(debug)?;
+            .send(signer, Some(to), SU256::zero(), data.into())
+            .map_err(|err| VerifierError::Rpc(debug(err)))?;
         glog::info!("[{}]tx sent: {:?}", tag, receipt);
         let result = receipt
             .wait_finished(&self.alive, |this| {
                 glog::info!("[{}] tx updates: {:?}", tag, this.status);
             })
-            .ok_or_else(|| format!("tx aborted"))?;
+            .ok_or(VerifierError::TxAborted)?;
+        let (success, block) = receipt_outcome(&result).map_err(VerifierError::Rpc)?;
+        if !success {
+            let err = match self.el.eth_call::<HexBytes>(call, block.into()) {
+                Err(err) => VerifierError::from_rpc_error(&err),
+                // the state has changed after the tx, the reason is lost
+                Ok(_) => VerifierError::Reverted(format!(
+                    "tx {:?} failed in block {}",
+                    result.transaction_hash(),
+                    block
+                )),
+            };
+            glog::error!(
+                "[{}] tx {:?} failed: {}",
+                tag,
+                result.transaction_hash(),
+                err
+            );
+            return Err(err);
+        }
         Ok(result)
     }
 
@@ -163,7 +306,6 @@ where
         {
             let quote = sgxlib_ra::dcap_generate_quote(report_data).map_err(debug)?;
             if _check_report_metadata {
-                use eth_types::HexBytes;
                 let pass_mrenclave = self
                     .verify_mrenclave(quote.get_mr_enclave())
                     .map_err(debug)?;
@@ -185,7 +327,18 @@ where
                 .map_err(debug)?;
             return Ok(data.into());
         }
-        #[cfg(not(feature = "tstd"))]
+        #[cfg(all(not(feature = "tstd"), feature = "sim-attestation"))]
+        {
+            let data = crate::sim_dcap_quote(report_data);
+            if !self
+                .verify_report_on_chain(&prover_key, &data)
+                .map_err(debug)?
+            {
+                return Err("simulated quote rejected, the verifier should use SimAttestation".into());
+            }
+            return Ok(data);
+        }
+        #[cfg(all(not(feature = "tstd"), not(feature = "sim-attestation")))]
         return Err("fail to generate attestation report".into());
     }
 
@@ -194,7 +347,7 @@ where
         relay: &Secp256k1PrivateKey,
         batch_id: &SU256,
         report: &[u8],
-    ) -> Result<SH256, String> {
+    ) -> Result<SH256, VerifierError> {
         let mut encoder = solidity::Encoder::new("commitBatch");
         encoder.add(batch_id);
         encoder.add(report);
@@ -203,6 +356,10 @@ where
         Ok(*receipt.transaction_hash())
     }
 
+    pub fn get_receipt(&self, tx_hash: &SH256) -> Result<Option<E::Receipt>, RpcError> {
+        self.el.get_receipt(tx_hash)
+    }
+
     pub fn recover_poe(&self, poe: &[u8]) -> Result<SH160, RpcError> {
         let mut encoder = solidity::Encoder::new("recoverPoe");
         encoder.add(poe);
@@ -218,6 +375,171 @@ where
         Ok(addr)
     }
 
+    /// Returns the committed batch info, or None if the batch is not committed.
+    pub fn batch_info(&self, batch_hash: &SH256) -> Result<Option<BatchInfo>, String> {
+        let mut encoder = solidity::Encoder::new("batches");
+        encoder.add(batch_hash);
+        let call = EthCall {
+            to: self.to.clone(),
+            data: encoder.encode().into(),
+            ..Default::default()
+        };
+        let data: HexBytes = self
+            .el
+            .eth_call(call, BlockSelector::Latest)
+            .map_err(debug)?;
+        let info = BatchInfo::decode(&data)?;
+        if !info.is_committed() {
+            return Ok(None);
+        }
+        Ok(Some(info))
+    }
+
+    pub fn is_batch_committed(&self, batch_hash: &SH256) -> Result<bool, String> {
+        Ok(self.batch_info(batch_hash)?.is_some())
+    }
+
+    pub fn owner(&self) -> Result<SH160, RpcError> {
+        let data = solidity::Encoder::new("owner").encode();
+        let call = EthCall {
+            to: self.to.clone(),
+            data: data.into(),
+            ..Default::default()
+        };
+        let val: SH256 = self.el.eth_call(call, BlockSelector::Latest)?;
+        let mut addr = SH160::default();
+        addr.as_bytes_mut().copy_from_slice(&val.0[12..]);
+        Ok(addr)
+    }
+
+    pub fn threshold(&self) -> Result<u64, RpcError> {
+        let data = solidity::Encoder::new("threshold").encode();
+        let call = EthCall {
+            to: self.to.clone(),
+            data: data.into(),
+            ..Default::default()
+        };
+        let val: SU256 = self.el.eth_call(call, BlockSelector::Latest)?;
+        Ok(val.as_u64())
+    }
+
+    fn get_events(&self, sig: &str, from: u64, to: u64) -> Result<Vec<Log>, RpcError> {
+        let filter = LogFilter {
+            address: vec![self.to],
+            topics: vec![vec![solidity::encode_eventsig(sig)]],
+            from_block: Some(from.into()),
+            to_block: Some(to.into()),
+            ..Default::default()
+        };
+        let mut logs = self.el.get_logs(&filter)?;
+        logs.retain(|log| !log.removed);
+        logs.sort_by_key(|log| (log.block_number.as_u64(), log.log_index.as_u64()));
+        Ok(logs)
+    }
+
+    pub fn block_number(&self) -> Result<u64, RpcError> {
+        let head = self.el.get_block_header(BlockSelector::Latest)?;
+        Ok(head.number.as_u64())
+    }
+
+    /// Lists the batches committed in the block range `from..=to`.
+    pub fn commit_batch_events(&self, from: u64, to: u64) -> Result<Vec<CommitBatchEvent>, String> {
+        let logs = self
+            .get_events(COMMIT_BATCH_SIG, from, to)
+            .map_err(debug)?;
+        logs.iter().map(CommitBatchEvent::decode).collect()
+    }
+
+    /// Lists the provers attested in the block range `from..=to`.
+    pub fn prover_approved_events(
+        &self,
+        from: u64,
+        to: u64,
+    ) -> Result<Vec<ProverApprovedEvent>, String> {
+        let logs = self
+            .get_events(PROVER_APPROVED_SIG, from, to)
+            .map_err(debug)?;
+        logs.iter().map(ProverApprovedEvent::decode).collect()
+    }
+
+    /// The AutomataDcapV3Attestation contract used by the verifier.
+    pub fn dcap_attestation(&self) -> Result<SH160, RpcError> {
+        let data = solidity::Encoder::new("dcapAttestation").encode();
+        let call = EthCall {
+            to: self.to.clone(),
+            data: data.into(),
+            ..Default::default()
+        };
+        let val: SH256 = self.el.eth_call(call, BlockSelector::Latest)?;
+        let mut addr = SH160::default();
+        addr.as_bytes_mut().copy_from_slice(&val.0[12..]);
+        Ok(addr)
+    }
+
+    /// Makes sure the signer is the owner, the tx will revert with "Not authorized" otherwise.
+    fn check_owner(&self, signer: &Secp256k1PrivateKey) -> Result<(), String> {
+        let owner = self.owner().map_err(debug)?;
+        let sender: SH160 = signer.public().eth_accountid().into();
+        if owner != sender {
+            return Err(format!("not authorized: owner={:?}, sender={:?}", owner, sender));
+        }
+        Ok(())
+    }
+
+    pub fn change_owner(
+        &self,
+        signer: &Secp256k1PrivateKey,
+        new_owner: &SH160,
+    ) -> Result<SH256, String> {
+        self.check_owner(signer)?;
+        let mut encoder = solidity::Encoder::new("changeOwner");
+        encoder.add(new_owner);
+        let receipt = self.send_tx("change_owner", signer, encoder.encode())?;
+        Ok(*receipt.transaction_hash())
+    }
+
+    pub fn change_attest_validity_seconds(
+        &self,
+        signer: &Secp256k1PrivateKey,
+        secs: u64,
+    ) -> Result<SH256, String> {
+        self.check_owner(signer)?;
+        let mut encoder = solidity::Encoder::new("changeAttestValiditySeconds");
+        encoder.add(&SU256::from(secs));
+        let receipt = self.send_tx("change_attest_validity_seconds", signer, encoder.encode())?;
+        Ok(*receipt.transaction_hash())
+    }
+
+    /// Registers or revokes the mrenclave on the attestation contract.
+    pub fn set_mrenclave(
+        &self,
+        signer: &Secp256k1PrivateKey,
+        mrenclave: &SH256,
+        trusted: bool,
+    ) -> Result<SH256, String> {
+        let attestation = self.dcap_attestation().map_err(debug)?;
+        let mut encoder = solidity::Encoder::new("setMrEnclave");
+        encoder.add(mrenclave);
+        encoder.add(&trusted);
+        let receipt = self.send_tx_to("set_mrenclave", signer, attestation, encoder.encode())?;
+        Ok(*receipt.transaction_hash())
+    }
+
+    /// Registers or revokes the mrsigner on the attestation contract.
+    pub fn set_mrsigner(
+        &self,
+        signer: &Secp256k1PrivateKey,
+        mrsigner: &SH256,
+        trusted: bool,
+    ) -> Result<SH256, String> {
+        let attestation = self.dcap_attestation().map_err(debug)?;
+        let mut encoder = solidity::Encoder::new("setMrSigner");
+        encoder.add(mrsigner);
+        encoder.add(&trusted);
+        let receipt = self.send_tx_to("set_mrsigner", signer, attestation, encoder.encode())?;
+        Ok(*receipt.transaction_hash())
+    }
+
     pub fn l2_chain_id(&self) -> Result<u64, RpcError> {
         let data = solidity::Encoder::new("layer2ChainId").encode();
         let call = EthCall {
@@ -229,3 +551,26 @@ where
         Ok(val.as_u64())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_receipt_outcome() {
+        let receipt = serde_json::json!({
+            "transactionHash": "0x1111111111111111111111111111111111111111111111111111111111111111",
+            "blockNumber": "0x12d687",
+            "status": "0x0",
+        });
+        assert_eq!(receipt_outcome(&receipt), Ok((false, 1234567)));
+
+        let mut receipt = receipt;
+        receipt["status"] = "0x1".into();
+        assert_eq!(receipt_outcome(&receipt), Ok((true, 1234567)));
+
+        // a pending receipt doesn't have the block number
+        receipt["blockNumber"] = serde_json::Value::Null;
+        assert!(receipt_outcome(&receipt).is_err());
+    }
+}
@@ -0,0 +1,202 @@
+use std::prelude::v1::*;
+
+use core::fmt;
+use eth_tools::RpcError;
+
+use crate::AbiReader;
+
+/// The selector of `Error(string)`.
+pub const REVERT_SELECTOR: [u8; 4] = [0x08, 0xc3, 0x79, 0xa0];
+
+/// The failures of the verifier transactions, the known variants match the
+/// `require` messages of `SGXVerifier`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum VerifierError {
+    NotAuthorized,
+    InvalidSignature,
+    InvalidSignatureLength,
+    InvalidV,
+    ProverNotAttested,
+    ProverOutOfDated,
+    BatchAlreadyCommitted,
+    AttestationValidationFailed,
+    AttestationProverMismatch,
+    /// Reverted with an unknown reason.
+    Reverted(String),
+    /// The tx was aborted before it's confirmed.
+    TxAborted,
+    Rpc(String),
+}
+
+/// What the caller should do with a failed verifier transaction.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum VerifierErrorAction {
+    Retry,
+    Reattest,
+    Skip,
+    Abort,
+}
+
+const REASONS: &[(&str, VerifierError)] = &[
+    ("Not authorized", VerifierError::NotAuthorized),
+    ("ECDSA: invalid signature", VerifierError::InvalidSignature),
+    ("invalid signature length", VerifierError::InvalidSignatureLength),
+    ("invalid v value", VerifierError::InvalidV),
+    ("Prover not attested", VerifierError::ProverNotAttested),
+    ("Prover out-of-dated", VerifierError::ProverOutOfDated),
+    ("batch already commit", VerifierError::BatchAlreadyCommitted),
+    (
+        "attestation validation failed",
+        VerifierError::AttestationValidationFailed,
+    ),
+    (
+        "attestation prover mismatch",
+        VerifierError::AttestationProverMismatch,
+    ),
+];
+
+impl VerifierError {
+    pub fn from_reason(reason: &str) -> Self {
+        for (msg, err) in REASONS {
+            if *msg == reason {
+                return err.clone();
+            }
+        }
+        Self::Reverted(reason.into())
+    }
+
+    /// Decodes the `Error(string)` revert data.
+    pub fn decode_revert_data(data: &[u8]) -> Option<String> {
+        if data.len() < 4 || data[..4] != REVERT_SELECTOR {
+            return None;
+        }
+        let args = AbiReader::new(&data[4..]);
+        let reason = args.bytes(args.offset(0).ok()?).ok()?;
+        Some(String::from_utf8_lossy(reason).into())
+    }
+
+    pub fn from_revert_data(data: &[u8]) -> Self {
+        match Self::decode_revert_data(data) {
+            Some(reason) => Self::from_reason(&reason),
+            None => Self::Reverted(format!("0x{}", hex_encode(data))),
+        }
+    }
+
+    /// The node reports the revert in the rpc error, either in the message
+    /// ("execution reverted: <reason>") or in the data field.
+    pub fn from_rpc_error(err: &RpcError) -> Self {
+        let msg = format!("{:?}", err);
+        for (reason, err) in REASONS {
+            if msg.contains(reason) {
+                return err.clone();
+            }
+        }
+        if let Some(data) = find_revert_data(&msg) {
+            return Self::from_revert_data(&data);
+        }
+        if msg.contains("execution reverted") {
+            return Self::Reverted(msg);
+        }
+        Self::Rpc(msg)
+    }
+
+    pub fn action(&self) -> VerifierErrorAction {
+        match self {
+            Self::BatchAlreadyCommitted => VerifierErrorAction::Skip,
+            Self::ProverNotAttested | Self::ProverOutOfDated => VerifierErrorAction::Reattest,
+            Self::TxAborted | Self::Rpc(_) => VerifierErrorAction::Retry,
+            _ => VerifierErrorAction::Abort,
+        }
+    }
+
+    /// A short name of the variant, used as the metric label.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::NotAuthorized => "not_authorized",
+            Self::InvalidSignature => "invalid_signature",
+            Self::InvalidSignatureLength => "invalid_signature_length",
+            Self::InvalidV => "invalid_v",
+            Self::ProverNotAttested => "prover_not_attested",
+            Self::ProverOutOfDated => "prover_out_of_dated",
+            Self::BatchAlreadyCommitted => "batch_already_committed",
+            Self::AttestationValidationFailed => "attestation_validation_failed",
+            Self::AttestationProverMismatch => "attestation_prover_mismatch",
+            Self::Reverted(_) => "reverted",
+            Self::TxAborted => "tx_aborted",
+            Self::Rpc(_) => "rpc",
+        }
+    }
+}
+
+fn hex_encode(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn find_revert_data(msg: &str) -> Option<Vec<u8>> {
+    let start = msg.find("0x08c379a0")? + 2;
+    let hex: String = msg[start..]
+        .chars()
+        .take_while(|c| c.is_ascii_hexdigit())
+        .collect();
+    let mut data = Vec::with_capacity(hex.len() / 2);
+    for i in (0..hex.len() / 2 * 2).step_by(2) {
+        data.push(u8::from_str_radix(&hex[i..i + 2], 16).ok()?);
+    }
+    Some(data)
+}
+
+impl fmt::Display for VerifierError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (reason, err) in REASONS {
+            if err == self {
+                return write!(f, "verifier reverted: {}", reason);
+            }
+        }
+        match self {
+            Self::Reverted(reason) => write!(f, "verifier reverted: {}", reason),
+            Self::TxAborted => write!(f, "tx aborted"),
+            Self::Rpc(err) => write!(f, "rpc error: {}", err),
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl From<VerifierError> for String {
+    fn from(err: VerifierError) -> String {
+        format!("{}", err)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_decode_revert_data() {
+        let mut data = REVERT_SELECTOR.to_vec();
+        let mut word = [0_u8; 32];
+        word[31] = 0x20;
+        data.extend_from_slice(&word);
+        let reason = b"Prover not attested";
+        word[31] = reason.len() as u8;
+        data.extend_from_slice(&word);
+        let mut padded = [0_u8; 32];
+        padded[..reason.len()].copy_from_slice(reason);
+        data.extend_from_slice(&padded);
+
+        let err = VerifierError::from_revert_data(&data);
+        assert_eq!(err, VerifierError::ProverNotAttested);
+        assert_eq!(err.action(), VerifierErrorAction::Reattest);
+        assert_eq!(
+            VerifierError::from_reason("batch already commit").action(),
+            VerifierErrorAction::Skip
+        );
+        assert_eq!(
+            VerifierError::from_reason("unknown"),
+            VerifierError::Reverted("unknown".into())
+        );
+
+        let msg = format!("{{\"data\":\"0x{}\"}}", hex_encode(&data));
+        assert_eq!(find_revert_data(&msg), Some(data));
+    }
+}
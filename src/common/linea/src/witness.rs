@@ -0,0 +1,172 @@
+use std::prelude::v1::*;
+
+use crypto::keccak_hash;
+use eth_types::SH256;
+use rlp::Rlp;
+use std::collections::BTreeMap;
+
+/// The MPT leaves reachable from a root over the witness nodes.
+#[derive(Debug, Default)]
+pub struct TrieDump {
+    /// The full key and the raw value of the leaves.
+    pub leaves: Vec<(Vec<u8>, Vec<u8>)>,
+    /// The nodes referenced but not in the witness, it's normal for a Pob
+    /// which only carries the accessed paths.
+    pub missing: Vec<SH256>,
+}
+
+/// Walks the MPT from the `root` over the nodes of the Pob.
+pub fn dump_trie(nodes: &[&[u8]], root: &SH256) -> Result<TrieDump, String> {
+    let nodes: BTreeMap<SH256, &[u8]> = nodes
+        .iter()
+        .map(|node| (keccak_hash(node).into(), *node))
+        .collect();
+    let mut dump = TrieDump::default();
+    walk_hash(&nodes, root, Vec::new(), &mut dump)?;
+    Ok(dump)
+}
+
+fn walk_hash(
+    nodes: &BTreeMap<SH256, &[u8]>,
+    hash: &SH256,
+    path: Vec<u8>,
+    dump: &mut TrieDump,
+) -> Result<(), String> {
+    match nodes.get(hash) {
+        Some(node) => walk_node(nodes, &Rlp::new(node), path, dump),
+        None => {
+            dump.missing.push(*hash);
+            Ok(())
+        }
+    }
+}
+
+/// The child is either the hash of the node or the node itself if its
+/// encoding is shorter than 32 bytes.
+fn walk_ref(
+    nodes: &BTreeMap<SH256, &[u8]>,
+    child: &Rlp,
+    path: Vec<u8>,
+    dump: &mut TrieDump,
+) -> Result<(), String> {
+    if child.is_list() {
+        return walk_node(nodes, child, path, dump);
+    }
+    let data = child.data().map_err(|err| format!("{:?}", err))?;
+    match data.len() {
+        0 => Ok(()),
+        32 => {
+            let mut hash = [0_u8; 32];
+            hash.copy_from_slice(data);
+            walk_hash(nodes, &hash.into(), path, dump)
+        }
+        n => Err(format!("invalid node reference length: {}", n)),
+    }
+}
+
+fn walk_node(
+    nodes: &BTreeMap<SH256, &[u8]>,
+    node: &Rlp,
+    path: Vec<u8>,
+    dump: &mut TrieDump,
+) -> Result<(), String> {
+    let item = |idx: usize| node.at(idx).map_err(|err| format!("{:?}", err));
+    match node.item_count().map_err(|err| format!("{:?}", err))? {
+        17 => {
+            for nibble in 0..16 {
+                let mut child_path = path.clone();
+                child_path.push(nibble as u8);
+                walk_ref(nodes, &item(nibble)?, child_path, dump)?;
+            }
+            Ok(())
+        }
+        2 => {
+            let encoded = item(0)?.data().map_err(|err| format!("{:?}", err))?;
+            let (is_leaf, nibbles) = decode_hex_prefix(encoded)?;
+            let mut path = path;
+            path.extend(nibbles);
+            if is_leaf {
+                let value = item(1)?.data().map_err(|err| format!("{:?}", err))?;
+                dump.leaves.push((nibbles_to_bytes(&path), value.to_vec()));
+                Ok(())
+            } else {
+                walk_ref(nodes, &item(1)?, path, dump)
+            }
+        }
+        n => Err(format!("invalid node item count: {}", n)),
+    }
+}
+
+/// Returns whether it's a leaf and the nibbles of the compact path.
+fn decode_hex_prefix(data: &[u8]) -> Result<(bool, Vec<u8>), String> {
+    let first = *data.first().ok_or("empty node path")?;
+    let flag = first >> 4;
+    if flag > 3 {
+        return Err(format!("invalid hex prefix flag: {}", flag));
+    }
+    let mut nibbles = Vec::with_capacity(data.len() * 2);
+    if flag & 1 == 1 {
+        nibbles.push(first & 0x0f);
+    }
+    for b in &data[1..] {
+        nibbles.push(b >> 4);
+        nibbles.push(b & 0x0f);
+    }
+    Ok((flag >= 2, nibbles))
+}
+
+fn nibbles_to_bytes(nibbles: &[u8]) -> Vec<u8> {
+    nibbles
+        .chunks(2)
+        .map(|pair| match pair {
+            [hi, lo] => (hi << 4) | lo,
+            [hi] => hi << 4,
+            _ => unreachable!(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rlp::RlpStream;
+
+    fn leaf(path: &[u8], value: &[u8]) -> Vec<u8> {
+        let mut s = RlpStream::new_list(2);
+        s.append(&path.to_vec());
+        s.append(&value.to_vec());
+        s.out().to_vec()
+    }
+
+    #[test]
+    fn test_dump_trie() {
+        // two leaves under a branch, the keys are 0x1 ++ [0xaa; 31] and
+        // 0x2 ++ [0xbb; 31] in nibbles.
+        let mut path_a = vec![0x3a];
+        path_a.extend_from_slice(&[0xaa; 31]);
+        let leaf_a = leaf(&path_a, &[0x55; 40]);
+        let mut path_b = vec![0x3b];
+        path_b.extend_from_slice(&[0xbb; 31]);
+        let leaf_b = leaf(&path_b, &[0x66; 40]);
+
+        let mut branch = RlpStream::new_list(17);
+        branch.append_empty_data();
+        branch.append(&keccak_hash(&leaf_a).to_vec());
+        branch.append(&keccak_hash(&leaf_b).to_vec());
+        branch.append(&[0x77_u8; 32].to_vec());
+        for _ in 4..17 {
+            branch.append_empty_data();
+        }
+        let branch = branch.out().to_vec();
+        let root: SH256 = keccak_hash(&branch).into();
+
+        let dump = dump_trie(&[&branch, &leaf_a, &leaf_b], &root).unwrap();
+        assert_eq!(dump.leaves.len(), 2);
+        assert_eq!(dump.leaves[0].0[..2], [0x1a, 0xaa]);
+        assert_eq!(dump.leaves[0].0.len(), 32);
+        assert_eq!(dump.leaves[0].1, vec![0x55; 40]);
+        assert_eq!(dump.leaves[1].0[..2], [0x2b, 0xbb]);
+        let missing: SH256 = [0x77; 32].into();
+        assert_eq!(dump.missing, vec![missing]);
+    }
+}
@@ -13,6 +13,7 @@ pub struct Client {
 }
 
 #[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct ShomeiConfig {
     pub endpoint: String,
     pub version: String,
@@ -0,0 +1,50 @@
+// SPDX-License-Identifier: MIT
+pragma solidity ^0.8.9;
+
+import {IAttestation} from "./interfaces/IAttestation.sol";
+
+// A permissive attestation for testing without SGX, it only checks the
+// layout of the DCAP v3 quote and returns the report data.
+// NEVER deploy it in production.
+contract SimAttestation is IAttestation {
+    uint256 constant HEADER_LENGTH = 48;
+    uint256 constant REPORT_LENGTH = 384;
+    uint256 constant REPORT_DATA_OFFSET = HEADER_LENGTH + 320;
+
+    mapping(bytes32 => bool) public trustedUserMrEnclave;
+    mapping(bytes32 => bool) public trustedUserMrSigner;
+    address public owner;
+
+    constructor() {
+        owner = msg.sender;
+    }
+
+    function setMrEnclave(bytes32 _mrEnclave, bool _trusted) external {
+        require(msg.sender == owner, "Not authorized");
+        trustedUserMrEnclave[_mrEnclave] = _trusted;
+    }
+
+    function setMrSigner(bytes32 _mrSigner, bool _trusted) external {
+        require(msg.sender == owner, "Not authorized");
+        trustedUserMrSigner[_mrSigner] = _trusted;
+    }
+
+    function verifyAttestation(bytes calldata data) external pure override returns (bool, bytes memory) {
+        if (data.length < HEADER_LENGTH + REPORT_LENGTH + 4) {
+            return (false, bytes(""));
+        }
+        // version 3, little endian
+        if (uint8(data[0]) != 3 || uint8(data[1]) != 0) {
+            return (false, bytes(""));
+        }
+        return (true, data[REPORT_DATA_OFFSET:REPORT_DATA_OFFSET + 64]);
+    }
+
+    function verifyMrEnclave(bytes32 _mrEnclave) external view override returns (bool) {
+        return trustedUserMrEnclave[_mrEnclave];
+    }
+
+    function verifyMrSigner(bytes32 _mrSigner) external view override returns (bool) {
+        return trustedUserMrSigner[_mrSigner];
+    }
+}
@@ -11,26 +11,7 @@ async function main() {
     throw "should provide CHAIN_ID";
   }
 
-  const sigVerifyLib = await ethers.deployContract("SigVerifyLib", [], {});
-  await sigVerifyLib.waitForDeployment();
-  const sigVerifyLibAddr = await sigVerifyLib.getAddress();
-  console.log("sigVerifyLib address:", sigVerifyLibAddr);
-  const attestation = await ethers.deployContract("AutomataDcapV3Attestation", [sigVerifyLibAddr], {});
-  await attestation.waitForDeployment();
-  const attestationAddr = await attestation.getAddress();
-  console.log("attestation address:", attestationAddr);
-
-  {
-    var enclaveId = parseEnclaveId();
-    await attestation.configureQeIdentityJson(enclaveId);
-    console.log("configureQeIdentityJson");
-  }
-
-  {
-    const tcbInfo = parseTcbInfo();
-    await attestation.configureTcbInfoJson(tcbInfo.fmspc, tcbInfo);
-    console.log("configureTcbInfoJson");
-  }
+  const attestationAddr = process.env['SIM_ATTESTATION'] ? await deploySimAttestation() : await deployDcapAttestation();
 
   const verifier = await ethers.deployContract("SGXVerifier", [attestationAddr, parseInt(chainId)], {
     // value: lockedAmount,
@@ -65,6 +46,39 @@ async function main() {
   }
 }
 
+async function deploySimAttestation(): Promise<string> {
+  console.log("WARNING: deploying the simulated attestation, NEVER use it in production");
+  const attestation = await ethers.deployContract("SimAttestation", [], {});
+  await attestation.waitForDeployment();
+  const attestationAddr = await attestation.getAddress();
+  console.log("attestation address:", attestationAddr);
+  return attestationAddr;
+}
+
+async function deployDcapAttestation(): Promise<string> {
+  const sigVerifyLib = await ethers.deployContract("SigVerifyLib", [], {});
+  await sigVerifyLib.waitForDeployment();
+  const sigVerifyLibAddr = await sigVerifyLib.getAddress();
+  console.log("sigVerifyLib address:", sigVerifyLibAddr);
+  const attestation = await ethers.deployContract("AutomataDcapV3Attestation", [sigVerifyLibAddr], {});
+  await attestation.waitForDeployment();
+  const attestationAddr = await attestation.getAddress();
+  console.log("attestation address:", attestationAddr);
+
+  {
+    var enclaveId = parseEnclaveId();
+    await attestation.configureQeIdentityJson(enclaveId);
+    console.log("configureQeIdentityJson");
+  }
+
+  {
+    const tcbInfo = parseTcbInfo();
+    await attestation.configureTcbInfoJson(tcbInfo.fmspc, tcbInfo);
+    console.log("configureTcbInfoJson");
+  }
+  return attestationAddr;
+}
+
 function parseEnclaveId(): EnclaveIdStruct.EnclaveIdStruct {
   const fileContent = fs.readFileSync('contracts/assets/identity.json', 'utf8');
   const obj = JSON.parse(fileContent);